@@ -0,0 +1,63 @@
+//! A worker process for distributed BPR20 proofs.
+//!
+//! Usage: `bpr20-worker [--curve <curve>] <proving-key> <coordinator-address>`
+//!
+//! The worker opens the chunked proving key at `<proving-key>`, written by
+//! `ProvingKey::write_chunked`, connects to the coordinator listening at
+//! `<coordinator-address>` and serves its requests until the coordinator
+//! closes the connection. The key is for the curve `<curve>`, one of
+//! `bls12-381`, `bls12-377`, `cp6-782`, `mnt4-298`, `mnt6-298`, `mnt4-753`
+//! and `mnt6-753`, or BLS12-381 if no curve is given.
+use ark_bls12_377::Bls12_377;
+use ark_bls12_381::Bls12_381;
+use ark_bpr20::{chunked::ChunkedProvingKey, distributed::serve, ArkworksMsm, Error};
+use ark_cp6_782::CP6_782;
+use ark_ec::PairingEngine;
+use ark_mnt4_298::MNT4_298;
+use ark_mnt4_753::MNT4_753;
+use ark_mnt6_298::MNT6_298;
+use ark_mnt6_753::MNT6_753;
+use ark_serialize::SerializationError;
+use std::{env, net::TcpStream, process};
+
+const USAGE: &str = "usage: bpr20-worker [--curve <curve>] <proving-key> <coordinator-address>";
+
+fn main() {
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    let curve = match args.first().map(String::as_str) {
+        Some("--curve") if args.len() > 1 => {
+            let curve = args[1].clone();
+            args.drain(..2);
+            curve
+        },
+        _ => "bls12-381".to_string(),
+    };
+    if args.len() != 2 {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+    let (pk_path, address) = (&args[0], &args[1]);
+    let result = match curve.as_str() {
+        "bls12-381" => run::<Bls12_381>(pk_path, address),
+        "bls12-377" => run::<Bls12_377>(pk_path, address),
+        "cp6-782" => run::<CP6_782>(pk_path, address),
+        "mnt4-298" => run::<MNT4_298>(pk_path, address),
+        "mnt6-298" => run::<MNT6_298>(pk_path, address),
+        "mnt4-753" => run::<MNT4_753>(pk_path, address),
+        "mnt6-753" => run::<MNT6_753>(pk_path, address),
+        _ => {
+            eprintln!("bpr20-worker: unknown curve {}\n{}", curve, USAGE);
+            process::exit(2);
+        },
+    };
+    if let Err(e) = result {
+        eprintln!("bpr20-worker: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run<E: PairingEngine>(pk_path: &str, address: &str) -> Result<(), Error> {
+    let pk = ChunkedProvingKey::<E>::open(pk_path)?;
+    let stream = TcpStream::connect(address).map_err(SerializationError::from)?;
+    serve(stream, &pk, &ArkworksMsm)
+}
@@ -0,0 +1,66 @@
+use ark_ec::PairingEngine;
+use ark_ff::PrimeField;
+use ark_serialize::CanonicalSerialize;
+use ark_std::vec::Vec;
+use blake2::{Blake2b, Digest};
+
+/// The domain separation tag at the start of the hash input of the
+/// challenge, which changes with any change of the encoding.
+pub const CHALLENGE_DOMAIN: &[u8] = b"ark-bpr20/challenge/v1";
+
+/// The input of the hash of the challenge `m` of a proof with the elements
+/// `a`, `b` and `delta_prime`.
+///
+/// The input is the concatenation of [`CHALLENGE_DOMAIN`], `a`, `b` and
+/// `delta_prime`, each preceded by its length in bytes as a little-endian
+/// `u64`. The elements are in their compressed encoding:
+///
+/// * the `x`-coordinate as its base field coefficients from the lowest to
+///   the highest, each in canonical (non-Montgomery) little-endian form; with
+///   `bits` the size of the base field modulus, the highest coefficient (the
+///   only one over the base field itself) takes `ceil((bits + 2) / 8)` bytes
+///   to make room for two flag bits, and every lower coefficient takes
+///   `ceil(bits / 8)` bytes, so that over BLS12-381 `a` takes 48 bytes and
+///   `b` takes 96;
+/// * bit 7 of the last byte set if `y > -y`, where the coordinates of
+///   extension fields are compared from the highest coefficient to the
+///   lowest;
+/// * bit 6 of the last byte set, and all other bits of the `x`-coordinate
+///   unset, for the identity.
+pub fn challenge_input<E: PairingEngine>(
+    a: &E::G1Affine,
+    b: &E::G2Affine,
+    delta_prime: &E::G2Affine,
+) -> Vec<u8> {
+    let mut input = Vec::new();
+    let mut append = |bytes: &[u8]| {
+        input.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        input.extend_from_slice(bytes);
+    };
+    append(CHALLENGE_DOMAIN);
+    for element in &[compressed(a), compressed(b), compressed(delta_prime)] {
+        append(element);
+    }
+    input
+}
+
+/// The challenge `m` of a proof with the elements `a`, `b` and
+/// `delta_prime`, which is the 64-byte Blake2b hash of their
+/// [`challenge_input`] read as a little-endian integer and reduced modulo
+/// the order of the scalar field.
+pub fn proof_challenge<E: PairingEngine>(
+    a: &E::G1Affine,
+    b: &E::G2Affine,
+    delta_prime: &E::G2Affine,
+) -> E::Fr {
+    let hash = Blake2b::digest(&challenge_input::<E>(a, b, delta_prime));
+    E::Fr::from_le_bytes_mod_order(&hash)
+}
+
+fn compressed<T: CanonicalSerialize>(element: &T) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(element.serialized_size());
+    element
+        .serialize(&mut bytes)
+        .expect("writing to a vector does not fail");
+    bytes
+}
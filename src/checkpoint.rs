@@ -0,0 +1,268 @@
+use ark_ff::Field;
+use ark_relations::r1cs::ConstraintMatrices;
+use ark_serialize::CanonicalSerialize;
+use blake2::{Blake2b, Digest};
+
+#[cfg(feature = "std")]
+use crate::Error;
+#[cfg(feature = "std")]
+use ark_serialize::{CanonicalDeserialize, SerializationError};
+#[cfg(feature = "std")]
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// A hash of the inputs of a run, which identifies the results it saves.
+pub(crate) struct Fingerprint(Blake2b);
+
+impl Fingerprint {
+    /// Starts the fingerprint of a run of the kind `label`.
+    pub(crate) fn new(label: &[u8]) -> Self {
+        Self(Blake2b::new().chain(label))
+    }
+
+    /// Adds the uncompressed serialization of `value`.
+    pub(crate) fn update<T: CanonicalSerialize + ?Sized>(mut self, value: &T) -> Self {
+        value
+            .serialize_uncompressed(HashWriter(&mut self.0))
+            .expect("hashing does not fail");
+        self
+    }
+
+    /// Adds the constraint matrices of a circuit.
+    pub(crate) fn update_matrices<F: Field>(mut self, matrices: &ConstraintMatrices<F>) -> Self {
+        self = self
+            .update(&matrices.num_instance_variables)
+            .update(&matrices.num_witness_variables)
+            .update(&matrices.num_constraints);
+        for matrix in [&matrices.a, &matrices.b, &matrices.c].iter() {
+            for row in matrix.iter() {
+                self = self.update(&row.len());
+                for (coeff, index) in row.iter() {
+                    self = self.update(coeff).update(index);
+                }
+            }
+        }
+        self
+    }
+
+    /// The fingerprint of the inputs added so far.
+    pub(crate) fn finish(self) -> [u8; 64] {
+        let mut fingerprint = [0u8; 64];
+        fingerprint.copy_from_slice(&self.0.finalize());
+        fingerprint
+    }
+}
+
+/// Feeds everything written to it into a hash.
+struct HashWriter<'a>(&'a mut Blake2b);
+
+impl<'a> ark_std::io::Write for HashWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> ark_std::io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> ark_std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A directory in which the generator and the prover save the results of
+/// their stages, so that interrupted runs can resume from them.
+///
+/// **The directory holds secret data.** The prover saves its sums over each
+/// query of the proving key, which determine the witness as much as the
+/// witness itself does, so the directory must be kept as secret as the
+/// witness. Only group elements are saved: the generator saves the queries
+/// of the proving key, and the prover its sums over them. There are
+/// deliberately no checkpoints after the QAP evaluation of the generator or
+/// after the witness map of the prover, whose field elements would reveal
+/// the toxic waste and the witness outright, so resumed runs compute them
+/// again. A run that succeeds deletes its results, and then the directory
+/// unless it holds other files.
+///
+/// Each result is saved in its own file, together with a fingerprint of the
+/// inputs of the run and a checksum. A run only resumes from results whose
+/// fingerprint matches its own inputs and whose checksum is intact, and
+/// recomputes and overwrites all others, so resumed runs produce exactly the
+/// same output as uninterrupted ones.
+#[cfg(feature = "std")]
+pub struct Checkpoints {
+    dir: PathBuf,
+}
+
+#[cfg(feature = "std")]
+impl Checkpoints {
+    /// The extension of the files of saved results.
+    const EXTENSION: &'static str = "ckpt";
+
+    /// The extension of the files of results that are being saved.
+    const TMP_EXTENSION: &'static str = "tmp";
+
+    /// Saves results in the directory `dir`, creating it if needed.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self, SerializationError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// The directory in which results are saved.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Removes all saved results, including partly written ones.
+    pub fn clear(&self) -> Result<(), SerializationError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let extension = path.extension();
+            if extension.map_or(false, |e| e == Self::EXTENSION || e == Self::TMP_EXTENSION) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes all saved results and then the directory, unless it holds
+    /// other files.
+    pub(crate) fn remove(&self) -> Result<(), SerializationError> {
+        self.clear()?;
+        let mut entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if entries.next().is_none() {
+            fs::remove_dir(&self.dir)?;
+        }
+        Ok(())
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, Self::EXTENSION))
+    }
+}
+
+/// A file of results is the fingerprint of the run, the uncompressed result
+/// and the Blake2b hash of the result.
+#[cfg(feature = "std")]
+impl Checkpoints {
+    /// Reads the saved result of the stage `name`, or `None` if there is none
+    /// for the run with `fingerprint` or it is damaged.
+    pub(crate) fn load<T: CanonicalDeserialize>(
+        &self,
+        name: &str,
+        fingerprint: &[u8; 64],
+    ) -> Result<Option<T>, Error> {
+        let path = self.path(name);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(SerializationError::from(e).into()),
+        };
+        let mut reader = HashingReader {
+            inner: BufReader::new(file),
+            hasher: Blake2b::new(),
+        };
+
+        let mut saved_fingerprint = [0u8; 64];
+        if reader.inner.read_exact(&mut saved_fingerprint).is_err()
+            || saved_fingerprint != *fingerprint
+        {
+            return Ok(None);
+        }
+        // The checksum covers the result, so it is read without the checks
+        // on its elements.
+        let value = match T::deserialize_unchecked(&mut reader) {
+            Ok(value) => value,
+            Err(_) => return Ok(None),
+        };
+        let mut checksum = [0u8; 64];
+        if reader.inner.read_exact(&mut checksum).is_err()
+            || checksum[..] != reader.hasher.finalize()[..]
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(value))
+    }
+
+    /// Saves `value` as the result of the stage `name` of the run with
+    /// `fingerprint`, replacing the previous file only once the new one is
+    /// complete.
+    pub(crate) fn save<T: CanonicalSerialize>(
+        &self,
+        name: &str,
+        fingerprint: &[u8; 64],
+        value: &T,
+    ) -> Result<(), Error> {
+        let path = self.path(name);
+        let tmp_path = path.with_extension(Self::TMP_EXTENSION);
+        // The directory is deleted once a run succeeds, before any later run
+        // saves its results.
+        fs::create_dir_all(&self.dir).map_err(SerializationError::from)?;
+        let mut writer = HashingWriter {
+            inner: BufWriter::new(File::create(&tmp_path).map_err(SerializationError::from)?),
+            hasher: Blake2b::new(),
+        };
+        writer
+            .inner
+            .write_all(fingerprint)
+            .map_err(SerializationError::from)?;
+        value.serialize_uncompressed(&mut writer)?;
+        let HashingWriter { mut inner, hasher } = writer;
+        inner
+            .write_all(&hasher.finalize())
+            .map_err(SerializationError::from)?;
+        let file = inner
+            .into_inner()
+            .map_err(|e| SerializationError::from(e.into_error()))?;
+        file.sync_all().map_err(SerializationError::from)?;
+        fs::rename(&tmp_path, &path).map_err(SerializationError::from)?;
+        Ok(())
+    }
+}
+
+/// Hashes everything read through it.
+#[cfg(feature = "std")]
+struct HashingReader<R> {
+    inner: R,
+    hasher: Blake2b,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Hashes everything written through it.
+#[cfg(feature = "std")]
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Blake2b,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,569 @@
+use crate::{
+    container::{curve_id, ContainerError},
+    generator::{scaled_powers, synthesize_for_setup},
+    msm::MsmBackend,
+    progress::{Phase, Query, RunOptions, Stages},
+    prover::{G1Query, MsmScalars, ProvingKeySource},
+    r1cs_to_qap::R1CStoQAP,
+    Error, ProvingKey, VerifyingKey,
+};
+use ark_ec::{msm::FixedBaseMSM, AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{Field, PrimeField};
+use ark_poly::EvaluationDomain;
+use ark_relations::r1cs::{ConstraintSynthesizer, SynthesisError};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::{cfg_iter, rand::Rng, Zero};
+use blake2::{Blake2b, Digest};
+use core::{convert::TryFrom, ops::Range};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// The magic bytes at the start of a chunked proving key file.
+const MAGIC: [u8; 8] = *b"BPR20PKC";
+
+/// The number of queries in a proving key.
+const NUM_QUERIES: usize = 5;
+
+/// The length of the Blake2b checksums of the header and of each chunk.
+const CHECKSUM_LEN: usize = 64;
+
+impl<E: PairingEngine> ProvingKey<E> {
+    /// Writes `self` in the chunked layout read by [`ChunkedProvingKey`].
+    ///
+    /// The layout is the magic bytes `BPR20PKC`, the
+    /// [`curve_id`](crate::container::curve_id) of `E`, the chunk size and
+    /// the uncompressed `vk`, `beta_g1` and `delta_g1`, followed by the lengths
+    /// of the A, B in `G1`, B in `G2`, H and L queries and by the Blake2b
+    /// hash of the header so far. The uncompressed elements of the queries
+    /// follow in that order, `chunk_size` at a time, each chunk followed by
+    /// its Blake2b hash. All integers are little-endian `u64`s. The prover
+    /// reads each query a chunk at a time, and checks each chunk it reads
+    /// against its hash.
+    pub fn write_chunked<W: Write>(
+        &self,
+        mut writer: W,
+        chunk_size: usize,
+    ) -> Result<(), SerializationError> {
+        if chunk_size == 0 {
+            return Err(SerializationError::InvalidData);
+        }
+        let lens = [
+            self.a_query.len(),
+            self.b_g1_query.len(),
+            self.b_g2_query.len(),
+            self.h_query.len(),
+            self.l_query.len(),
+        ];
+        write_header(
+            &mut writer,
+            chunk_size,
+            &self.vk,
+            &self.beta_g1,
+            &self.delta_g1,
+            &lens,
+        )?;
+        for query in [&self.a_query, &self.b_g1_query].iter() {
+            for chunk in query.chunks(chunk_size) {
+                write_chunk(&mut writer, chunk)?;
+            }
+        }
+        for chunk in self.b_g2_query.chunks(chunk_size) {
+            write_chunk(&mut writer, chunk)?;
+        }
+        for query in [&self.h_query, &self.l_query].iter() {
+            for chunk in query.chunks(chunk_size) {
+                write_chunk(&mut writer, chunk)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create parameters for a circuit, given some toxic waste and group
+/// generators, writing the proving key to `writer` in the chunked layout
+/// read by [`ChunkedProvingKey`] and returning the verifying key.
+///
+/// Each query is computed and written `chunk_size` elements at a time with
+/// window tables sized for one chunk, so no query is held in memory in full.
+/// The QAP evaluations of the variables in each chunk are computed as it is
+/// written, by scanning all constraints, so besides a chunk only the
+/// constraint matrices and the Lagrange coefficients of the evaluation domain
+/// are held.
+///
+/// The proving key is the same as the one
+/// [`generate_parameters`](crate::generate_parameters) creates from the same
+/// inputs, written with [`ProvingKey::write_chunked`].
+#[allow(clippy::too_many_arguments)]
+pub fn generate_chunked_parameters<E, C, R, W>(
+    circuit: C,
+    alpha: E::Fr,
+    beta: E::Fr,
+    gamma: E::Fr,
+    delta: E::Fr,
+    g1_generator: E::G1Projective,
+    g2_generator: E::G2Projective,
+    rng: &mut R,
+    writer: W,
+    chunk_size: usize,
+) -> Result<VerifyingKey<E>, Error>
+where
+    E: PairingEngine,
+    C: ConstraintSynthesizer<E::Fr>,
+    R: Rng,
+    W: Write,
+{
+    generate_chunked_parameters_with_options(
+        circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        rng,
+        writer,
+        chunk_size,
+        RunOptions::new(),
+    )
+}
+
+/// Create parameters for a circuit like [`generate_chunked_parameters`],
+/// observing the run with `options`.
+///
+/// With a [`Progress`](crate::Progress), the run reports synthesis, the
+/// QAP evaluation and each query after each of its chunks is written. With a
+/// [`CancellationToken`](crate::CancellationToken), it returns
+/// [`Error::Cancelled`] once the chunk being written when the token is
+/// cancelled is done, leaving `writer` with part of the key. The checkpoints
+/// of `options` are not used, since the key is written as it is computed.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_chunked_parameters_with_options<E, C, R, W>(
+    circuit: C,
+    alpha: E::Fr,
+    beta: E::Fr,
+    gamma: E::Fr,
+    delta: E::Fr,
+    g1_generator: E::G1Projective,
+    g2_generator: E::G2Projective,
+    rng: &mut R,
+    mut writer: W,
+    chunk_size: usize,
+    options: RunOptions<'_>,
+) -> Result<VerifyingKey<E>, Error>
+where
+    E: PairingEngine,
+    C: ConstraintSynthesizer<E::Fr>,
+    R: Rng,
+    W: Write,
+{
+    if chunk_size == 0 {
+        return Err(SerializationError::InvalidData.into());
+    }
+    let stages = options.stages();
+    let setup_time = start_phase!("BPR20::Generator to chunked proving key");
+    stages.report(Phase::Synthesis, 0.0)?;
+    let (cs, domain, t) = synthesize_for_setup(circuit, rng)?;
+    stages.report(Phase::Synthesis, 1.0)?;
+
+    // Only the matrices and the Lagrange coefficients are kept, and the QAP
+    // evaluations are computed from them a chunk at a time.
+    let reduction_time = start_phase!("R1CS to QAP Lagrange coefficients");
+    stages.report(Phase::Qap, 0.0)?;
+    let matrices = cs.to_matrices().ok_or(SynthesisError::MissingCS)?;
+    drop(cs);
+    let num_instance_variables = matrices.num_instance_variables;
+    let num_variables = num_instance_variables + matrices.num_witness_variables;
+    let u = domain.evaluate_all_lagrange_coefficients(t);
+    stages.report(Phase::Qap, 1.0)?;
+    let zt = domain.evaluate_vanishing_polynomial(t);
+    end_phase!(reduction_time);
+    let qap = |r: Range<usize>| R1CStoQAP::evaluate_variables(&matrices, &u, r);
+
+    let gamma_inverse = gamma.inverse().ok_or(SynthesisError::UnexpectedIdentity)?;
+    let delta_inverse = delta.inverse().ok_or(SynthesisError::UnexpectedIdentity)?;
+    let num_h = domain.size() - 1;
+    let num_l = num_variables - num_instance_variables;
+    let table_size = core::cmp::min(chunk_size, core::cmp::max(num_variables, num_h));
+    let g1_table = WindowTable::new(g1_generator, table_size);
+    let g2_table = WindowTable::new(g2_generator, table_size);
+
+    let vk_time = start_phase!("Generate the verifying key");
+    stages.report(Phase::Msm(Query::GammaAbc), 0.0)?;
+    let (a, b, c) = qap(0..num_instance_variables);
+    let gamma_abc = cfg_iter!(a)
+        .zip(&b)
+        .zip(&c)
+        .map(|((a, b), c)| (beta * a + &(alpha * b) + c) * &gamma_inverse)
+        .collect::<Vec<_>>();
+    let alpha_g1 = g1_generator.mul(&alpha.into_repr());
+    let beta_g1 = g1_generator.mul(&beta.into_repr());
+    let beta_g2 = g2_generator.mul(&beta.into_repr());
+    let delta_g1 = g1_generator.mul(&delta.into_repr());
+    let delta_g2 = g2_generator.mul(&delta.into_repr());
+    let gamma_g2 = g2_generator.mul(&gamma.into_repr());
+    let vk = VerifyingKey::<E> {
+        alpha_g1: alpha_g1.into_affine(),
+        beta_g2: beta_g2.into_affine(),
+        gamma_g2: gamma_g2.into_affine(),
+        delta_g2: delta_g2.into_affine(),
+        gamma_abc_g1: g1_table.mul(&gamma_abc),
+        alpha_g1_beta_g2: E::pairing(alpha_g1, beta_g2),
+        zt_delta_g1: g1_table.mul(&[zt * &delta_inverse])[0],
+    };
+    stages.report(Phase::Msm(Query::GammaAbc), 1.0)?;
+    end_phase!(vk_time);
+
+    write_header(
+        &mut writer,
+        chunk_size,
+        &vk,
+        &beta_g1.into_affine(),
+        &delta_g1.into_affine(),
+        &[num_variables, num_variables, num_variables, num_h, num_l],
+    )?;
+
+    let queries_time = start_phase!("Write A, B G1, B G2, H and L");
+    let w = &mut writer;
+    write_query(w, &stages, Query::A, num_variables, chunk_size, |r| {
+        g1_table.mul(&qap(r).0)
+    })?;
+    write_query(w, &stages, Query::BG1, num_variables, chunk_size, |r| {
+        g1_table.mul(&qap(r).1)
+    })?;
+    write_query(w, &stages, Query::BG2, num_variables, chunk_size, |r| {
+        g2_table.mul(&qap(r).1)
+    })?;
+    write_query(w, &stages, Query::H, num_h, chunk_size, |r| {
+        g1_table.mul(&scaled_powers(zt * &delta_inverse, t, r))
+    })?;
+    // Only the witness part of the L-query is part of the proving key.
+    write_query(w, &stages, Query::L, num_l, chunk_size, |r| {
+        let (a, b, c) = qap(r.start + num_instance_variables..r.end + num_instance_variables);
+        let l = cfg_iter!(a)
+            .zip(&b)
+            .zip(&c)
+            .map(|((a, b), c)| (beta * a + &(alpha * b) + c) * &delta_inverse)
+            .collect::<Vec<_>>();
+        g1_table.mul(&l)
+    })?;
+    writer.flush().map_err(SerializationError::from)?;
+    end_phase!(queries_time);
+    end_phase!(setup_time);
+
+    Ok(vk)
+}
+
+/// Writes the `len` elements of `query`, computing the elements in each
+/// range of `chunk_size` of them with `chunk` and reporting the progress of
+/// the query after each chunk is written.
+fn write_query<G, St, W, F>(
+    writer: &mut W,
+    stages: &St,
+    query: Query,
+    len: usize,
+    chunk_size: usize,
+    chunk: F,
+) -> Result<(), Error>
+where
+    G: CanonicalSerialize,
+    St: Stages<Error = Error>,
+    W: Write,
+    F: Fn(Range<usize>) -> Vec<G>,
+{
+    let phase = Phase::Msm(query);
+    stages.report(phase, 0.0)?;
+    for lo in (0..len).step_by(chunk_size) {
+        let hi = core::cmp::min(lo + chunk_size, len);
+        write_chunk(writer, &chunk(lo..hi))?;
+        stages.report(phase, hi as f64 / len as f64)?;
+    }
+    stages.report(phase, 1.0)
+}
+
+/// Writes the header of the chunked layout, with the lengths `lens` of the
+/// queries, followed by its hash.
+fn write_header<E: PairingEngine, W: Write>(
+    writer: &mut W,
+    chunk_size: usize,
+    vk: &VerifyingKey<E>,
+    beta_g1: &E::G1Affine,
+    delta_g1: &E::G1Affine,
+    lens: &[usize; NUM_QUERIES],
+) -> Result<(), SerializationError> {
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&curve_id::<E>());
+    (chunk_size as u64).serialize(&mut header)?;
+    vk.serialize_uncompressed(&mut header)?;
+    beta_g1.serialize_uncompressed(&mut header)?;
+    delta_g1.serialize_uncompressed(&mut header)?;
+    for len in lens.iter() {
+        (*len as u64).serialize(&mut header)?;
+    }
+    writer.write_all(&header)?;
+    writer.write_all(&Blake2b::digest(&header))?;
+    Ok(())
+}
+
+/// Writes the uncompressed `elements` of a chunk, without their number,
+/// followed by their hash.
+fn write_chunk<G: CanonicalSerialize, W: Write>(
+    writer: &mut W,
+    elements: &[G],
+) -> Result<(), SerializationError> {
+    let mut bytes = Vec::new();
+    for g in elements.iter() {
+        g.serialize_uncompressed(&mut bytes)?;
+    }
+    writer.write_all(&bytes)?;
+    writer.write_all(&Blake2b::digest(&bytes))?;
+    Ok(())
+}
+
+/// A window table of multiples of a generator, for fixed-base MSMs of up to
+/// about as many scalars as it was sized for.
+struct WindowTable<G: ProjectiveCurve> {
+    window: usize,
+    table: Vec<Vec<G::Affine>>,
+}
+
+impl<G: ProjectiveCurve> WindowTable<G> {
+    fn new(generator: G, num_scalars: usize) -> Self {
+        let window = FixedBaseMSM::get_mul_window_size(num_scalars);
+        let scalar_bits = G::ScalarField::size_in_bits();
+        let table = FixedBaseMSM::get_window_table(scalar_bits, window, generator);
+        Self { window, table }
+    }
+
+    /// Computes `scalars[i] * generator` for each `i`.
+    fn mul(&self, scalars: &[G::ScalarField]) -> Vec<G::Affine> {
+        let scalar_bits = G::ScalarField::size_in_bits();
+        let query =
+            FixedBaseMSM::multi_scalar_mul::<G>(scalar_bits, self.window, &self.table, scalars);
+        G::batch_normalization_into_affine(&query)
+    }
+}
+
+/// A proving key in a file written by [`ProvingKey::write_chunked`], whose
+/// queries are read a chunk at a time straight into the prover's MSMs
+/// instead of being held in memory.
+pub struct ChunkedProvingKey<E: PairingEngine> {
+    path: PathBuf,
+    vk: VerifyingKey<E>,
+    beta_g1: E::G1Affine,
+    delta_g1: E::G1Affine,
+    chunk_size: usize,
+    /// The offset in the file and the length of each query, in the order
+    /// A, B in `G1`, B in `G2`, H and L.
+    queries: [(u64, usize); NUM_QUERIES],
+}
+
+impl<E: PairingEngine> ChunkedProvingKey<E> {
+    /// Opens the chunked proving key at `path`, reading only its header,
+    /// which fails if the key is for another curve than `E`, if the header
+    /// does not match its hash or if the file does not have the size given by
+    /// the header.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut reader = BufReader::new(File::open(&path).map_err(SerializationError::from)?);
+
+        let mut magic = [0u8; 8];
+        let mut found = [0u8; 16];
+        reader
+            .read_exact(&mut magic)
+            .and_then(|_| reader.read_exact(&mut found))
+            .map_err(SerializationError::from)?;
+        if magic != MAGIC {
+            return Err(SerializationError::InvalidData.into());
+        }
+        // The rest of the header can only be read for the right curve, so the
+        // curve is compared before the header is checked against its hash.
+        let expected = curve_id::<E>();
+        if found != expected {
+            return Err(ContainerError::CurveMismatch { expected, found }.into());
+        }
+
+        Ok(Self::read_header(path, reader)?)
+    }
+
+    /// Reads the rest of the header of the chunked proving key at `path`
+    /// from `reader`, which is past the magic bytes and the curve.
+    fn read_header(path: PathBuf, mut reader: BufReader<File>) -> Result<Self, SerializationError> {
+        let chunk_size = u64::deserialize(&mut reader)?;
+        if chunk_size == 0 {
+            return Err(SerializationError::InvalidData);
+        }
+        let vk = VerifyingKey::deserialize_uncompressed(&mut reader)?;
+        let beta_g1 = E::G1Affine::deserialize_uncompressed(&mut reader)?;
+        let delta_g1 = E::G1Affine::deserialize_uncompressed(&mut reader)?;
+        let mut lens = [0u64; NUM_QUERIES];
+        for len in lens.iter_mut() {
+            *len = u64::deserialize(&mut reader)?;
+        }
+
+        let header_len = reader.stream_position()?;
+        let mut header = vec![0u8; header_len as usize];
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut header)?;
+        let mut checksum = [0u8; CHECKSUM_LEN];
+        reader.read_exact(&mut checksum)?;
+        if checksum[..] != Blake2b::digest(&header)[..] {
+            return Err(SerializationError::InvalidData);
+        }
+
+        // The header is only protected by an unkeyed hash, so the lengths in
+        // it are checked against the size of the file before any offset is
+        // computed from them.
+        let file_len = std::fs::metadata(&path)?.len();
+        let g1_size = E::G1Affine::zero().uncompressed_size() as u64;
+        let g2_size = E::G2Affine::zero().uncompressed_size() as u64;
+        let sizes = [g1_size, g1_size, g2_size, g1_size, g1_size];
+        let mut offset = header_len + CHECKSUM_LEN as u64;
+        let mut queries = [(0, 0); NUM_QUERIES];
+        for ((query, &len), size) in queries.iter_mut().zip(lens.iter()).zip(sizes.iter()) {
+            let num_chunks = len / chunk_size + (len % chunk_size != 0) as u64;
+            let end = len
+                .checked_mul(*size)
+                .and_then(|bytes| bytes.checked_add(num_chunks.checked_mul(CHECKSUM_LEN as u64)?))
+                .and_then(|bytes| offset.checked_add(bytes))
+                .filter(|&end| end <= file_len)
+                .ok_or(SerializationError::InvalidData)?;
+            let len = usize::try_from(len).map_err(|_| SerializationError::InvalidData)?;
+            *query = (offset, len);
+            offset = end;
+        }
+        if offset != file_len {
+            return Err(SerializationError::InvalidData);
+        }
+        // A chunk size larger than every query gives the same layout as the
+        // length of the longest one, which fits in the file.
+        let max_len = lens.iter().copied().max().unwrap_or(0);
+        let chunk_size = core::cmp::min(chunk_size, core::cmp::max(max_len, 1)) as usize;
+
+        Ok(Self {
+            path,
+            vk,
+            beta_g1,
+            delta_g1,
+            chunk_size,
+            queries,
+        })
+    }
+
+    /// The number of query elements read at a time, which is at most the
+    /// length of the longest query.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Computes `sum_i scalars[i] * query[i]` on `backend` over the
+    /// `query`-th query, reading it a chunk at a time and failing if a chunk
+    /// does not match its hash.
+    fn msm<G: AffineCurve<ScalarField = E::Fr>, B: MsmBackend>(
+        &self,
+        backend: &B,
+        query: usize,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> Result<G::Projective, Error> {
+        let (offset, query_len) = self.queries[query];
+        // Scalars beyond the end of the query are ignored and vice versa.
+        let len = core::cmp::min(query_len, scalars.len());
+
+        // The chunks before the one with the first non-zero scalar are not
+        // read.
+        let start = core::cmp::min(scalars.start(), len);
+        if start == len {
+            return Ok(G::Projective::zero());
+        }
+        let element_size = G::zero().uncompressed_size();
+        // `open` checked that the queries fit in the file, so these offsets
+        // do not overflow.
+        let chunk_len = self.chunk_size as u64 * element_size as u64 + CHECKSUM_LEN as u64;
+        let mut lo = start - start % self.chunk_size;
+
+        let mut reader = BufReader::new(File::open(&self.path).map_err(SerializationError::from)?);
+        reader
+            .seek(SeekFrom::Start(
+                offset + (lo / self.chunk_size) as u64 * chunk_len,
+            ))
+            .map_err(SerializationError::from)?;
+        let mut bytes = Vec::new();
+        let mut chunk = Vec::with_capacity(core::cmp::min(self.chunk_size, query_len - lo));
+        let mut checksum = [0u8; CHECKSUM_LEN];
+        let mut acc = G::Projective::zero();
+        while lo < len {
+            let n = core::cmp::min(self.chunk_size, query_len - lo);
+            bytes.resize(n * element_size, 0);
+            reader
+                .read_exact(&mut bytes)
+                .map_err(SerializationError::from)?;
+            reader
+                .read_exact(&mut checksum)
+                .map_err(SerializationError::from)?;
+            if checksum[..] != Blake2b::digest(&bytes)[..] {
+                return Err(SerializationError::InvalidData.into());
+            }
+            chunk.clear();
+            let mut elements = &bytes[..];
+            for _ in 0..n {
+                // The elements were checked when the key was generated and
+                // the chunk matches its hash, so they are read without the
+                // subgroup checks.
+                chunk.push(G::deserialize_unchecked(&mut elements)?);
+            }
+            let from = core::cmp::max(lo, start);
+            let to = core::cmp::min(lo + n, len);
+            acc += &scalars.msm_range(backend, &chunk[from - lo..to - lo], from);
+            lo += n;
+        }
+        Ok(acc)
+    }
+}
+
+impl<E: PairingEngine> ProvingKeySource<E> for ChunkedProvingKey<E> {
+    type Error = Error;
+
+    fn vk(&self) -> &VerifyingKey<E> {
+        &self.vk
+    }
+
+    fn beta_g1(&self) -> E::G1Affine {
+        self.beta_g1
+    }
+
+    fn delta_g1(&self) -> E::G1Affine {
+        self.delta_g1
+    }
+
+    fn num_variables(&self) -> usize {
+        self.queries[0].1
+    }
+
+    fn msm_g1<B: MsmBackend>(
+        &self,
+        backend: &B,
+        query: G1Query,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> Result<E::G1Projective, Error> {
+        let query = match query {
+            G1Query::A => 0,
+            G1Query::BG1 => 1,
+            G1Query::H => 3,
+            G1Query::L => 4,
+        };
+        self.msm::<E::G1Affine, B>(backend, query, scalars)
+    }
+
+    fn msm_b_g2<B: MsmBackend>(
+        &self,
+        backend: &B,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> Result<E::G2Projective, Error> {
+        self.msm::<E::G2Affine, B>(backend, 2, scalars)
+    }
+}
@@ -0,0 +1,527 @@
+#[cfg(feature = "std")]
+use crate::Error;
+use crate::{checkpoint::Fingerprint, progress::Query, ProvingKey, VerifyingKey};
+use ark_ec::{AffineCurve, PairingEngine};
+use ark_ff::Field;
+use ark_serialize::{CanonicalSerialize, SerializationError};
+use ark_std::{io::Write, vec::Vec};
+use blake2::{Blake2b, Digest};
+use core::fmt;
+
+#[cfg(feature = "std")]
+use ark_serialize::CanonicalDeserialize;
+#[cfg(feature = "std")]
+use core::convert::TryInto;
+#[cfg(feature = "std")]
+use std::io::{Read, Seek, SeekFrom};
+
+/// The magic bytes at the start of a key container.
+const MAGIC: [u8; 8] = *b"BPR20KEY";
+
+/// The version of the container format that is written, and the only one
+/// that is read.
+pub const CONTAINER_VERSION: u32 = 1;
+
+/// The flag that is set if the elements of the sections are compressed.
+const FLAG_COMPRESSED: u32 = 1;
+
+/// The length of the fixed part of the header, up to the section table.
+const HEADER_LEN: usize = 8 + 4 + 4 + 16 + 64 + 4;
+
+/// The length of an entry of the section table.
+const ENTRY_LEN: usize = 4 + 8 + 8 + 64;
+
+/// The sections a container can have, in the order in which they are
+/// written.
+const SECTIONS: [Section; 7] = [
+    Section::VerifyingKey,
+    Section::ProverElements,
+    Section::Query(Query::A),
+    Section::Query(Query::BG1),
+    Section::Query(Query::BG2),
+    Section::Query(Query::H),
+    Section::Query(Query::L),
+];
+
+/// A section of a key container.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    /// The verifying key.
+    VerifyingKey,
+    /// The elements `beta * G` and `delta * G` of the proving key.
+    ProverElements,
+    /// A query of the proving key. The verifying key holds the elements of
+    /// [`Query::GammaAbc`], which is not a section of its own.
+    Query(Query),
+}
+
+impl Section {
+    /// The identifier of the section in the section table.
+    fn id(self) -> u32 {
+        match self {
+            Section::VerifyingKey => 1,
+            Section::ProverElements => 2,
+            Section::Query(Query::A) => 3,
+            Section::Query(Query::BG1) => 4,
+            Section::Query(Query::BG2) => 5,
+            Section::Query(Query::H) => 6,
+            Section::Query(Query::L) => 7,
+            Section::Query(Query::GammaAbc) => unreachable!("not a section"),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn from_id(id: u32) -> Option<Self> {
+        SECTIONS.iter().copied().find(|section| section.id() == id)
+    }
+}
+
+/// The reasons for which a key container cannot be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContainerError {
+    /// The data does not start with the magic bytes of a key container.
+    NotAContainer,
+    /// The container was written in a version of the format that is not
+    /// supported.
+    UnsupportedVersion(u32),
+    /// The container holds keys for another curve than the one it is read
+    /// for.
+    CurveMismatch {
+        /// The identifier of the curve the container is read for.
+        expected: [u8; 16],
+        /// The identifier of the curve of the keys in the container.
+        found: [u8; 16],
+    },
+    /// The container does not have the section, for example because it only
+    /// holds a verifying key.
+    MissingSection(Section),
+    /// The query was read as a query in `E::G1`, but its elements are in
+    /// `E::G2`.
+    NotInG1(Query),
+    /// The header or the section table is damaged.
+    CorruptedHeader,
+    /// The section does not match its checksum or cannot be read.
+    CorruptedSection(Section),
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContainerError::NotAContainer => write!(f, "not a BPR20 key container"),
+            ContainerError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported key container version {} (expected {})",
+                version, CONTAINER_VERSION
+            ),
+            ContainerError::CurveMismatch { expected, found } => write!(
+                f,
+                "the keys are for the curve {}, not {}",
+                Hex(found),
+                Hex(expected)
+            ),
+            ContainerError::MissingSection(section) => {
+                write!(f, "the container has no {:?} section", section)
+            },
+            ContainerError::NotInG1(query) => write!(f, "the {:?} query is not in G1", query),
+            ContainerError::CorruptedHeader => write!(f, "the container header is corrupted"),
+            ContainerError::CorruptedSection(section) => {
+                write!(f, "the {:?} section is corrupted", section)
+            },
+        }
+    }
+}
+
+/// Displays bytes in lowercase hexadecimal.
+pub(crate) struct Hex<'a>(pub(crate) &'a [u8]);
+
+impl<'a> fmt::Display for Hex<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+/// An identifier of the pairing-friendly curve `E`: the first 16 bytes of
+/// the Blake2b hash of the characteristics of its scalar and base fields and
+/// of the generators of its groups.
+pub fn curve_id<E: PairingEngine>() -> [u8; 16] {
+    let fingerprint = Fingerprint::new(b"BPR20 curve")
+        .update(&E::Fr::characteristic().to_vec())
+        .update(&E::Fq::characteristic().to_vec())
+        .update(&E::G1Affine::prime_subgroup_generator())
+        .update(&E::G2Affine::prime_subgroup_generator())
+        .finish();
+    let mut id = [0u8; 16];
+    id.copy_from_slice(&fingerprint[..16]);
+    id
+}
+
+impl<E: PairingEngine> VerifyingKey<E> {
+    /// The Blake2b hash of the uncompressed verifying key, which identifies
+    /// the circuit and the setup the key belongs to.
+    pub fn fingerprint(&self) -> [u8; 64] {
+        Fingerprint::new(b"BPR20 verifying key")
+            .update(self)
+            .finish()
+    }
+
+    /// Writes `self` as a key container with only the
+    /// [`Section::VerifyingKey`] section, in the format described at
+    /// [`ProvingKey::write_container`].
+    pub fn write_container<W: Write>(
+        &self,
+        writer: W,
+        compressed: bool,
+    ) -> Result<(), SerializationError> {
+        Contents { vk: self, pk: None }.write(writer, compressed)
+    }
+}
+
+impl<E: PairingEngine> ProvingKey<E> {
+    /// Writes `self` as a key container, which [`KeyContainer`] reads in
+    /// whole or in part.
+    ///
+    /// The container starts with a header of
+    ///
+    /// | bytes | contents                                                   |
+    /// |-------|------------------------------------------------------------|
+    /// | 8     | the magic bytes `BPR20KEY`                                 |
+    /// | 4     | the format version, [`CONTAINER_VERSION`]                  |
+    /// | 4     | flags, of which bit 0 is set if the elements are compressed |
+    /// | 16    | the [`curve_id`] of the curve                              |
+    /// | 64    | the [`VerifyingKey::fingerprint`] of the keys              |
+    /// | 4     | the number of sections                                     |
+    ///
+    /// followed by the section table, with an entry of the identifier, the
+    /// offset from the start of the container, the length and the Blake2b
+    /// hash of each section, and the Blake2b hash of the header and the
+    /// table. The identifiers are 1 for the verifying key, 2 for `beta_g1`
+    /// and `delta_g1`, and 3 to 7 for the A, B in `G1`, B in `G2`, H and L
+    /// queries. The sections follow in the order of their identifiers, each
+    /// holding the canonical serialization of its contents. All integers
+    /// are little-endian, of 4 bytes for identifiers and counts and of 8
+    /// bytes for offsets and lengths.
+    ///
+    /// Each section is serialized twice, once to compute its length and
+    /// hash and once to write it, so that the writer need not be seekable.
+    pub fn write_container<W: Write>(
+        &self,
+        writer: W,
+        compressed: bool,
+    ) -> Result<(), SerializationError> {
+        Contents {
+            vk: &self.vk,
+            pk: Some(self),
+        }
+        .write(writer, compressed)
+    }
+}
+
+/// The keys written to a container.
+struct Contents<'a, E: PairingEngine> {
+    vk: &'a VerifyingKey<E>,
+    pk: Option<&'a ProvingKey<E>>,
+}
+
+impl<'a, E: PairingEngine> Contents<'a, E> {
+    fn sections(&self) -> &'static [Section] {
+        match self.pk {
+            Some(_) => &SECTIONS,
+            None => &SECTIONS[..1],
+        }
+    }
+
+    fn write<W: Write>(&self, mut writer: W, compressed: bool) -> Result<(), SerializationError> {
+        let sections = self.sections();
+        let mut header = Vec::with_capacity(HEADER_LEN + sections.len() * ENTRY_LEN);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&CONTAINER_VERSION.to_le_bytes());
+        let flags = if compressed { FLAG_COMPRESSED } else { 0 };
+        header.extend_from_slice(&flags.to_le_bytes());
+        header.extend_from_slice(&curve_id::<E>());
+        header.extend_from_slice(&self.vk.fingerprint());
+        header.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+
+        let mut offset = (HEADER_LEN + sections.len() * ENTRY_LEN + 64) as u64;
+        for &section in sections {
+            let mut checksum = ChecksumWriter {
+                hasher: Blake2b::new(),
+                len: 0,
+            };
+            self.write_section(section, &mut checksum, compressed)?;
+            header.extend_from_slice(&section.id().to_le_bytes());
+            header.extend_from_slice(&offset.to_le_bytes());
+            header.extend_from_slice(&checksum.len.to_le_bytes());
+            header.extend_from_slice(&checksum.hasher.finalize());
+            offset += checksum.len;
+        }
+        let header_checksum = Blake2b::digest(&header);
+        writer.write_all(&header)?;
+        writer.write_all(&header_checksum)?;
+
+        for &section in sections {
+            self.write_section(section, &mut writer, compressed)?;
+        }
+        Ok(())
+    }
+
+    fn write_section<W: Write>(
+        &self,
+        section: Section,
+        writer: W,
+        compressed: bool,
+    ) -> Result<(), SerializationError> {
+        let pk = match (section, self.pk) {
+            (Section::VerifyingKey, _) => return serialize(self.vk, writer, compressed),
+            (_, Some(pk)) => pk,
+            (_, None) => unreachable!("only proving keys have other sections"),
+        };
+        match section {
+            Section::ProverElements => serialize(&(pk.beta_g1, pk.delta_g1), writer, compressed),
+            Section::Query(Query::A) => serialize(&pk.a_query, writer, compressed),
+            Section::Query(Query::BG1) => serialize(&pk.b_g1_query, writer, compressed),
+            Section::Query(Query::BG2) => serialize(&pk.b_g2_query, writer, compressed),
+            Section::Query(Query::H) => serialize(&pk.h_query, writer, compressed),
+            Section::Query(Query::L) => serialize(&pk.l_query, writer, compressed),
+            Section::VerifyingKey | Section::Query(Query::GammaAbc) => unreachable!(),
+        }
+    }
+}
+
+fn serialize<T: CanonicalSerialize, W: Write>(
+    value: &T,
+    writer: W,
+    compressed: bool,
+) -> Result<(), SerializationError> {
+    if compressed {
+        value.serialize(writer)
+    } else {
+        value.serialize_uncompressed(writer)
+    }
+}
+
+/// Computes the length and the hash of everything written to it.
+struct ChecksumWriter {
+    hasher: Blake2b,
+    len: u64,
+}
+
+impl Write for ChecksumWriter {
+    fn write(&mut self, buf: &[u8]) -> ark_std::io::Result<usize> {
+        self.hasher.update(buf);
+        self.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> ark_std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A key container written by [`ProvingKey::write_container`] or
+/// [`VerifyingKey::write_container`], from which the keys or single
+/// sections are read.
+///
+/// Opening a container only reads and checks its header, which fails if the
+/// container is for another curve than `E`. Each section is only read when
+/// it is asked for, by seeking to it, and is checked against its hash.
+#[cfg(feature = "std")]
+pub struct KeyContainer<E: PairingEngine, R> {
+    reader: R,
+    version: u32,
+    compressed: bool,
+    fingerprint: [u8; 64],
+    /// The offset, length and hash of each known section.
+    sections: Vec<(Section, u64, u64, [u8; 64])>,
+    _engine: core::marker::PhantomData<E>,
+}
+
+#[cfg(feature = "std")]
+impl<E: PairingEngine, R: Read + Seek> KeyContainer<E, R> {
+    /// Opens the key container read from `reader`, which starts at the
+    /// current position of `reader`.
+    pub fn open(mut reader: R) -> Result<Self, Error> {
+        let start = reader.stream_position().map_err(SerializationError::from)?;
+        let mut header = vec![0u8; HEADER_LEN];
+        read_exact(&mut reader, &mut header[..8], ContainerError::NotAContainer)?;
+        if header[..8] != MAGIC {
+            return Err(ContainerError::NotAContainer.into());
+        }
+        read_exact(
+            &mut reader,
+            &mut header[8..],
+            ContainerError::CorruptedHeader,
+        )?;
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != CONTAINER_VERSION {
+            return Err(ContainerError::UnsupportedVersion(version).into());
+        }
+        let flags = u32::from_le_bytes(header[12..16].try_into().unwrap());
+        let mut found = [0u8; 16];
+        found.copy_from_slice(&header[16..32]);
+        let mut fingerprint = [0u8; 64];
+        fingerprint.copy_from_slice(&header[32..96]);
+        let num_sections = u32::from_le_bytes(header[96..100].try_into().unwrap()) as usize;
+        // Unknown sections of later writers are skipped, but a count far
+        // beyond the known ones means the header is damaged.
+        if num_sections > 4 * SECTIONS.len() {
+            return Err(ContainerError::CorruptedHeader.into());
+        }
+
+        header.resize(HEADER_LEN + num_sections * ENTRY_LEN, 0);
+        read_exact(
+            &mut reader,
+            &mut header[HEADER_LEN..],
+            ContainerError::CorruptedHeader,
+        )?;
+        let mut checksum = [0u8; 64];
+        read_exact(&mut reader, &mut checksum, ContainerError::CorruptedHeader)?;
+        if checksum[..] != Blake2b::digest(&header)[..] {
+            return Err(ContainerError::CorruptedHeader.into());
+        }
+
+        // The curve is only compared once the header is known to be intact,
+        // so that damage is not reported as a curve mismatch.
+        let expected = curve_id::<E>();
+        if found != expected {
+            return Err(ContainerError::CurveMismatch { expected, found }.into());
+        }
+
+        let sections = header[HEADER_LEN..]
+            .chunks(ENTRY_LEN)
+            .filter_map(|entry| {
+                let section = Section::from_id(u32::from_le_bytes(entry[..4].try_into().unwrap()))?;
+                let offset = u64::from_le_bytes(entry[4..12].try_into().unwrap());
+                let len = u64::from_le_bytes(entry[12..20].try_into().unwrap());
+                let mut checksum = [0u8; 64];
+                checksum.copy_from_slice(&entry[20..]);
+                Some((section, start + offset, len, checksum))
+            })
+            .collect();
+
+        Ok(Self {
+            reader,
+            version,
+            compressed: flags & FLAG_COMPRESSED != 0,
+            fingerprint,
+            sections,
+            _engine: core::marker::PhantomData,
+        })
+    }
+
+    /// The version of the format the container was written in.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns `true` if the elements of the sections are compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// The [`VerifyingKey::fingerprint`] of the keys in the container.
+    pub fn fingerprint(&self) -> [u8; 64] {
+        self.fingerprint
+    }
+
+    /// Returns `true` if the container has `section`.
+    pub fn has_section(&self, section: Section) -> bool {
+        self.sections.iter().any(|(s, ..)| *s == section)
+    }
+
+    /// Reads the verifying key, which every container has.
+    pub fn read_verifying_key(&mut self) -> Result<VerifyingKey<E>, Error> {
+        let vk: VerifyingKey<E> = self.read_section(Section::VerifyingKey)?;
+        if vk.fingerprint() != self.fingerprint {
+            return Err(ContainerError::CorruptedSection(Section::VerifyingKey).into());
+        }
+        Ok(vk)
+    }
+
+    /// Reads the query `query` in `E::G1`, without the rest of the proving
+    /// key. The elements of [`Query::GammaAbc`] are read from the verifying
+    /// key.
+    ///
+    /// Fails with [`ContainerError::NotInG1`] if `query` is [`Query::BG2`],
+    /// which is read with [`read_b_g2_query`](Self::read_b_g2_query).
+    pub fn read_g1_query(&mut self, query: Query) -> Result<Vec<E::G1Affine>, Error> {
+        match query {
+            Query::BG2 => Err(ContainerError::NotInG1(query).into()),
+            Query::GammaAbc => Ok(self.read_verifying_key()?.gamma_abc_g1),
+            _ => self.read_section(Section::Query(query)),
+        }
+    }
+
+    /// Reads the B-query in `E::G2`, without the rest of the proving key.
+    pub fn read_b_g2_query(&mut self) -> Result<Vec<E::G2Affine>, Error> {
+        self.read_section(Section::Query(Query::BG2))
+    }
+
+    /// Reads the whole proving key.
+    pub fn read_proving_key(&mut self) -> Result<ProvingKey<E>, Error> {
+        let vk = self.read_verifying_key()?;
+        let (beta_g1, delta_g1) = self.read_section(Section::ProverElements)?;
+        Ok(ProvingKey {
+            vk,
+            beta_g1,
+            delta_g1,
+            a_query: self.read_g1_query(Query::A)?,
+            b_g1_query: self.read_g1_query(Query::BG1)?,
+            b_g2_query: self.read_b_g2_query()?,
+            h_query: self.read_g1_query(Query::H)?,
+            l_query: self.read_g1_query(Query::L)?,
+        })
+    }
+
+    /// Reads `section`, checking that it matches its hash and that its
+    /// contents take up exactly its length.
+    fn read_section<T: CanonicalDeserialize>(&mut self, section: Section) -> Result<T, Error> {
+        let &(_, offset, len, checksum) = self
+            .sections
+            .iter()
+            .find(|(s, ..)| *s == section)
+            .ok_or(ContainerError::MissingSection(section))?;
+        self.reader
+            .seek(SeekFrom::Start(offset))
+            .map_err(SerializationError::from)?;
+        let mut reader = ChecksumReader {
+            inner: (&mut self.reader).take(len),
+            hasher: Blake2b::new(),
+        };
+        let corrupted = ContainerError::CorruptedSection(section);
+        let value = if self.compressed {
+            T::deserialize(&mut reader)
+        } else {
+            T::deserialize_uncompressed(&mut reader)
+        }
+        .map_err(|_| corrupted.clone())?;
+        if reader.inner.limit() != 0 || reader.hasher.finalize()[..] != checksum[..] {
+            return Err(corrupted.into());
+        }
+        Ok(value)
+    }
+}
+
+/// Fills `buf` from `reader`, failing with `error` if the data ends first.
+#[cfg(feature = "std")]
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8], error: ContainerError) -> Result<(), Error> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => Error::from(error),
+        _ => SerializationError::from(e).into(),
+    })
+}
+
+/// Hashes everything read through it.
+#[cfg(feature = "std")]
+struct ChecksumReader<R> {
+    inner: R,
+    hasher: Blake2b,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
@@ -1,13 +1,16 @@
-use ark_ec::PairingEngine;
-use ark_ff::bytes::ToBytes;
+use crate::{
+    checkpoint::Fingerprint,
+    prepared::{Prepared, SerializablePreparedG2},
+};
+use ark_ec::{msm::VariableBaseMSM, AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{bytes::ToBytes, PrimeField};
+use ark_relations::r1cs::Result as R1CSResult;
 use ark_serialize::*;
 use ark_std::{
     io::{self, Result as IoResult},
     vec::Vec,
 };
 
-
-
 /// A proof in the BPR20 SNARK.
 #[derive(Clone, Debug, PartialEq, CanonicalSerialize, CanonicalDeserialize)]
 pub struct Proof<E: PairingEngine> {
@@ -60,7 +63,7 @@ pub struct VerifyingKey<E: PairingEngine> {
     pub gamma_abc_g1: Vec<E::G1Affine>,
     /// The element `e(alpha * G, beta * H)` in `E::GT`.
     pub alpha_g1_beta_g2: E::Fqk,
-    /// The element `zt*delta^{-1}` in `E::G1` 
+    /// The element `zt*delta^{-1}` in `E::G1`
     pub zt_delta_g1: E::G1Affine,
 }
 
@@ -79,6 +82,38 @@ impl<E: PairingEngine> ToBytes for VerifyingKey<E> {
     }
 }
 
+impl<E: PairingEngine> VerifyingKey<E> {
+    /// Returns the verification key for the statements whose public inputs
+    /// at the indices of `fixed` have the values of `fixed`, which folds
+    /// these inputs into the first element of `gamma_abc_g1`. The returned
+    /// key takes the other public inputs, in order.
+    ///
+    /// Fails if `fixed` has an index twice or an index that is not the one
+    /// of a public input.
+    pub fn specialize(&self, fixed: &[(usize, E::Fr)]) -> R1CSResult<Self> {
+        let remaining = crate::verifier::remaining_indices(self.gamma_abc_g1.len(), fixed)?;
+        let (bases, scalars): (Vec<_>, Vec<_>) = fixed
+            .iter()
+            .map(|&(index, value)| (self.gamma_abc_g1[index + 1], value.into_repr()))
+            .unzip();
+        let g_ic = self.gamma_abc_g1[0].into_projective()
+            + VariableBaseMSM::multi_scalar_mul(&bases, &scalars);
+
+        let mut gamma_abc_g1 = Vec::with_capacity(remaining.len() + 1);
+        gamma_abc_g1.push(g_ic.into_affine());
+        gamma_abc_g1.extend(remaining.iter().map(|&index| self.gamma_abc_g1[index + 1]));
+        Ok(Self {
+            alpha_g1: self.alpha_g1,
+            beta_g2: self.beta_g2,
+            gamma_g2: self.gamma_g2,
+            delta_g2: self.delta_g2,
+            gamma_abc_g1,
+            alpha_g1_beta_g2: self.alpha_g1_beta_g2,
+            zt_delta_g1: self.zt_delta_g1,
+        })
+    }
+}
+
 impl<E: PairingEngine> Default for VerifyingKey<E> {
     fn default() -> Self {
         Self {
@@ -95,12 +130,55 @@ impl<E: PairingEngine> Default for VerifyingKey<E> {
 
 /// Preprocessed verification key parameters that enable faster verification
 /// at the expense of larger size in memory.
+///
+/// The key is serialized as its verification key, its elements prepared for
+/// pairings, and its tables of multiples of `delta * H` and of the elements
+/// of `gamma_abc_g1`, followed by a Blake2b checksum of their uncompressed
+/// serialization. The prepared elements and the tables are always
+/// uncompressed, so that reading the key computes none of them; this needs
+/// the prepared elements of the curve to implement [`SerializablePreparedG2`].
+///
+/// Deserialization fails if the checksum or the shape of the tables do not
+/// match. Checked deserialization also checks that the points of the
+/// verification key are in the prime order subgroup, and that the prepared
+/// elements and the tables are the ones derived from it, which takes about
+/// as long as preparing the key and needs no scalar multiplications.
+/// Unchecked deserialization relies on the checksum only, and is the fastest
+/// way to load a key from trusted storage.
 #[derive(Clone, Debug, PartialEq)]
 pub struct PreparedVerifyingKey<E: PairingEngine> {
     /// The unprepared verification key.
     pub vk: VerifyingKey<E>,
     /// The element `- gamma * H` in `E::G2`, prepared for use in pairings.
     pub gamma_g2_neg_pc: E::G2Prepared,
+    /// The element `delta * H` in `E::G2`, prepared for use in pairings.
+    pub delta_g2_pc: E::G2Prepared,
+    /// The window size of `delta_g2_table`.
+    pub delta_g2_window: usize,
+    /// The fixed-base table of multiples of `delta * H` in `E::G2`, for the
+    /// multiplications by the challenge of proofs.
+    pub delta_g2_table: Vec<Vec<E::G2Affine>>,
+    /// The window size of `gamma_abc_g1_tables`, which is 0 if the key has
+    /// no such tables.
+    pub gamma_abc_g1_window: usize,
+    /// The fixed-base tables of multiples of the elements of
+    /// `vk.gamma_abc_g1` after the first, for the multiplications by the
+    /// public inputs, or no tables.
+    pub gamma_abc_g1_tables: Vec<Vec<Vec<E::G1Affine>>>,
+}
+
+/// Public inputs of a statement prepared with [`prepare_fixed_inputs`], which
+/// [`prepare_remaining_inputs`] completes with the other public inputs.
+///
+/// [`prepare_fixed_inputs`]: crate::prepare_fixed_inputs
+/// [`prepare_remaining_inputs`]: crate::prepare_remaining_inputs
+#[derive(Clone, Debug, PartialEq)]
+pub struct PreparedFixedInputs<E: PairingEngine> {
+    /// The first element of `gamma_abc_g1` plus the products of the fixed
+    /// inputs with their elements of `gamma_abc_g1`.
+    pub g_ic: E::G1Projective,
+    /// The indices of the public inputs that are not fixed, in order.
+    pub remaining: Vec<usize>,
 }
 
 impl<E: PairingEngine> From<PreparedVerifyingKey<E>> for VerifyingKey<E> {
@@ -117,10 +195,7 @@ impl<E: PairingEngine> From<VerifyingKey<E>> for PreparedVerifyingKey<E> {
 
 impl<E: PairingEngine> Default for PreparedVerifyingKey<E> {
     fn default() -> Self {
-        Self {
-            vk: VerifyingKey::default(),
-            gamma_g2_neg_pc: E::G2Prepared::default(),
-        }
+        crate::prepare_verifying_key(&VerifyingKey::default())
     }
 }
 
@@ -132,6 +207,229 @@ impl<E: PairingEngine> ToBytes for PreparedVerifyingKey<E> {
     }
 }
 
+/// How the parts of a [`PreparedVerifyingKey`] or of a
+/// [`SparseProvingKey`](crate::SparseProvingKey) are serialized.
+#[derive(Clone, Copy)]
+pub(crate) enum Encoding {
+    Compressed,
+    Uncompressed,
+    Unchecked,
+}
+
+impl Encoding {
+    fn write<T: CanonicalSerialize, W: Write>(
+        self,
+        value: &T,
+        writer: W,
+    ) -> Result<(), SerializationError> {
+        match self {
+            Encoding::Compressed => value.serialize(writer),
+            Encoding::Uncompressed => value.serialize_uncompressed(writer),
+            Encoding::Unchecked => value.serialize_unchecked(writer),
+        }
+    }
+
+    pub(crate) fn read<T: CanonicalDeserialize, R: Read>(
+        self,
+        reader: R,
+    ) -> Result<T, SerializationError> {
+        match self {
+            Encoding::Compressed => T::deserialize(reader),
+            Encoding::Uncompressed => T::deserialize_uncompressed(reader),
+            Encoding::Unchecked => T::deserialize_unchecked(reader),
+        }
+    }
+}
+
+impl<E: PairingEngine> PreparedVerifyingKey<E> {
+    /// The largest window size of the tables that is read.
+    const MAX_WINDOW: usize = 20;
+
+    /// The checksum of the parts of the key that are serialized.
+    fn checksum(&self) -> [u8; 64]
+    where
+        E::G2Prepared: SerializablePreparedG2,
+    {
+        Fingerprint::new(b"BPR20 prepared verifying key")
+            .update(&self.vk)
+            .update(&Prepared(&self.gamma_g2_neg_pc))
+            .update(&Prepared(&self.delta_g2_pc))
+            .update(&self.delta_g2_window)
+            .update(&self.delta_g2_table)
+            .update(&self.gamma_abc_g1_window)
+            .update(&self.gamma_abc_g1_tables)
+            .finish()
+    }
+
+    /// The key prepared from `vk` with the window sizes of `self`.
+    fn derived(&self) -> Self {
+        let mut pvk = crate::prepare_verifying_key_with_window(&self.vk, self.delta_g2_window);
+        if self.gamma_abc_g1_window > 0 {
+            crate::add_input_tables(&mut pvk, self.gamma_abc_g1_window);
+        }
+        pvk
+    }
+
+    /// Returns `true` if `delta_g2_table` and `gamma_abc_g1_tables` have the
+    /// shape of tables of multiples of `delta * H` and of the elements of
+    /// `vk.gamma_abc_g1` after the first, with their window sizes.
+    fn has_valid_tables(&self) -> bool {
+        self.has_valid_input_tables()
+            && Self::is_table_of(
+                &self.delta_g2_table,
+                self.delta_g2_window,
+                &self.vk.delta_g2,
+            )
+    }
+
+    /// Returns `true` if `gamma_abc_g1_tables` has no tables, or has the
+    /// shape of tables of multiples of the elements of `vk.gamma_abc_g1`
+    /// after the first with the window size `gamma_abc_g1_window`.
+    pub(crate) fn has_valid_input_tables(&self) -> bool {
+        let window = self.gamma_abc_g1_window;
+        if window == 0 || self.gamma_abc_g1_tables.is_empty() {
+            return self.gamma_abc_g1_tables.is_empty();
+        }
+        self.gamma_abc_g1_tables.len() + 1 == self.vk.gamma_abc_g1.len()
+            && self
+                .gamma_abc_g1_tables
+                .iter()
+                .zip(&self.vk.gamma_abc_g1[1..])
+                .all(|(table, base)| Self::is_table_of(table, window, base))
+    }
+
+    /// Returns `true` if `table` has the shape of a table of multiples of
+    /// `base` with the window size `window`.
+    fn is_table_of<G: AffineCurve>(table: &[Vec<G>], window: usize, base: &G) -> bool {
+        if window == 0 || window > Self::MAX_WINDOW {
+            return false;
+        }
+        let scalar_size = E::Fr::size_in_bits();
+        let outerc = (scalar_size + window - 1) / window;
+        table.len() == outerc
+            && table.iter().all(|row| row.len() == 1 << window)
+            && table[0][0].is_zero()
+            && table[0][1] == *base
+    }
+
+    fn write<W: Write>(&self, mut writer: W, encoding: Encoding) -> Result<(), SerializationError>
+    where
+        E::G2Prepared: SerializablePreparedG2,
+    {
+        encoding.write(&self.vk, &mut writer)?;
+        self.gamma_g2_neg_pc.serialize_prepared(&mut writer)?;
+        self.delta_g2_pc.serialize_prepared(&mut writer)?;
+        encoding.write(&self.delta_g2_window, &mut writer)?;
+        self.delta_g2_table.serialize_uncompressed(&mut writer)?;
+        encoding.write(&self.gamma_abc_g1_window, &mut writer)?;
+        self.gamma_abc_g1_tables
+            .serialize_uncompressed(&mut writer)?;
+        writer.write_all(&self.checksum())?;
+        Ok(())
+    }
+
+    fn read<R: Read>(mut reader: R, encoding: Encoding) -> Result<Self, SerializationError>
+    where
+        E::G2Prepared: SerializablePreparedG2,
+    {
+        // Only the verification key is checked as it is read. A checked read
+        // compares the rest with the elements derived from it below, which is
+        // much faster than checking that each point of the tables is in the
+        // prime order subgroup.
+        let vk: VerifyingKey<E> = encoding.read(&mut reader)?;
+        let gamma_g2_neg_pc = E::G2Prepared::deserialize_prepared_unchecked(&mut reader)?;
+        let delta_g2_pc = E::G2Prepared::deserialize_prepared_unchecked(&mut reader)?;
+        let delta_g2_window: usize = encoding.read(&mut reader)?;
+        if delta_g2_window == 0 || delta_g2_window > Self::MAX_WINDOW {
+            return Err(SerializationError::InvalidData);
+        }
+        let delta_g2_table: Vec<Vec<E::G2Affine>> = Encoding::Unchecked.read(&mut reader)?;
+        let gamma_abc_g1_window: usize = encoding.read(&mut reader)?;
+        if gamma_abc_g1_window > Self::MAX_WINDOW {
+            return Err(SerializationError::InvalidData);
+        }
+        let gamma_abc_g1_tables: Vec<Vec<Vec<E::G1Affine>>> =
+            Encoding::Unchecked.read(&mut reader)?;
+        let mut checksum = [0u8; 64];
+        reader.read_exact(&mut checksum)?;
+
+        let pvk = Self {
+            vk,
+            gamma_g2_neg_pc,
+            delta_g2_pc,
+            delta_g2_window,
+            delta_g2_table,
+            gamma_abc_g1_window,
+            gamma_abc_g1_tables,
+        };
+        if checksum != pvk.checksum() || !pvk.has_valid_tables() {
+            return Err(SerializationError::InvalidData);
+        }
+        // The prepared elements are compared through the checksum, which
+        // covers their serialization.
+        if !matches!(encoding, Encoding::Unchecked) && checksum != pvk.derived().checksum() {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(pvk)
+    }
+}
+
+impl<E: PairingEngine> CanonicalSerialize for PreparedVerifyingKey<E>
+where
+    E::G2Prepared: SerializablePreparedG2,
+{
+    fn serialize<W: Write>(&self, writer: W) -> Result<(), SerializationError> {
+        self.write(writer, Encoding::Compressed)
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.vk.serialized_size()
+            + self.gamma_g2_neg_pc.prepared_size()
+            + self.delta_g2_pc.prepared_size()
+            + self.delta_g2_window.serialized_size()
+            + self.delta_g2_table.uncompressed_size()
+            + self.gamma_abc_g1_window.serialized_size()
+            + self.gamma_abc_g1_tables.uncompressed_size()
+            + 64
+    }
+
+    fn serialize_uncompressed<W: Write>(&self, writer: W) -> Result<(), SerializationError> {
+        self.write(writer, Encoding::Uncompressed)
+    }
+
+    fn serialize_unchecked<W: Write>(&self, writer: W) -> Result<(), SerializationError> {
+        self.write(writer, Encoding::Unchecked)
+    }
+
+    fn uncompressed_size(&self) -> usize {
+        self.vk.uncompressed_size()
+            + self.gamma_g2_neg_pc.prepared_size()
+            + self.delta_g2_pc.prepared_size()
+            + self.delta_g2_window.uncompressed_size()
+            + self.delta_g2_table.uncompressed_size()
+            + self.gamma_abc_g1_window.uncompressed_size()
+            + self.gamma_abc_g1_tables.uncompressed_size()
+            + 64
+    }
+}
+
+impl<E: PairingEngine> CanonicalDeserialize for PreparedVerifyingKey<E>
+where
+    E::G2Prepared: SerializablePreparedG2,
+{
+    fn deserialize<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::read(reader, Encoding::Compressed)
+    }
+
+    fn deserialize_uncompressed<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::read(reader, Encoding::Uncompressed)
+    }
+
+    fn deserialize_unchecked<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::read(reader, Encoding::Unchecked)
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -0,0 +1,412 @@
+use crate::{
+    checkpoint::Fingerprint,
+    chunked::ChunkedProvingKey,
+    msm::MsmBackend,
+    prover::{G1Query, MsmScalars, ProvingKeySource},
+    Error, VerifyingKey,
+};
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::PrimeField;
+use ark_poly::{EvaluationDomain, GeneralEvaluationDomain};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::{cfg_into_iter, cfg_iter_mut, Zero};
+use std::{
+    fmt::Display,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// The version of the wire protocol, which a worker sends when it connects.
+const PROTOCOL_VERSION: u64 = 2;
+
+/// The tag of a request for an MSM over a range of a query.
+const MSM: u8 = 0;
+/// The tag of a request for [`ProvingKeySource::evaluate_on_coset`].
+const EVALUATE_ON_COSET: u8 = 1;
+/// The tag of a request for [`ProvingKeySource::interpolate_on_coset`].
+const INTERPOLATE_ON_COSET: u8 = 2;
+
+/// The tag of a response that holds a result.
+const OK: u8 = 0;
+/// The tag of a response that holds an error message.
+const ERR: u8 = 1;
+
+/// A proving key source that splits the MSMs and FFTs of the prover across
+/// worker processes, for creating proofs with a
+/// [`ProverContext`](crate::ProverContext).
+///
+/// Each MSM is split into one contiguous range of its query per worker. A
+/// worker is sent only the scalars of its range, reads the range from its
+/// own copy of the chunked proving key and returns the partial sum, and the
+/// coordinator adds up the partial sums. The FFTs of the witness map are
+/// handed out one polynomial per worker. Workers run [`serve`], for example
+/// in the `bpr20-worker` binary that the `worker` feature builds.
+///
+/// The [`MsmBackend`] of the prover context is not used, since the MSMs run
+/// on the workers.
+///
+/// The connections are neither encrypted nor authenticated, and the workers
+/// are sent the witness, so they are only for workers on trusted links such
+/// as loopback.
+pub struct Coordinator<E: PairingEngine> {
+    pk: ChunkedProvingKey<E>,
+    workers: Vec<Mutex<TcpStream>>,
+}
+
+impl<E: PairingEngine> Coordinator<E> {
+    /// Create a coordinator for the proving key `pk` from connections to
+    /// `workers`, checking that they serve the same proving key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `workers` is empty.
+    pub fn new(pk: ChunkedProvingKey<E>, workers: Vec<TcpStream>) -> Result<Self, Error> {
+        assert!(
+            !workers.is_empty(),
+            "a coordinator needs at least one worker"
+        );
+        let workers = workers
+            .into_iter()
+            .map(|mut stream| {
+                stream.set_nodelay(true).map_err(SerializationError::from)?;
+                let hello = read_frame(&mut stream)?
+                    .ok_or_else(|| Error::Worker("connection closed".into()))?;
+                let mut reader = &hello[..];
+                let version = u64::deserialize(&mut reader)?;
+                if version != PROTOCOL_VERSION {
+                    return Err(Error::Worker(format!(
+                        "unsupported protocol version {}",
+                        version
+                    )));
+                }
+                let mut digest = [0u8; 64];
+                reader
+                    .read_exact(&mut digest)
+                    .map_err(SerializationError::from)?;
+                if digest != key_digest(&pk) {
+                    return Err(Error::Worker(
+                        "worker serves a different proving key".into(),
+                    ));
+                }
+                Ok(Mutex::new(stream))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Self { pk, workers })
+    }
+
+    /// Accept connections from `num_workers` workers on `listener`, and
+    /// create a coordinator from them with [`Coordinator::new`].
+    pub fn accept(
+        pk: ChunkedProvingKey<E>,
+        listener: &TcpListener,
+        num_workers: usize,
+    ) -> Result<Self, Error> {
+        let workers = (0..num_workers)
+            .map(|_| listener.accept().map(|(stream, _)| stream))
+            .collect::<io::Result<Vec<_>>>()
+            .map_err(SerializationError::from)?;
+        Self::new(pk, workers)
+    }
+
+    /// The number of workers.
+    pub fn num_workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Sends `request` to the `worker`-th worker and returns its result.
+    fn call<T: CanonicalDeserialize>(&self, worker: usize, request: &[u8]) -> Result<T, Error> {
+        let mut stream = self.workers[worker]
+            .lock()
+            .map_err(|_| Error::Worker("connection poisoned".into()))?;
+        write_frame(&mut stream, request)?;
+        let response =
+            read_frame(&mut stream)?.ok_or_else(|| Error::Worker("connection closed".into()))?;
+        match response.split_first() {
+            Some((&OK, mut result)) => Ok(T::deserialize_uncompressed(&mut result)?),
+            Some((&ERR, message)) => {
+                Err(Error::Worker(String::from_utf8_lossy(message).into_owned()))
+            },
+            _ => Err(SerializationError::InvalidData.into()),
+        }
+    }
+
+    /// Computes `sum_i scalars[i] * query[i]` over the `query`-th query,
+    /// with one range of the query per worker.
+    fn msm<G: AffineCurve<ScalarField = E::Fr>>(
+        &self,
+        query: u8,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> Result<G::Projective, Error> {
+        let start = scalars.start();
+        let len = scalars.len();
+        if start >= len {
+            return Ok(G::Projective::zero());
+        }
+        let num_workers = self.workers.len();
+        let shard_size = (len - start + num_workers - 1) / num_workers;
+
+        let partial_sums = cfg_into_iter!(0..self.workers.len())
+            .map(|worker| {
+                let lo = core::cmp::min(start + worker * shard_size, len);
+                let hi = core::cmp::min(lo + shard_size, len);
+                if lo == hi {
+                    return Ok(G::Projective::zero());
+                }
+                let mut request = vec![MSM];
+                query.serialize(&mut request)?;
+                (lo as u64).serialize(&mut request)?;
+                scalars.to_repr(lo..hi).serialize(&mut request)?;
+                Ok(self.call::<G>(worker, &request)?.into_projective())
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(partial_sums.into_iter().sum())
+    }
+
+    /// Sends each of `evaluations` with the request `tag` to a worker, and
+    /// replaces it with the result.
+    fn fft(
+        &self,
+        tag: u8,
+        domain: &GeneralEvaluationDomain<E::Fr>,
+        evaluations: &mut [Vec<E::Fr>],
+    ) -> Result<(), Error> {
+        let num_workers = self.workers.len();
+        cfg_iter_mut!(evaluations)
+            .enumerate()
+            .try_for_each(|(i, evaluations)| {
+                let mut request = vec![tag];
+                (domain.size() as u64).serialize(&mut request)?;
+                evaluations.serialize(&mut request)?;
+                let result = self.call::<Vec<E::Fr>>(i % num_workers, &request)?;
+                if result.len() != evaluations.len() {
+                    return Err(SerializationError::InvalidData.into());
+                }
+                *evaluations = result;
+                Ok(())
+            })
+    }
+}
+
+impl<E: PairingEngine> ProvingKeySource<E> for Coordinator<E> {
+    type Error = Error;
+
+    fn vk(&self) -> &VerifyingKey<E> {
+        self.pk.vk()
+    }
+
+    fn beta_g1(&self) -> E::G1Affine {
+        self.pk.beta_g1()
+    }
+
+    fn delta_g1(&self) -> E::G1Affine {
+        self.pk.delta_g1()
+    }
+
+    fn num_variables(&self) -> usize {
+        self.pk.num_variables()
+    }
+
+    fn msm_g1<B: MsmBackend>(
+        &self,
+        _: &B,
+        query: G1Query,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> Result<E::G1Projective, Error> {
+        let query = match query {
+            G1Query::A => 0,
+            G1Query::BG1 => 1,
+            G1Query::H => 3,
+            G1Query::L => 4,
+        };
+        self.msm::<E::G1Affine>(query, scalars)
+    }
+
+    fn msm_b_g2<B: MsmBackend>(
+        &self,
+        _: &B,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> Result<E::G2Projective, Error> {
+        self.msm::<E::G2Affine>(2, scalars)
+    }
+
+    fn evaluate_on_coset(
+        &self,
+        domain: &GeneralEvaluationDomain<E::Fr>,
+        evaluations: &mut [Vec<E::Fr>],
+    ) -> Result<(), Error> {
+        self.fft(EVALUATE_ON_COSET, domain, evaluations)
+    }
+
+    fn interpolate_on_coset(
+        &self,
+        domain: &GeneralEvaluationDomain<E::Fr>,
+        evaluations: &mut Vec<E::Fr>,
+    ) -> Result<(), Error> {
+        self.fft(
+            INTERPOLATE_ON_COSET,
+            domain,
+            core::slice::from_mut(evaluations),
+        )
+    }
+}
+
+/// Serve the requests of a [`Coordinator`] on `stream` until it closes the
+/// connection, computing them over `pk` with the MSMs on `backend`.
+///
+/// Every message is a little-endian `u64` length followed by that many
+/// bytes, whose fields are serialized with [`CanonicalSerialize`] and points
+/// uncompressed. On connecting, the worker sends the protocol version and the
+/// 64-byte Blake2b digest of the verifying key, `beta_g1`, `delta_g1` and
+/// number of variables of `pk`. A request is a tag byte followed by
+///
+/// - `0`: the index of a query in the order A, B in `G1`, B in `G2`, H and
+///   L as a `u8`, the index `start` of the first element of a range of that
+///   query as a `u64` and the bigints of the scalars for the range. The
+///   result is the sum of the scalars times the query elements from `start`
+///   on.
+/// - `1`: a domain size as a `u64` and evaluations over that domain. The
+///   result is the evaluations of the interpolated polynomial over the coset
+///   of the domain.
+/// - `2`: a domain size as a `u64` and evaluations over the coset of that
+///   domain. The result is the coefficients of the interpolated polynomial.
+///
+/// A response is the tag byte `0` followed by the result, or `1` followed by
+/// an error message in UTF-8.
+pub fn serve<E, S, B>(mut stream: TcpStream, pk: &S, backend: &B) -> Result<(), Error>
+where
+    E: PairingEngine,
+    S: ProvingKeySource<E>,
+    S::Error: Display,
+    B: MsmBackend,
+{
+    stream.set_nodelay(true).map_err(SerializationError::from)?;
+    let mut hello = Vec::new();
+    PROTOCOL_VERSION.serialize(&mut hello)?;
+    hello.extend_from_slice(&key_digest(pk));
+    write_frame(&mut stream, &hello)?;
+
+    while let Some(request) = read_frame(&mut stream)? {
+        let mut response = vec![OK];
+        if let Err(message) = handle_request::<E, S, B>(pk, backend, &request, &mut response) {
+            response = vec![ERR];
+            response.extend_from_slice(message.as_bytes());
+        }
+        write_frame(&mut stream, &response)?;
+    }
+
+    Ok(())
+}
+
+/// Appends the result of `request` to `response`, or describes why it could
+/// not be computed.
+fn handle_request<E, S, B>(
+    pk: &S,
+    backend: &B,
+    request: &[u8],
+    response: &mut Vec<u8>,
+) -> Result<(), String>
+where
+    E: PairingEngine,
+    S: ProvingKeySource<E>,
+    S::Error: Display,
+    B: MsmBackend,
+{
+    let invalid = |e: SerializationError| format!("invalid request: {}", e);
+    let (&tag, mut reader) = request.split_first().ok_or("empty request")?;
+    match tag {
+        MSM => {
+            let query = u8::deserialize(&mut reader).map_err(invalid)?;
+            let start = u64::deserialize(&mut reader).map_err(invalid)? as usize;
+            let scalars = Vec::<<E::Fr as PrimeField>::BigInt>::deserialize(&mut reader)
+                .map_err(invalid)?
+                .into_iter()
+                .map(E::Fr::from_repr)
+                .collect::<Option<Vec<_>>>()
+                .ok_or("scalar out of range")?;
+            let scalars = MsmScalars::Chunked {
+                parts: [&[], &scalars],
+                start,
+                chunk_size: usize::MAX,
+            };
+            let query = match query {
+                0 => Some(G1Query::A),
+                1 => Some(G1Query::BG1),
+                2 => None,
+                3 => Some(G1Query::H),
+                4 => Some(G1Query::L),
+                _ => return Err(format!("unknown query {}", query)),
+            };
+            match query {
+                Some(query) => pk
+                    .msm_g1(backend, query, scalars)
+                    .map_err(|e| e.to_string())?
+                    .into_affine()
+                    .serialize_uncompressed(response),
+                None => pk
+                    .msm_b_g2(backend, scalars)
+                    .map_err(|e| e.to_string())?
+                    .into_affine()
+                    .serialize_uncompressed(response),
+            }
+        },
+        EVALUATE_ON_COSET | INTERPOLATE_ON_COSET => {
+            let domain_size = u64::deserialize(&mut reader).map_err(invalid)? as usize;
+            let mut evaluations = Vec::<E::Fr>::deserialize(&mut reader).map_err(invalid)?;
+            let domain = GeneralEvaluationDomain::<E::Fr>::new(domain_size)
+                .filter(|domain| domain.size() == domain_size)
+                .filter(|_| evaluations.len() == domain_size)
+                .ok_or("invalid evaluation domain")?;
+            if tag == EVALUATE_ON_COSET {
+                pk.evaluate_on_coset(&domain, core::slice::from_mut(&mut evaluations))
+            } else {
+                pk.interpolate_on_coset(&domain, &mut evaluations)
+            }
+            .map_err(|e| e.to_string())?;
+            evaluations.serialize_uncompressed(response)
+        },
+        _ => return Err(format!("unknown request {}", tag)),
+    }
+    .map_err(|e| e.to_string())
+}
+
+/// The digest of the proving key `pk` that a worker sends when it connects,
+/// which covers the verifying key and so the setup the key belongs to.
+fn key_digest<E: PairingEngine, S: ProvingKeySource<E>>(pk: &S) -> [u8; 64] {
+    Fingerprint::new(b"BPR20 worker key")
+        .update(pk.vk())
+        .update(&pk.beta_g1())
+        .update(&pk.delta_g1())
+        .update(&(pk.num_variables() as u64))
+        .finish()
+}
+
+/// Writes `frame` prefixed with its length.
+fn write_frame(stream: &mut TcpStream, frame: &[u8]) -> Result<(), SerializationError> {
+    (frame.len() as u64).serialize(&mut *stream)?;
+    stream.write_all(frame)?;
+    Ok(())
+}
+
+/// Reads a frame written by [`write_frame`], or `None` if the connection was
+/// closed before it.
+fn read_frame(stream: &mut TcpStream) -> Result<Option<Vec<u8>>, SerializationError> {
+    let mut len = [0u8; 8];
+    match stream.read_exact(&mut len) {
+        Ok(()) => {},
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u64::from_le_bytes(len);
+    let mut frame = Vec::new();
+    (&mut *stream).take(len).read_to_end(&mut frame)?;
+    if frame.len() as u64 != len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(Some(frame))
+}
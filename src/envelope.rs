@@ -0,0 +1,231 @@
+use crate::container::{curve_id, Hex};
+use crate::{verify_proof, Error, PreparedVerifyingKey, Proof, VerifyingKey};
+use ark_ec::PairingEngine;
+use ark_ff::Zero;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::vec::Vec;
+use core::{convert::TryInto, fmt};
+
+/// The magic bytes at the start of a proof envelope.
+const MAGIC: [u8; 8] = *b"BPR20PRF";
+
+/// The version of the envelope format that is written, and the only one
+/// that is read.
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// The length of the header of an envelope, up to the proof.
+const HEADER_LEN: usize = 8 + 4 + 16 + 64;
+
+/// The length of the fixed-size encoding of a proof over BLS12-381.
+pub const BLS12_381_PROOF_LEN: usize = 2 * 48 + 2 * 96;
+
+/// The length of the fixed-size encoding of a proof over BLS12-377.
+pub const BLS12_377_PROOF_LEN: usize = 2 * 48 + 2 * 96;
+
+/// The length of the fixed-size encoding of a proof over CP6-782.
+pub const CP6_782_PROOF_LEN: usize = 2 * 98 + 2 * 294;
+
+/// The length of the fixed-size encoding of a proof over MNT4-298.
+pub const MNT4_298_PROOF_LEN: usize = 2 * 38 + 2 * 76;
+
+/// The length of the fixed-size encoding of a proof over MNT6-298.
+pub const MNT6_298_PROOF_LEN: usize = 2 * 38 + 2 * 114;
+
+/// The length of the fixed-size encoding of a proof over MNT4-753.
+pub const MNT4_753_PROOF_LEN: usize = 2 * 95 + 2 * 190;
+
+/// The length of the fixed-size encoding of a proof over MNT6-753.
+pub const MNT6_753_PROOF_LEN: usize = 2 * 95 + 2 * 285;
+
+/// The reasons for which a proof envelope is rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EnvelopeError {
+    /// The data does not start with the magic bytes of a proof envelope.
+    NotAnEnvelope,
+    /// The envelope was written in a version of the format that is not
+    /// supported.
+    UnsupportedVersion(u32),
+    /// The proof is over another curve than the one it is read for.
+    CurveMismatch {
+        /// The identifier of the curve the envelope is read for.
+        expected: [u8; 16],
+        /// The identifier of the curve of the proof.
+        found: [u8; 16],
+    },
+    /// The proof is for another verifying key than the one it is checked
+    /// against.
+    VerifyingKeyMismatch,
+    /// The proof or the public inputs cannot be decoded, or are followed by
+    /// more data.
+    Malformed,
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::NotAnEnvelope => write!(f, "not a BPR20 proof envelope"),
+            EnvelopeError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported proof envelope version {} (expected {})",
+                version, ENVELOPE_VERSION
+            ),
+            EnvelopeError::CurveMismatch { expected, found } => write!(
+                f,
+                "the proof is over the curve {}, not {}",
+                Hex(found),
+                Hex(expected)
+            ),
+            EnvelopeError::VerifyingKeyMismatch => {
+                write!(f, "the proof is for another verifying key")
+            },
+            EnvelopeError::Malformed => write!(f, "the proof envelope is malformed"),
+        }
+    }
+}
+
+/// A proof together with its public inputs and what identifies the curve
+/// and the verifying key it is for, so that it can be sent and stored on
+/// its own.
+///
+/// The encoding of [`to_bytes`](Self::to_bytes) is the magic bytes
+/// `BPR20PRF`, the format version [`ENVELOPE_VERSION`] as a little-endian
+/// `u32`, the 16-byte [`curve_id`] of the curve, the 64-byte
+/// [`VerifyingKey::fingerprint`] of the verifying key, the compressed proof,
+/// the number of public inputs as a little-endian `u32`, and the compressed
+/// public inputs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProofEnvelope<E: PairingEngine> {
+    /// The [`VerifyingKey::fingerprint`] of the key the proof is for.
+    pub vk_fingerprint: [u8; 64],
+    /// The proof.
+    pub proof: Proof<E>,
+    /// The public inputs of the proof, without the leading `one`.
+    pub public_inputs: Vec<E::Fr>,
+}
+
+impl<E: PairingEngine> ProofEnvelope<E> {
+    /// The envelope of `proof` for the public inputs `public_inputs` and the
+    /// verifying key `vk`.
+    pub fn new(vk: &VerifyingKey<E>, proof: Proof<E>, public_inputs: Vec<E::Fr>) -> Self {
+        Self {
+            vk_fingerprint: vk.fingerprint(),
+            proof,
+            public_inputs,
+        }
+    }
+
+    /// Encodes the envelope.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let input_len = E::Fr::zero().serialized_size();
+        let mut bytes = Vec::with_capacity(
+            HEADER_LEN + self.proof.serialized_size() + 4 + self.public_inputs.len() * input_len,
+        );
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&ENVELOPE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&curve_id::<E>());
+        bytes.extend_from_slice(&self.vk_fingerprint);
+        self.proof
+            .serialize(&mut bytes)
+            .expect("writing to a vector does not fail");
+        bytes.extend_from_slice(&(self.public_inputs.len() as u32).to_le_bytes());
+        for input in self.public_inputs.iter() {
+            input
+                .serialize(&mut bytes)
+                .expect("writing to a vector does not fail");
+        }
+        bytes
+    }
+
+    /// Decodes an envelope for a proof over `E` for the verifying key `vk`.
+    ///
+    /// The curve and the verifying key are checked before the proof is
+    /// decoded, and the elements of the proof are checked to be in their
+    /// groups, so a proof that decodes can be verified directly.
+    pub fn from_bytes(bytes: &[u8], vk: &VerifyingKey<E>) -> Result<Self, Error> {
+        if bytes.len() < 8 || bytes[..8] != MAGIC {
+            return Err(EnvelopeError::NotAnEnvelope.into());
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(EnvelopeError::Malformed.into());
+        }
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version != ENVELOPE_VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(version).into());
+        }
+        let expected = curve_id::<E>();
+        let found: [u8; 16] = bytes[12..28].try_into().unwrap();
+        if found != expected {
+            return Err(EnvelopeError::CurveMismatch { expected, found }.into());
+        }
+        let mut vk_fingerprint = [0u8; 64];
+        vk_fingerprint.copy_from_slice(&bytes[28..HEADER_LEN]);
+        if vk_fingerprint != vk.fingerprint() {
+            return Err(EnvelopeError::VerifyingKeyMismatch.into());
+        }
+
+        let mut reader = &bytes[HEADER_LEN..];
+        let proof = Proof::deserialize(&mut reader).map_err(|_| EnvelopeError::Malformed)?;
+        let mut num_inputs = [0u8; 4];
+        if reader.len() < 4 {
+            return Err(EnvelopeError::Malformed.into());
+        }
+        num_inputs.copy_from_slice(&reader[..4]);
+        reader = &reader[4..];
+        // The number of inputs is checked against the remaining data before
+        // anything is allocated for them.
+        let num_inputs = u32::from_le_bytes(num_inputs) as usize;
+        if reader.len() != num_inputs * E::Fr::zero().serialized_size() {
+            return Err(EnvelopeError::Malformed.into());
+        }
+        let public_inputs = (0..num_inputs)
+            .map(|_| E::Fr::deserialize(&mut reader))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| EnvelopeError::Malformed)?;
+
+        Ok(Self {
+            vk_fingerprint,
+            proof,
+            public_inputs,
+        })
+    }
+
+    /// Verifies the proof for its public inputs with `pvk`, failing without
+    /// running any pairing if the proof is for another verifying key.
+    pub fn verify(&self, pvk: &PreparedVerifyingKey<E>) -> Result<bool, Error> {
+        if self.vk_fingerprint != pvk.vk.fingerprint() {
+            return Err(EnvelopeError::VerifyingKeyMismatch.into());
+        }
+        Ok(verify_proof(pvk, &self.proof, &self.public_inputs)?)
+    }
+}
+
+impl<E: PairingEngine> Proof<E> {
+    /// The length of the fixed-size encoding of proofs over `E`, which is
+    /// the length of their compressed serialization.
+    pub fn fixed_size() -> usize {
+        2 * E::G1Affine::zero().serialized_size() + 2 * E::G2Affine::zero().serialized_size()
+    }
+
+    /// The fixed-size encoding of the proof, which is its compressed
+    /// serialization. `N` is [`fixed_size`](Self::fixed_size), such as
+    /// [`BLS12_381_PROOF_LEN`] for proofs over BLS12-381. Fails if `N` is
+    /// not the fixed size of proofs over `E`.
+    pub fn to_fixed_bytes<const N: usize>(&self) -> Result<[u8; N], SerializationError> {
+        if N != Self::fixed_size() {
+            return Err(SerializationError::InvalidData);
+        }
+        let mut bytes = [0u8; N];
+        self.serialize(&mut bytes[..])?;
+        Ok(bytes)
+    }
+
+    /// Decodes a proof from its fixed-size encoding, checking that its
+    /// elements are in their groups. Fails if `N` is not the fixed size of
+    /// proofs over `E`.
+    pub fn from_fixed_bytes<const N: usize>(bytes: &[u8; N]) -> Result<Self, SerializationError> {
+        if N != Self::fixed_size() {
+            return Err(SerializationError::InvalidData);
+        }
+        Self::deserialize(&bytes[..])
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{container::ContainerError, envelope::EnvelopeError};
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::SerializationError;
+use ark_std::string::String;
+use core::fmt;
+
+/// An error of the BPR20 zkSNARK, for operations that can fail for reasons
+/// other than synthesis, such as reading a proving key from disk.
+#[derive(Debug)]
+pub enum Error {
+    /// An error during synthesis, proving or verification.
+    Synthesis(SynthesisError),
+    /// An error while reading or writing keys.
+    Serialization(SerializationError),
+    /// An error reported by a worker process computing part of a proof.
+    Worker(String),
+    /// A key container that cannot be read.
+    Container(ContainerError),
+    /// A proof envelope that is rejected.
+    Envelope(EnvelopeError),
+    /// The operation was cancelled through its [`crate::CancellationToken`].
+    Cancelled,
+    /// A committed [`crate::WitnessPrefix`] that is longer than the witness
+    /// or differs from its start.
+    WitnessPrefixMismatch,
+}
+
+impl From<SynthesisError> for Error {
+    fn from(e: SynthesisError) -> Self {
+        Error::Synthesis(e)
+    }
+}
+
+impl From<SerializationError> for Error {
+    fn from(e: SerializationError) -> Self {
+        Error::Serialization(e)
+    }
+}
+
+impl From<ContainerError> for Error {
+    fn from(e: ContainerError) -> Self {
+        Error::Container(e)
+    }
+}
+
+impl From<EnvelopeError> for Error {
+    fn from(e: EnvelopeError) -> Self {
+        Error::Envelope(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Synthesis(e) => write!(f, "synthesis error: {}", e),
+            Error::Serialization(e) => write!(f, "serialization error: {}", e),
+            Error::Worker(e) => write!(f, "worker error: {}", e),
+            Error::Container(e) => write!(f, "key container error: {}", e),
+            Error::Envelope(e) => write!(f, "proof envelope error: {}", e),
+            Error::Cancelled => write!(f, "cancelled"),
+            Error::WitnessPrefixMismatch => {
+                write!(f, "witness prefix does not match the witness")
+            },
+        }
+    }
+}
+
+impl ark_std::error::Error for Error {}
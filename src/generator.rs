@@ -1,13 +1,24 @@
-use crate::{r1cs_to_qap::R1CStoQAP, ProvingKey, Vec, VerifyingKey};
-use ark_ec::{msm::FixedBaseMSM, PairingEngine, ProjectiveCurve};
-use ark_ff::{Field, PrimeField, UniformRand, Zero};
+#[cfg(feature = "std")]
+use crate::{checkpoint::Checkpoints, stats::Recorder, SetupStats};
+use crate::{
+    checkpoint::Fingerprint,
+    msm::{ArkworksMsm, MsmBackend},
+    progress::{Phase, Query, RunOptions, Stages, Unobserved},
+    r1cs_to_qap::R1CStoQAP,
+    stats::CircuitStats,
+    Error, ProvingKey, Vec, VerifyingKey,
+};
+use ark_ec::{PairingEngine, ProjectiveCurve};
+use ark_ff::{Field, PrimeField, UniformRand};
 use ark_poly::{EvaluationDomain, GeneralEvaluationDomain};
 use ark_relations::r1cs::{
-    ConstraintSynthesizer, ConstraintSystem, OptimizationGoal, Result as R1CSResult,
-    SynthesisError, SynthesisMode,
+    ConstraintSynthesizer, ConstraintSystem, ConstraintSystemRef, OptimizationGoal,
+    Result as R1CSResult, SynthesisError, SynthesisMode,
 };
 use ark_std::rand::Rng;
-use ark_std::{cfg_into_iter, cfg_iter};
+use ark_std::{cfg_chunks_mut, cfg_iter, ops::Range, vec};
+#[cfg(feature = "std")]
+use std::time::Instant;
 
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
@@ -42,6 +53,7 @@ where
 }
 
 /// Create parameters for a circuit, given some toxic waste and group generators
+#[inline]
 pub fn generate_parameters<E, C, R>(
     circuit: C,
     alpha: E::Fr,
@@ -57,48 +69,252 @@ where
     C: ConstraintSynthesizer<E::Fr>,
     R: Rng,
 {
-    type D<F> = GeneralEvaluationDomain<F>;
+    generate_parameters_with_backend::<E, C, R, _>(
+        circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        rng,
+        &ArkworksMsm,
+    )
+}
 
-    let setup_time = start_timer!(|| "BPR20::Generator");
-    let cs = ConstraintSystem::new_ref();
-    cs.set_optimization_goal(OptimizationGoal::Constraints);
-    cs.set_mode(SynthesisMode::Setup);
+/// Create parameters for a circuit, given some toxic waste and group
+/// generators, running the fixed-base MSMs on `backend`.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_parameters_with_backend<E, C, R, B>(
+    circuit: C,
+    alpha: E::Fr,
+    beta: E::Fr,
+    gamma: E::Fr,
+    delta: E::Fr,
+    g1_generator: E::G1Projective,
+    g2_generator: E::G2Projective,
+    rng: &mut R,
+    backend: &B,
+) -> R1CSResult<ProvingKey<E>>
+where
+    E: PairingEngine,
+    C: ConstraintSynthesizer<E::Fr>,
+    R: Rng,
+    B: MsmBackend,
+{
+    generate_parameters_in_stages::<E, C, R, B, _>(
+        circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        rng,
+        backend,
+        Unobserved,
+    )
+    .map(|(pk, _)| pk)
+}
 
-    // Synthesize the circuit.
-    let synthesis_time = start_timer!(|| "Constraint synthesis");
-    circuit.generate_constraints(cs.clone())?;
-    end_timer!(synthesis_time);
+/// Create parameters for a circuit, given some toxic waste and group
+/// generators, saving each query to `checkpoints` as it is computed.
+///
+/// A run resumes from the queries that an interrupted run with the same
+/// inputs and RNG state saved to `checkpoints`, and creates the same
+/// parameters as an uninterrupted run. The saved queries are deleted once
+/// the run succeeds.
+///
+/// There is no checkpoint after the QAP evaluation. Its field elements are
+/// evaluations at the toxic waste `t`, from which `t` can be recovered, so
+/// saving them would leak the toxic waste to disk. A resumed run evaluates
+/// the QAP again, which is cheap next to computing the queries.
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn generate_parameters_with_checkpoints<E, C, R>(
+    circuit: C,
+    alpha: E::Fr,
+    beta: E::Fr,
+    gamma: E::Fr,
+    delta: E::Fr,
+    g1_generator: E::G1Projective,
+    g2_generator: E::G2Projective,
+    rng: &mut R,
+    checkpoints: &Checkpoints,
+) -> Result<ProvingKey<E>, Error>
+where
+    E: PairingEngine,
+    C: ConstraintSynthesizer<E::Fr>,
+    R: Rng,
+{
+    generate_parameters_with_options(
+        circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        rng,
+        RunOptions::new().with_checkpoints(checkpoints),
+    )
+}
 
-    let lc_time = start_timer!(|| "Inlining LCs");
-    cs.finalize();
-    end_timer!(lc_time);
+/// Create parameters for a circuit, given some toxic waste and group
+/// generators, observing the run with `options`.
+///
+/// With a [`Progress`](crate::Progress), the run reports synthesis, the
+/// QAP evaluation and the fixed-base MSM of each query. With a
+/// [`CancellationToken`](crate::CancellationToken), it returns
+/// [`Error::Cancelled`] soon after the token is cancelled. The parameters
+/// are the same as those of an unobserved run.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_parameters_with_options<E, C, R>(
+    circuit: C,
+    alpha: E::Fr,
+    beta: E::Fr,
+    gamma: E::Fr,
+    delta: E::Fr,
+    g1_generator: E::G1Projective,
+    g2_generator: E::G2Projective,
+    rng: &mut R,
+    options: RunOptions<'_>,
+) -> Result<ProvingKey<E>, Error>
+where
+    E: PairingEngine,
+    C: ConstraintSynthesizer<E::Fr>,
+    R: Rng,
+{
+    generate_parameters_in_stages::<E, C, R, _, _>(
+        circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        rng,
+        &ArkworksMsm,
+        options.stages(),
+    )
+    .map(|(pk, _)| pk)
+}
 
-    ///////////////////////////////////////////////////////////////////////////
-    let domain_time = start_timer!(|| "Constructing evaluation domain");
+/// Create parameters for a circuit, given some toxic waste and group
+/// generators, together with statistics of the setup.
+///
+/// Each query is computed with its own fixed-base MSM, so that its duration
+/// is recorded. The parameters are the same as those of
+/// [`generate_parameters`].
+#[cfg(feature = "std")]
+#[allow(clippy::too_many_arguments)]
+pub fn generate_parameters_with_stats<E, C, R>(
+    circuit: C,
+    alpha: E::Fr,
+    beta: E::Fr,
+    gamma: E::Fr,
+    delta: E::Fr,
+    g1_generator: E::G1Projective,
+    g2_generator: E::G2Projective,
+    rng: &mut R,
+) -> Result<(ProvingKey<E>, SetupStats), Error>
+where
+    E: PairingEngine,
+    C: ConstraintSynthesizer<E::Fr>,
+    R: Rng,
+{
+    let start = Instant::now();
+    let recorder = Recorder::default();
+    let (pk, circuit) = generate_parameters_in_stages::<E, C, R, _, _>(
+        circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        rng,
+        &ArkworksMsm,
+        RunOptions::new().with_recorder(&recorder).stages(),
+    )?;
+    let total_duration = start.elapsed();
+
+    let msm_sizes = vec![
+        (Query::A, pk.a_query.len()),
+        (Query::BG1, pk.b_g1_query.len()),
+        (Query::BG2, pk.b_g2_query.len()),
+        (Query::H, pk.h_query.len()),
+        (Query::L, pk.l_query.len()),
+        (Query::GammaAbc, pk.vk.gamma_abc_g1.len()),
+    ];
+    let stats = SetupStats {
+        circuit,
+        msm_sizes,
+        durations: recorder.into_durations(),
+        total_duration,
+    };
 
-    let domain_size = cs.num_constraints() + cs.num_instance_variables();
-    let domain = D::new(domain_size).ok_or(SynthesisError::PolynomialDegreeTooLarge)?;
-    let t = domain.sample_element_outside_domain(rng);
+    Ok((pk, stats))
+}
 
-    end_timer!(domain_time);
-    ///////////////////////////////////////////////////////////////////////////
+/// Create parameters for a circuit as the stages `stages`, returning them
+/// together with the size of the circuit.
+#[allow(clippy::too_many_arguments)]
+fn generate_parameters_in_stages<E, C, R, B, St>(
+    circuit: C,
+    alpha: E::Fr,
+    beta: E::Fr,
+    gamma: E::Fr,
+    delta: E::Fr,
+    g1_generator: E::G1Projective,
+    g2_generator: E::G2Projective,
+    rng: &mut R,
+    backend: &B,
+    mut stages: St,
+) -> Result<(ProvingKey<E>, CircuitStats), St::Error>
+where
+    E: PairingEngine,
+    C: ConstraintSynthesizer<E::Fr>,
+    R: Rng,
+    B: MsmBackend,
+    St: Stages,
+{
+    type D<F> = GeneralEvaluationDomain<F>;
 
-    let reduction_time = start_timer!(|| "R1CS to QAP Instance Map with Evaluation");
+    let setup_time = start_phase!("BPR20::Generator");
+    stages.report(Phase::Synthesis, 0.0)?;
+    let (cs, domain, t) = synthesize_for_setup(circuit, rng)?;
+    stages.report(Phase::Synthesis, 1.0)?;
+
+    stages.begin(|| {
+        Fingerprint::new(b"BPR20 setup")
+            .update(&alpha)
+            .update(&beta)
+            .update(&gamma)
+            .update(&delta)
+            .update(&g1_generator.into_affine())
+            .update(&g2_generator.into_affine())
+            .update(&t)
+            .update_matrices(&cs.to_matrices().unwrap())
+            .finish()
+    });
+
+    let reduction_time = start_phase!("R1CS to QAP Instance Map with Evaluation");
     let num_instance_variables = cs.num_instance_variables();
-    let (a, b, c, zt, qap_num_variables, m_raw) =
-        R1CStoQAP::instance_map_with_evaluation::<E::Fr, D<E::Fr>>(cs, &t)?;
-    end_timer!(reduction_time);
-
-    // Compute query densities
-    let non_zero_a: usize = cfg_into_iter!(0..qap_num_variables)
-        .map(|i| usize::from(!a[i].is_zero()))
-        .sum();
-
-    let non_zero_b: usize = cfg_into_iter!(0..qap_num_variables)
-        .map(|i| usize::from(!b[i].is_zero()))
-        .sum();
-
-    let scalar_bits = E::Fr::size_in_bits();
+    let circuit_stats = CircuitStats {
+        num_constraints: cs.num_constraints(),
+        num_instance_variables,
+        num_witness_variables: cs.num_witness_variables(),
+        domain_size: domain.size(),
+    };
+    // The evaluations are derived from `t`, so they are never saved, and are
+    // computed again by resumed runs.
+    stages.report(Phase::Qap, 0.0)?;
+    let (a, b, c, ..) = R1CStoQAP::instance_map_with_evaluation::<E::Fr, D<E::Fr>>(cs, &t)?;
+    stages.report(Phase::Qap, 1.0)?;
+    let zt = domain.evaluate_vanishing_polynomial(t);
+    let m_raw = domain.size();
+    end_phase!(reduction_time);
 
     let gamma_inverse = gamma.inverse().ok_or(SynthesisError::UnexpectedIdentity)?;
     let delta_inverse = delta.inverse().ok_or(SynthesisError::UnexpectedIdentity)?;
@@ -109,122 +325,98 @@ where
         .map(|((a, b), c)| (beta * a + &(alpha * b) + c) * &gamma_inverse)
         .collect::<Vec<_>>();
 
-    let l = cfg_iter!(a)
-        .zip(&b)
-        .zip(&c)
+    // Only the witness part of the L-query is part of the proving key.
+    let l = cfg_iter!(a[num_instance_variables..])
+        .zip(&b[num_instance_variables..])
+        .zip(&c[num_instance_variables..])
         .map(|((a, b), c)| (beta * a + &(alpha * b) + c) * &delta_inverse)
         .collect::<Vec<_>>();
 
     drop(c);
 
-    // Compute B window table
-    let g2_time = start_timer!(|| "Compute G2 table");
-    let g2_window = FixedBaseMSM::get_mul_window_size(non_zero_b);
-    let g2_table =
-        FixedBaseMSM::get_window_table::<E::G2Projective>(scalar_bits, g2_window, g2_generator);
-    end_timer!(g2_time);
-
-    // Compute the B-query in G2
-    let b_g2_time = start_timer!(|| "Calculate B G2");
-    let b_g2_query =
-        FixedBaseMSM::multi_scalar_mul::<E::G2Projective>(scalar_bits, g2_window, &g2_table, &b);
-    drop(g2_table);
-    end_timer!(b_g2_time);
-
-    // Compute G window table
-    let g1_window_time = start_timer!(|| "Compute G1 window table");
-    let g1_window =
-        FixedBaseMSM::get_mul_window_size(non_zero_a + non_zero_b + qap_num_variables + m_raw + 1);
-    let g1_table =
-        FixedBaseMSM::get_window_table::<E::G1Projective>(scalar_bits, g1_window, g1_generator);
-    end_timer!(g1_window_time);
-
     // Generate the R1CS proving key
-    let proving_key_time = start_timer!(|| "Generate the R1CS proving key");
-
-
+    let proving_key_time = start_phase!("Generate the R1CS proving key");
 
     let alpha_g1 = g1_generator.mul(&alpha.into_repr());
     let beta_g1 = g1_generator.mul(&beta.into_repr());
     let beta_g2 = g2_generator.mul(&beta.into_repr());
     let delta_g1 = g1_generator.mul(&delta.into_repr());
     let delta_g2 = g2_generator.mul(&delta.into_repr());
+    let gamma_g2 = g2_generator.mul(&gamma.into_repr());
 
+    // The B-query in G2 is computed concurrently with the queries in G1.
+    let b_g2 = || {
+        let b_g2_time = start_phase!("Calculate B G2");
+        let b_g2_phase = Phase::Msm(Query::BG2);
+        let b_g2_query = stages.stage::<_, St::Error, _>(b_g2_phase, "b_g2_query", || {
+            fixed_base_msm_in_steps(&stages, b_g2_phase, backend, g2_generator, &b)
+        });
+        end_phase!(b_g2_time);
+        b_g2_query
+    };
 
-    // Compute the A-query
-    let a_time = start_timer!(|| "Calculate A");
-    let a_query =
-        FixedBaseMSM::multi_scalar_mul::<E::G1Projective>(scalar_bits, g1_window, &g1_table, &a);
-    drop(a);
-    end_timer!(a_time);
-
-    // Compute the B-query in G1
-    let b_g1_time = start_timer!(|| "Calculate B G1");
-    let b_g1_query =
-        FixedBaseMSM::multi_scalar_mul::<E::G1Projective>(scalar_bits, g1_window, &g1_table, &b);
-    drop(b);
-    end_timer!(b_g1_time);
-
-    // Compute the H-query
-    let h_time = start_timer!(|| "Calculate H");
-    let h_query = FixedBaseMSM::multi_scalar_mul::<E::G1Projective>(
-        scalar_bits,
-        g1_window,
-        &g1_table,
-        &cfg_into_iter!(0..m_raw - 1)
-            .map(|i| zt * &delta_inverse * &t.pow([i as u64]))
-            .collect::<Vec<_>>(),
-    );
-    let g1_zt_deltainverse = h_query[0];
-    end_timer!(h_time);
-
-    // Compute the L-query
-    let l_time = start_timer!(|| "Calculate L");
-    let l_query = FixedBaseMSM::multi_scalar_mul::<E::G1Projective>(
-        scalar_bits,
-        g1_window,
-        &g1_table,
-        &l[num_instance_variables..],
-    );
-    drop(l);
-    end_timer!(l_time);
-
-    end_timer!(proving_key_time);
-
-    // Generate R1CS verification key
-    let verifying_key_time = start_timer!(|| "Generate the R1CS verification key");
-    let gamma_g2 = g2_generator.mul(&gamma.into_repr());
-    let gamma_abc_g1 = FixedBaseMSM::multi_scalar_mul::<E::G1Projective>(
-        scalar_bits,
-        g1_window,
-        &g1_table,
-        &gamma_abc,
-    );
+    // Compute the A-, B-, H- and L-queries in G1 and the gamma_abc elements
+    // of the verification key, sharing the precomputation on the generator.
+    // If the stages are observed, each query is computed on its own instead,
+    // so that it is saved and reported as soon as it is done.
+    let g1 = || {
+        let g1_time = start_phase!("Calculate A, B G1, H, L and gamma_abc");
+        let h = scaled_powers(zt * &delta_inverse, t, 0..m_raw - 1);
+        let g1_scalars: [&[E::Fr]; 5] = [&a, &b, &h, &l, &gamma_abc];
+        let g1_queries = if stages.enabled() {
+            let names = [
+                (Query::A, "a_query"),
+                (Query::BG1, "b_g1_query"),
+                (Query::H, "h_query"),
+                (Query::L, "l_query"),
+                (Query::GammaAbc, "gamma_abc_g1"),
+            ];
+            names
+                .iter()
+                .zip(g1_scalars.iter())
+                .map(|(&(query, name), scalars)| {
+                    let phase = Phase::Msm(query);
+                    stages.stage(phase, name, || {
+                        fixed_base_msm_in_steps(&stages, phase, backend, g1_generator, scalars)
+                    })
+                })
+                .collect::<Result<Vec<_>, St::Error>>()
+        } else {
+            Ok(backend
+                .fixed_base_msm(g1_generator, &g1_scalars)
+                .iter()
+                .map(|query| E::G1Projective::batch_normalization_into_affine(query))
+                .collect())
+        };
+        end_phase!(g1_time);
+        g1_queries
+    };
 
-    drop(g1_table);
+    let (b_g2_query, g1_queries) = crate::join(b_g2, g1);
+    let b_g2_query = b_g2_query?;
+    let mut g1_queries = g1_queries?.into_iter();
+    drop((a, b, l));
+    let a_query = g1_queries.next().unwrap();
+    let b_g1_query = g1_queries.next().unwrap();
+    let h_query = g1_queries.next().unwrap();
+    let l_query = g1_queries.next().unwrap();
+    let gamma_abc_g1 = g1_queries.next().unwrap();
+    let g1_zt_deltainverse = h_query[0];
 
-    end_timer!(verifying_key_time);
+    end_phase!(proving_key_time);
 
     let vk = VerifyingKey::<E> {
         alpha_g1: alpha_g1.into_affine(),
         beta_g2: beta_g2.into_affine(),
         gamma_g2: gamma_g2.into_affine(),
         delta_g2: delta_g2.into_affine(),
-        gamma_abc_g1: E::G1Projective::batch_normalization_into_affine(&gamma_abc_g1),
+        gamma_abc_g1,
         alpha_g1_beta_g2: E::pairing(alpha_g1, beta_g2),
-        zt_delta_g1: g1_zt_deltainverse.into_affine(),
+        zt_delta_g1: g1_zt_deltainverse,
     };
+    end_phase!(setup_time);
 
-    let batch_normalization_time = start_timer!(|| "Convert proving key elements to affine");
-    let a_query = E::G1Projective::batch_normalization_into_affine(&a_query);
-    let b_g1_query = E::G1Projective::batch_normalization_into_affine(&b_g1_query);
-    let b_g2_query = E::G2Projective::batch_normalization_into_affine(&b_g2_query);
-    let h_query = E::G1Projective::batch_normalization_into_affine(&h_query);
-    let l_query = E::G1Projective::batch_normalization_into_affine(&l_query);
-    end_timer!(batch_normalization_time);
-    end_timer!(setup_time);
-
-    Ok(ProvingKey {
+    let pk = ProvingKey {
         vk,
         beta_g1: beta_g1.into_affine(),
         delta_g1: delta_g1.into_affine(),
@@ -233,5 +425,97 @@ where
         b_g2_query,
         h_query,
         l_query,
-    })
+    };
+    stages.finish()?;
+
+    Ok((pk, circuit_stats))
+}
+
+/// Computes `scalars[i] * base` for each `i` on `backend`, in as many steps as
+/// `stages` asks for and reporting the progress of `phase` after each step.
+fn fixed_base_msm_in_steps<G, B, St>(
+    stages: &St,
+    phase: Phase,
+    backend: &B,
+    base: G,
+    scalars: &[G::ScalarField],
+) -> Result<Vec<G::Affine>, St::Error>
+where
+    G: ProjectiveCurve,
+    B: MsmBackend,
+    St: Stages,
+{
+    let steps = stages.msm_steps();
+    let step_size = core::cmp::max((scalars.len() + steps - 1) / steps, 1);
+    let mut query = Vec::with_capacity(scalars.len());
+    for step in scalars.chunks(step_size) {
+        let step_query = backend.fixed_base_msm(base, &[step]).pop().unwrap();
+        query.extend(G::batch_normalization_into_affine(&step_query));
+        stages.report(phase, query.len() as f64 / scalars.len() as f64)?;
+    }
+    Ok(query)
+}
+
+/// Synthesizes `circuit` for setup, and constructs its evaluation domain and
+/// samples a point `t` outside of it.
+#[allow(clippy::type_complexity)]
+pub(crate) fn synthesize_for_setup<F, C, R>(
+    circuit: C,
+    rng: &mut R,
+) -> R1CSResult<(ConstraintSystemRef<F>, GeneralEvaluationDomain<F>, F)>
+where
+    F: PrimeField,
+    C: ConstraintSynthesizer<F>,
+    R: Rng,
+{
+    let cs = ConstraintSystem::new_ref();
+    cs.set_optimization_goal(OptimizationGoal::Constraints);
+    cs.set_mode(SynthesisMode::Setup);
+
+    // Synthesize the circuit.
+    let synthesis_time = start_phase!("Constraint synthesis");
+    circuit.generate_constraints(cs.clone())?;
+    end_phase!(synthesis_time);
+
+    let lc_time = start_phase!("Inlining LCs");
+    cs.finalize();
+    end_phase!(lc_time);
+
+    ///////////////////////////////////////////////////////////////////////////
+    let domain_time = start_phase!("Constructing evaluation domain");
+
+    let domain_size = cs.num_constraints() + cs.num_instance_variables();
+    let domain = GeneralEvaluationDomain::new(domain_size)
+        .ok_or(SynthesisError::PolynomialDegreeTooLarge)?;
+    let t = domain.sample_element_outside_domain(rng);
+
+    end_phase!(domain_time);
+    ///////////////////////////////////////////////////////////////////////////
+
+    Ok((cs, domain, t))
+}
+
+/// Computes `scale * t^i` for `i` in `range`, with one exponentiation per
+/// parallel chunk and a multiplication for each further power.
+pub(crate) fn scaled_powers<F: Field>(scale: F, t: F, range: Range<usize>) -> Vec<F> {
+    let n = range.len();
+    #[cfg(feature = "parallel")]
+    let chunk_size = {
+        let num_threads = rayon::current_num_threads();
+        core::cmp::max((n + num_threads - 1) / num_threads, 1)
+    };
+    #[cfg(not(feature = "parallel"))]
+    let chunk_size = core::cmp::max(n, 1);
+
+    let mut powers = vec![F::zero(); n];
+    cfg_chunks_mut!(powers, chunk_size)
+        .enumerate()
+        .for_each(|(i, chunk)| {
+            let mut power = scale * &t.pow([(range.start + i * chunk_size) as u64]);
+            for p in chunk.iter_mut() {
+                *p = power;
+                power *= &t;
+            }
+        });
+    powers
 }
@@ -1,5 +1,9 @@
 //! An implementation of the [`BPR20`] zkSNARK.
 //!
+//! Under the optional `tracing` feature, each phase of setup, proving and
+//! verification is a `debug` span of the `tracing` crate with the target
+//! `ark_bpr20`. The `r1cs` feature does not enable these spans.
+//!
 //! [`BPR20`]: https://eprint.iacr.org/2020/1306
 #![cfg_attr(not(feature = "std"), no_std)]
 #![warn(
@@ -15,6 +19,35 @@
 #[macro_use]
 extern crate ark_std;
 
+/// Starts the phase `$name`, which is timed with `start_timer!` under the
+/// `print-trace` feature and is a `tracing` span under the `tracing`
+/// feature, until it is ended with `end_phase!`.
+macro_rules! start_phase {
+    ($name:literal) => {
+        (
+            start_timer!(|| $name),
+            crate::PhaseSpan {
+                #[cfg(feature = "tracing")]
+                _span: tracing::debug_span!(target: "ark_bpr20", $name).entered(),
+            },
+        )
+    };
+}
+
+/// Ends a phase started with `start_phase!`.
+macro_rules! end_phase {
+    ($phase:expr) => {{
+        let (timer, _span) = $phase;
+        end_timer!(timer);
+    }};
+}
+
+/// The `tracing` span of a phase, which is exited when this is dropped.
+pub(crate) struct PhaseSpan {
+    #[cfg(feature = "tracing")]
+    pub(crate) _span: tracing::span::EnteredSpan,
+}
+
 #[cfg(feature = "r1cs")]
 #[macro_use]
 extern crate derivative;
@@ -28,12 +61,63 @@ pub mod data_structures;
 /// Generate public parameters for the BPR20 zkSNARK construction.
 pub mod generator;
 
+/// Multi-scalar multiplication backends for the prover and generator.
+pub mod msm;
+
 /// Create proofs for the BPR20 zkSNARK construction.
 pub mod prover;
 
+/// Proving keys with precomputed multiples of their elements.
+pub mod precomputed;
+
+/// Proving keys without the query elements of variables that do not appear
+/// in a query.
+pub mod sparse;
+
+/// Serialization of the elements of `G2` prepared for pairings.
+pub mod prepared;
+
+/// The challenge of proofs, and the specified encoding of its hash input.
+pub mod challenge;
+
 /// Verify proofs for the BPR20 zkSNARK construction.
 pub mod verifier;
 
+/// Errors of the BPR20 zkSNARK construction.
+pub mod error;
+
+/// Save the results of long-running setups and proofs, and resume from them.
+pub mod checkpoint;
+
+/// Report the progress of setups and proofs, and cancel them.
+pub mod progress;
+
+/// Statistics of setups and proofs, such as the durations of their phases.
+pub mod stats;
+
+/// Store keys in a versioned container format with a section table, and
+/// read them in whole or in part.
+pub mod container;
+
+/// Self-describing proofs, and fixed-size encodings of proofs.
+pub mod envelope;
+
+/// `serde` implementations for proofs and keys, and functions for public
+/// inputs, as their compressed encoding.
+#[cfg(feature = "serde")]
+mod serde_impls;
+
+/// Store proving keys on disk and stream their queries into the prover.
+#[cfg(feature = "std")]
+pub mod chunked;
+
+/// Split the work of one proof across worker processes on the same machine.
+///
+/// The protocol is plaintext and unauthenticated, and workers see the
+/// witness, so it is only for trusted or loopback links.
+#[cfg(feature = "distributed")]
+pub mod distributed;
+
 /// Constraints for the BPR20 verifier.
 #[cfg(feature = "r1cs")]
 pub mod constraints;
@@ -42,7 +126,12 @@ pub mod constraints;
 mod test;
 
 pub use self::data_structures::*;
-pub use self::{generator::*, prover::*, verifier::*};
+#[cfg(feature = "serde")]
+pub use self::serde_impls::serde_public_inputs;
+pub use self::{
+    challenge::*, error::*, generator::*, msm::*, precomputed::*, prepared::*, progress::*,
+    prover::*, sparse::*, stats::*, verifier::*,
+};
 
 use ark_crypto_primitives::snark::*;
 use ark_ec::PairingEngine;
@@ -50,6 +139,22 @@ use ark_relations::r1cs::{ConstraintSynthesizer, SynthesisError};
 use ark_std::rand::RngCore;
 use ark_std::{marker::PhantomData, vec::Vec};
 
+/// Runs `oper_a` and `oper_b` concurrently on the rayon pool when the
+/// `parallel` feature is enabled, and sequentially otherwise.
+#[inline]
+pub(crate) fn join<A, B, RA, RB>(oper_a: A, oper_b: B) -> (RA, RB)
+where
+    A: FnOnce() -> RA + Send,
+    B: FnOnce() -> RB + Send,
+    RA: Send,
+    RB: Send,
+{
+    #[cfg(feature = "parallel")]
+    return rayon::join(oper_a, oper_b);
+    #[cfg(not(feature = "parallel"))]
+    return (oper_a(), oper_b());
+}
+
 /// The SNARK of [[BPR20]](https://eprint.iacr.org/2020/1306.pdf).
 pub struct BPR20<E: PairingEngine> {
     e_phantom: PhantomData<E>,
@@ -95,6 +200,4 @@ impl<E: PairingEngine> SNARK<E::Fr> for BPR20<E> {
     }
 }
 
-
-
 impl<E: PairingEngine> CircuitSpecificSetupSNARK<E::Fr> for BPR20<E> {}
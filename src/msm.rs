@@ -0,0 +1,138 @@
+use ark_ec::{
+    msm::{FixedBaseMSM, VariableBaseMSM},
+    AffineCurve, ProjectiveCurve,
+};
+use ark_ff::{BigInteger, PrimeField, Zero};
+use ark_std::{cfg_into_iter, vec, vec::Vec};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// An implementation of the multi-scalar multiplications that the prover
+/// and the generator run, so that tuned implementations can be plugged in.
+///
+/// [`ArkworksMsm`] is the default backend.
+pub trait MsmBackend: Sync {
+    /// Computes `sum_i scalars[i] * bases[i]`, ignoring the scalars beyond
+    /// the end of `bases` and vice versa.
+    fn variable_base_msm<G: AffineCurve>(
+        &self,
+        bases: &[G],
+        scalars: &[<G::ScalarField as PrimeField>::BigInt],
+    ) -> G::Projective;
+
+    /// Computes `sum_i scalars[i] * bases[i]` like
+    /// [`variable_base_msm`](MsmBackend::variable_base_msm), for scalars
+    /// that are all less than `2^num_bits`.
+    ///
+    /// The default implementation runs the bucket method over the low
+    /// `num_bits` bits only.
+    fn short_scalar_msm<G: AffineCurve>(
+        &self,
+        bases: &[G],
+        scalars: &[<G::ScalarField as PrimeField>::BigInt],
+        num_bits: usize,
+    ) -> G::Projective {
+        bucket_msm(bases, scalars, num_bits)
+    }
+
+    /// Computes `s * base` for every scalar `s` of every batch in `scalars`,
+    /// returning one vector per batch. The batches share the same base, so
+    /// any precomputation on it can be shared as well.
+    fn fixed_base_msm<G: ProjectiveCurve>(
+        &self,
+        base: G,
+        scalars: &[&[G::ScalarField]],
+    ) -> Vec<Vec<G>>;
+}
+
+/// The MSM backend of arkworks, using `VariableBaseMSM` and `FixedBaseMSM`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ArkworksMsm;
+
+impl MsmBackend for ArkworksMsm {
+    fn variable_base_msm<G: AffineCurve>(
+        &self,
+        bases: &[G],
+        scalars: &[<G::ScalarField as PrimeField>::BigInt],
+    ) -> G::Projective {
+        VariableBaseMSM::multi_scalar_mul(bases, scalars)
+    }
+
+    fn fixed_base_msm<G: ProjectiveCurve>(
+        &self,
+        base: G,
+        scalars: &[&[G::ScalarField]],
+    ) -> Vec<Vec<G>> {
+        let num_scalars = scalars.iter().map(|s| s.len()).sum();
+        let scalar_bits = G::ScalarField::size_in_bits();
+        let window = FixedBaseMSM::get_mul_window_size(num_scalars);
+        let table = FixedBaseMSM::get_window_table::<G>(scalar_bits, window, base);
+        scalars
+            .iter()
+            .map(|s| FixedBaseMSM::multi_scalar_mul::<G>(scalar_bits, window, &table, s))
+            .collect()
+    }
+}
+
+/// The bucket method over the low `num_bits` bits of the scalars, which is
+/// the algorithm of `VariableBaseMSM` with the number of windows bounded by
+/// `num_bits` instead of the modulus size.
+fn bucket_msm<G: AffineCurve>(
+    bases: &[G],
+    scalars: &[<G::ScalarField as PrimeField>::BigInt],
+    num_bits: usize,
+) -> G::Projective {
+    let size = core::cmp::min(bases.len(), scalars.len());
+    let scalars = &scalars[..size];
+    let bases = &bases[..size];
+
+    // The window size of `VariableBaseMSM`, which is `log2(size) * ln(2) + 2`.
+    let c = if size < 32 {
+        3
+    } else {
+        (ark_std::log2(size) * 69 / 100) as usize + 2
+    };
+    let zero = G::Projective::zero();
+    let window_starts = (0..num_bits).step_by(c).collect::<Vec<_>>();
+
+    let window_sums = cfg_into_iter!(window_starts)
+        .map(|w_start| {
+            let mut res = zero;
+            let mut buckets = vec![zero; (1 << c) - 1];
+            for (scalar, base) in scalars.iter().zip(bases) {
+                let digit = bits(scalar, w_start, c);
+                if digit != 0 {
+                    buckets[(digit - 1) as usize].add_assign_mixed(base);
+                }
+            }
+
+            // sum_{i in 0..num_buckets} (sum_{j in i..num_buckets} bucket[j])
+            let mut running_sum = zero;
+            for b in buckets.into_iter().rev() {
+                running_sum += &b;
+                res += &running_sum;
+            }
+            res
+        })
+        .collect::<Vec<_>>();
+
+    // Traverse the windows from high to low.
+    window_sums.iter().rev().fold(zero, |mut total, sum| {
+        for _ in 0..c {
+            total.double_in_place();
+        }
+        total + sum
+    })
+}
+
+/// The `len < 64` bits of `n` starting at bit `start`.
+fn bits<B: BigInteger>(n: &B, start: usize, len: usize) -> u64 {
+    let limbs = n.as_ref();
+    let (limb, shift) = (start / 64, start % 64);
+    let mut bits = limbs.get(limb).map_or(0, |l| l >> shift);
+    if shift + len > 64 {
+        bits |= limbs.get(limb + 1).map_or(0, |l| l << (64 - shift));
+    }
+    bits & ((1 << len) - 1)
+}
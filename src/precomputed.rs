@@ -0,0 +1,192 @@
+use crate::{
+    msm::MsmBackend,
+    prover::{G1Query, MsmScalars, ProvingKeySource},
+    ProvingKey, VerifyingKey,
+};
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{BigInteger, PrimeField, Zero};
+use ark_relations::r1cs::Result as R1CSResult;
+use ark_std::{cfg_chunks_mut, cfg_iter, vec, vec::Vec};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// A proving key that stores shifted multiples of each query element, so
+/// that the prover's MSMs run over more bases with shorter scalars.
+///
+/// With a precomputation factor `k`, each element `P` of a query is stored
+/// as `P, 2^w * P, ..., 2^{(k-1)w} * P`, where `w` is the scalar bit length
+/// divided by `k`, rounded up. Each scalar is then split into `k` digits of
+/// `w` bits, and every MSM becomes one over `k` times as many bases with
+/// `w`-bit scalars, which needs fewer bucket-method windows. The key takes
+/// about `k` times the memory of the [`ProvingKey`] it is built from, so it
+/// is meant for provers that create many proofs with the same key.
+///
+/// A factor of 1 stores the queries unchanged.
+pub struct PrecomputedProvingKey<E: PairingEngine> {
+    vk: VerifyingKey<E>,
+    beta_g1: E::G1Affine,
+    delta_g1: E::G1Affine,
+    factor: usize,
+    /// The number of bits of each scalar digit.
+    digit_bits: usize,
+    a_table: Vec<E::G1Affine>,
+    b_g1_table: Vec<E::G1Affine>,
+    b_g2_table: Vec<E::G2Affine>,
+    h_table: Vec<E::G1Affine>,
+    l_table: Vec<E::G1Affine>,
+}
+
+impl<E: PairingEngine> PrecomputedProvingKey<E> {
+    /// Precompute the shifted multiples of every query element of `pk`,
+    /// using the precomputation factor `factor`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is zero.
+    pub fn new(pk: &ProvingKey<E>, factor: usize) -> Self {
+        assert!(factor > 0, "the precomputation factor must be positive");
+
+        let precomputation_time = start_phase!("Precompute proving key tables");
+        let scalar_bits = E::Fr::size_in_bits();
+        let digit_bits = (scalar_bits + factor - 1) / factor;
+        let precomputed = Self {
+            vk: pk.vk.clone(),
+            beta_g1: pk.beta_g1,
+            delta_g1: pk.delta_g1,
+            factor,
+            digit_bits,
+            a_table: shifted_multiples(&pk.a_query, factor, digit_bits),
+            b_g1_table: shifted_multiples(&pk.b_g1_query, factor, digit_bits),
+            b_g2_table: shifted_multiples(&pk.b_g2_query, factor, digit_bits),
+            h_table: shifted_multiples(&pk.h_query, factor, digit_bits),
+            l_table: shifted_multiples(&pk.l_query, factor, digit_bits),
+        };
+        end_phase!(precomputation_time);
+
+        precomputed
+    }
+
+    /// The precomputation factor, i.e. the number of multiples stored for
+    /// each query element.
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Computes `sum_i scalars[i] * query[i]` on `backend` from the table of
+    /// a query, splitting each scalar into `factor` digits.
+    fn msm<G: AffineCurve<ScalarField = E::Fr>, B: MsmBackend>(
+        &self,
+        backend: &B,
+        table: &[G],
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> G::Projective {
+        let factor = self.factor;
+        // Scalars beyond the end of the query are ignored and vice versa.
+        let len = core::cmp::min(table.len() / factor, scalars.len());
+        let chunk_size = scalars.chunk_size().unwrap_or(len).clamp(1, len.max(1));
+
+        let mut acc = G::Projective::zero();
+        let mut start = core::cmp::min(scalars.start(), len);
+        while start < len {
+            let end = core::cmp::min(start + chunk_size, len);
+            let scalars = scalars.to_repr(start..end);
+            let mut digits = vec![<E::Fr as PrimeField>::BigInt::default(); scalars.len() * factor];
+            cfg_chunks_mut!(digits, factor)
+                .zip(cfg_iter!(scalars))
+                .for_each(|(digits, scalar)| self.split(scalar, digits));
+            let bases = &table[start * factor..end * factor];
+            acc += &backend.short_scalar_msm(bases, &digits, self.digit_bits);
+            start = end;
+        }
+        acc
+    }
+
+    /// Splits `scalar` into `digits`, least significant first, such that
+    /// `scalar = sum_j digits[j] * 2^{j * digit_bits}`.
+    fn split(
+        &self,
+        scalar: &<E::Fr as PrimeField>::BigInt,
+        digits: &mut [<E::Fr as PrimeField>::BigInt],
+    ) {
+        let mut scalar = *scalar;
+        for digit in digits.iter_mut() {
+            *digit = scalar;
+            truncate(digit, self.digit_bits);
+            scalar.divn(self.digit_bits as u32);
+        }
+    }
+}
+
+/// Clears all bits of `n` from bit `bits` on.
+fn truncate<B: BigInteger>(n: &mut B, bits: usize) {
+    for (i, limb) in n.as_mut().iter_mut().enumerate() {
+        let limb_start = i * 64;
+        if limb_start >= bits {
+            *limb = 0;
+        } else if bits - limb_start < 64 {
+            *limb &= (1u64 << (bits - limb_start)) - 1;
+        }
+    }
+}
+
+/// Returns `P, 2^w * P, ..., 2^{(factor-1)w} * P` for every element `P` of
+/// `query`, where `w` is `digit_bits`.
+fn shifted_multiples<G: AffineCurve>(query: &[G], factor: usize, digit_bits: usize) -> Vec<G> {
+    let mut table = vec![G::Projective::zero(); query.len() * factor];
+    cfg_chunks_mut!(table, factor)
+        .zip(cfg_iter!(query))
+        .for_each(|(multiples, base)| {
+            let mut multiple = base.into_projective();
+            for m in multiples.iter_mut() {
+                *m = multiple;
+                for _ in 0..digit_bits {
+                    multiple.double_in_place();
+                }
+            }
+        });
+    G::Projective::batch_normalization_into_affine(&table)
+}
+
+impl<E: PairingEngine> ProvingKeySource<E> for PrecomputedProvingKey<E> {
+    type Error = ark_relations::r1cs::SynthesisError;
+
+    fn vk(&self) -> &VerifyingKey<E> {
+        &self.vk
+    }
+
+    fn beta_g1(&self) -> E::G1Affine {
+        self.beta_g1
+    }
+
+    fn delta_g1(&self) -> E::G1Affine {
+        self.delta_g1
+    }
+
+    fn num_variables(&self) -> usize {
+        self.a_table.len() / self.factor
+    }
+
+    fn msm_g1<B: MsmBackend>(
+        &self,
+        backend: &B,
+        query: G1Query,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> R1CSResult<E::G1Projective> {
+        let table = match query {
+            G1Query::A => &self.a_table,
+            G1Query::BG1 => &self.b_g1_table,
+            G1Query::H => &self.h_table,
+            G1Query::L => &self.l_table,
+        };
+        Ok(self.msm(backend, table, scalars))
+    }
+
+    fn msm_b_g2<B: MsmBackend>(
+        &self,
+        backend: &B,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> R1CSResult<E::G2Projective> {
+        Ok(self.msm(backend, &self.b_g2_table, scalars))
+    }
+}
@@ -0,0 +1,258 @@
+use ark_ec::{
+    bls12::{self, Bls12Parameters},
+    bn::{self, BnParameters},
+    bw6::{self, BW6Parameters},
+    mnt4::{
+        self,
+        g2::{AteAdditionCoefficients as Mnt4Addition, AteDoubleCoefficients as Mnt4Double},
+        MNT4Parameters,
+    },
+    mnt6::{
+        self,
+        g2::{AteAdditionCoefficients as Mnt6Addition, AteDoubleCoefficients as Mnt6Double},
+        MNT6Parameters,
+    },
+    short_weierstrass_jacobian::GroupAffine,
+    AffineCurve, SWModelParameters,
+};
+use ark_serialize::*;
+use ark_std::vec::Vec;
+
+/// Elements of `G2` prepared for pairings, which a
+/// [`PreparedVerifyingKey`](crate::PreparedVerifyingKey) serializes so that
+/// reading it does not prepare them again.
+///
+/// This is implemented for the prepared elements of the BLS12, BN, BW6, MNT4
+/// and MNT6 models of `ark-ec`, and for affine elements, which curves with
+/// pairings of their own such as CP6-782 use as prepared elements. Prepared
+/// elements are written as their coefficients in the order of their fields,
+/// each field element uncompressed and each vector preceded by its length.
+pub trait SerializablePreparedG2: Sized {
+    /// Writes the prepared element.
+    fn serialize_prepared<W: Write>(&self, writer: W) -> Result<(), SerializationError>;
+
+    /// The number of bytes written by `serialize_prepared`.
+    fn prepared_size(&self) -> usize;
+
+    /// Reads a prepared element, checking that its field elements are
+    /// canonical, that its points are in the prime order subgroup, and that it
+    /// has as many coefficients as the pairing uses.
+    fn deserialize_prepared<R: Read>(reader: R) -> Result<Self, SerializationError>;
+
+    /// Reads a prepared element like `deserialize_prepared`, without checking
+    /// that its points are in the prime order subgroup.
+    fn deserialize_prepared_unchecked<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::deserialize_prepared(reader)
+    }
+}
+
+/// Fails unless the lengths of a prepared element are `expected`.
+fn check_lengths(found: &[usize], expected: &[usize]) -> Result<(), SerializationError> {
+    if found == expected {
+        Ok(())
+    } else {
+        Err(SerializationError::InvalidData)
+    }
+}
+
+impl<P: Bls12Parameters> SerializablePreparedG2 for bls12::G2Prepared<P> {
+    fn serialize_prepared<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        self.ell_coeffs.serialize_uncompressed(&mut writer)?;
+        self.infinity.serialize(&mut writer)
+    }
+
+    fn prepared_size(&self) -> usize {
+        self.ell_coeffs.uncompressed_size() + self.infinity.serialized_size()
+    }
+
+    fn deserialize_prepared<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let prepared = Self {
+            ell_coeffs: Vec::deserialize_uncompressed(&mut reader)?,
+            infinity: bool::deserialize(&mut reader)?,
+        };
+        // The identity is prepared without coefficients.
+        let expected = if prepared.infinity {
+            0
+        } else {
+            Self::from(bls12::G2Affine::<P>::prime_subgroup_generator())
+                .ell_coeffs
+                .len()
+        };
+        check_lengths(&[prepared.ell_coeffs.len()], &[expected])?;
+        Ok(prepared)
+    }
+}
+
+impl<P: BnParameters> SerializablePreparedG2 for bn::G2Prepared<P> {
+    fn serialize_prepared<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        self.ell_coeffs.serialize_uncompressed(&mut writer)?;
+        self.infinity.serialize(&mut writer)
+    }
+
+    fn prepared_size(&self) -> usize {
+        self.ell_coeffs.uncompressed_size() + self.infinity.serialized_size()
+    }
+
+    fn deserialize_prepared<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let prepared = Self {
+            ell_coeffs: Vec::deserialize_uncompressed(&mut reader)?,
+            infinity: bool::deserialize(&mut reader)?,
+        };
+        let expected = if prepared.infinity {
+            0
+        } else {
+            Self::from(bn::G2Affine::<P>::prime_subgroup_generator())
+                .ell_coeffs
+                .len()
+        };
+        check_lengths(&[prepared.ell_coeffs.len()], &[expected])?;
+        Ok(prepared)
+    }
+}
+
+impl<P: BW6Parameters> SerializablePreparedG2 for bw6::G2Prepared<P> {
+    fn serialize_prepared<W: Write>(&self, mut writer: W) -> Result<(), SerializationError> {
+        self.ell_coeffs_1.serialize_uncompressed(&mut writer)?;
+        self.ell_coeffs_2.serialize_uncompressed(&mut writer)?;
+        self.infinity.serialize(&mut writer)
+    }
+
+    fn prepared_size(&self) -> usize {
+        self.ell_coeffs_1.uncompressed_size()
+            + self.ell_coeffs_2.uncompressed_size()
+            + self.infinity.serialized_size()
+    }
+
+    fn deserialize_prepared<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let prepared = Self {
+            ell_coeffs_1: Vec::deserialize_uncompressed(&mut reader)?,
+            ell_coeffs_2: Vec::deserialize_uncompressed(&mut reader)?,
+            infinity: bool::deserialize(&mut reader)?,
+        };
+        let expected = if prepared.infinity {
+            [0, 0]
+        } else {
+            let generator = Self::from(bw6::G2Affine::<P>::prime_subgroup_generator());
+            [generator.ell_coeffs_1.len(), generator.ell_coeffs_2.len()]
+        };
+        check_lengths(
+            &[prepared.ell_coeffs_1.len(), prepared.ell_coeffs_2.len()],
+            &expected,
+        )?;
+        Ok(prepared)
+    }
+}
+
+/// Implements [`SerializablePreparedG2`] for the prepared elements of an MNT
+/// model, whose coefficients are structs with the same fields in both models.
+macro_rules! impl_mnt_prepared {
+    ($model:ident, $params:ident, $double:ident, $addition:ident) => {
+        impl<P: $params> SerializablePreparedG2 for $model::G2Prepared<P> {
+            fn serialize_prepared<W: Write>(
+                &self,
+                mut writer: W,
+            ) -> Result<(), SerializationError> {
+                self.x.serialize_uncompressed(&mut writer)?;
+                self.y.serialize_uncompressed(&mut writer)?;
+                self.x_over_twist.serialize_uncompressed(&mut writer)?;
+                self.y_over_twist.serialize_uncompressed(&mut writer)?;
+                self.double_coefficients.len().serialize(&mut writer)?;
+                for c in &self.double_coefficients {
+                    c.c_h.serialize_uncompressed(&mut writer)?;
+                    c.c_4c.serialize_uncompressed(&mut writer)?;
+                    c.c_j.serialize_uncompressed(&mut writer)?;
+                    c.c_l.serialize_uncompressed(&mut writer)?;
+                }
+                self.addition_coefficients.len().serialize(&mut writer)?;
+                for c in &self.addition_coefficients {
+                    c.c_l1.serialize_uncompressed(&mut writer)?;
+                    c.c_rz.serialize_uncompressed(&mut writer)?;
+                }
+                Ok(())
+            }
+
+            fn prepared_size(&self) -> usize {
+                let element = self.x.uncompressed_size();
+                4 * element
+                    + self.double_coefficients.len().serialized_size()
+                    + 4 * element * self.double_coefficients.len()
+                    + self.addition_coefficients.len().serialized_size()
+                    + 2 * element * self.addition_coefficients.len()
+            }
+
+            fn deserialize_prepared<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+                let x = CanonicalDeserialize::deserialize_uncompressed(&mut reader)?;
+                let y = CanonicalDeserialize::deserialize_uncompressed(&mut reader)?;
+                let x_over_twist = CanonicalDeserialize::deserialize_uncompressed(&mut reader)?;
+                let y_over_twist = CanonicalDeserialize::deserialize_uncompressed(&mut reader)?;
+                let generator = Self::from($model::G2Affine::<P>::prime_subgroup_generator());
+
+                let len = usize::deserialize(&mut reader)?;
+                check_lengths(&[len], &[generator.double_coefficients.len()])?;
+                let mut double_coefficients = Vec::with_capacity(len);
+                for _ in 0..len {
+                    double_coefficients.push($double {
+                        c_h: CanonicalDeserialize::deserialize_uncompressed(&mut reader)?,
+                        c_4c: CanonicalDeserialize::deserialize_uncompressed(&mut reader)?,
+                        c_j: CanonicalDeserialize::deserialize_uncompressed(&mut reader)?,
+                        c_l: CanonicalDeserialize::deserialize_uncompressed(&mut reader)?,
+                    });
+                }
+
+                let len = usize::deserialize(&mut reader)?;
+                check_lengths(&[len], &[generator.addition_coefficients.len()])?;
+                let mut addition_coefficients = Vec::with_capacity(len);
+                for _ in 0..len {
+                    addition_coefficients.push($addition {
+                        c_l1: CanonicalDeserialize::deserialize_uncompressed(&mut reader)?,
+                        c_rz: CanonicalDeserialize::deserialize_uncompressed(&mut reader)?,
+                    });
+                }
+
+                Ok(Self {
+                    x,
+                    y,
+                    x_over_twist,
+                    y_over_twist,
+                    double_coefficients,
+                    addition_coefficients,
+                })
+            }
+        }
+    };
+}
+
+impl_mnt_prepared!(mnt4, MNT4Parameters, Mnt4Double, Mnt4Addition);
+impl_mnt_prepared!(mnt6, MNT6Parameters, Mnt6Double, Mnt6Addition);
+
+impl<P: SWModelParameters> SerializablePreparedG2 for GroupAffine<P> {
+    fn serialize_prepared<W: Write>(&self, writer: W) -> Result<(), SerializationError> {
+        self.serialize_uncompressed(writer)
+    }
+
+    fn prepared_size(&self) -> usize {
+        self.uncompressed_size()
+    }
+
+    fn deserialize_prepared<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::deserialize_uncompressed(reader)
+    }
+
+    fn deserialize_prepared_unchecked<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::deserialize_unchecked(reader)
+    }
+}
+
+/// A prepared element serialized through [`SerializablePreparedG2`], so that
+/// it can be added to checksums.
+pub(crate) struct Prepared<'a, G>(pub(crate) &'a G);
+
+impl<'a, G: SerializablePreparedG2> CanonicalSerialize for Prepared<'a, G> {
+    fn serialize<W: Write>(&self, writer: W) -> Result<(), SerializationError> {
+        self.0.serialize_prepared(writer)
+    }
+
+    fn serialized_size(&self) -> usize {
+        self.0.prepared_size()
+    }
+}
@@ -0,0 +1,301 @@
+use ark_relations::r1cs::SynthesisError;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::Error;
+#[cfg(feature = "std")]
+use crate::{checkpoint::Checkpoints, stats::Recorder};
+
+/// The number of steps in which each MSM of an observed run is computed, so
+/// that its progress can be reported and it can be cancelled part way.
+const MSM_STEPS: usize = 16;
+
+/// A phase of a setup or a proof.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Synthesizing the circuit.
+    Synthesis,
+    /// Evaluating the QAP polynomials of the circuit, during setup.
+    Qap,
+    /// The FFTs that compute the coefficients of `h`, while proving.
+    Fft,
+    /// An MSM over a query of the proving key: the fixed-base MSM that
+    /// computes the query during setup, or the variable-base MSM over it
+    /// while proving.
+    Msm(Query),
+}
+
+/// A query of the proving key or the verifying key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Query {
+    /// The elements `a_i * G`.
+    A,
+    /// The elements `b_i * G`.
+    BG1,
+    /// The elements `b_i * H`.
+    BG2,
+    /// The elements `h_i * G`.
+    H,
+    /// The elements `l_i * G`.
+    L,
+    /// The elements `gamma_abc_i * G` of the verifying key.
+    GammaAbc,
+}
+
+/// Receives reports of the progress of a setup or a proof.
+///
+/// Phases may run concurrently, so reports of different phases can be
+/// interleaved and may come from several threads. The reports of each phase
+/// start at `0.0`, never decrease, and end at `1.0` once it is done. A phase
+/// whose result was resumed from a checkpoint only reports its start and
+/// end.
+pub trait Progress: Sync {
+    /// Reports that the fraction `fraction` of `phase` is done.
+    fn report(&self, phase: Phase, fraction: f64);
+}
+
+impl<F: Fn(Phase, f64) + Sync> Progress for F {
+    fn report(&self, phase: Phase, fraction: f64) {
+        self(phase, fraction)
+    }
+}
+
+/// A flag through which a setup or a proof can be cancelled from another
+/// thread.
+///
+/// The flag is checked between phases and between the steps in which each
+/// MSM is computed, after which the run returns [`Error::Cancelled`].
+#[derive(Debug, Default)]
+pub struct CancellationToken {
+    cancelled: AtomicBool,
+}
+
+impl CancellationToken {
+    /// A token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the runs that use this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// The ways in which a setup or a proof is observed: the checkpoints it
+/// saves its results to, the receiver of its progress, and the token that
+/// cancels it. None of them are used by default.
+#[derive(Clone, Copy, Default)]
+pub struct RunOptions<'a> {
+    #[cfg(feature = "std")]
+    checkpoints: Option<&'a Checkpoints>,
+    progress: Option<&'a dyn Progress>,
+    cancellation: Option<&'a CancellationToken>,
+    #[cfg(feature = "std")]
+    recorder: Option<&'a Recorder>,
+}
+
+impl<'a> RunOptions<'a> {
+    /// Options that do not observe the run.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Save the results of the run to `checkpoints`, resume from them, and
+    /// delete them once the run succeeds. **The results of proofs are as
+    /// secret as the witness**; see [`Checkpoints`].
+    #[cfg(feature = "std")]
+    pub fn with_checkpoints(mut self, checkpoints: &'a Checkpoints) -> Self {
+        self.checkpoints = Some(checkpoints);
+        self
+    }
+
+    /// Report the progress of the run to `progress`.
+    pub fn with_progress(mut self, progress: &'a dyn Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Cancel the run once `cancellation` is cancelled.
+    pub fn with_cancellation(mut self, cancellation: &'a CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Record the durations of the phases of the run to `recorder`.
+    #[cfg(feature = "std")]
+    pub(crate) fn with_recorder(mut self, recorder: &'a Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// The stages of a run observed with these options.
+    pub(crate) fn stages(self) -> ObservedStages<'a> {
+        ObservedStages {
+            options: self,
+            #[cfg(feature = "std")]
+            fingerprint: [0u8; 64],
+        }
+    }
+}
+
+/// The stages of a setup or a proof, whose results may be saved so that an
+/// interrupted run can resume from them, and whose progress may be reported.
+pub(crate) trait Stages: Sync {
+    /// The error returned when a result cannot be saved or the run is
+    /// cancelled.
+    type Error: From<SynthesisError> + Send;
+
+    /// Returns `true` if the stages are observed, in which case they should
+    /// be computed one at a time instead of being combined.
+    fn enabled(&self) -> bool;
+
+    /// The number of steps in which to compute each MSM.
+    fn msm_steps(&self) -> usize;
+
+    /// Starts a run, whose saved results are only resumed by runs with the
+    /// same `fingerprint`. The fingerprint is only computed if the results
+    /// are saved.
+    fn begin<F: FnOnce() -> [u8; 64]>(&mut self, fingerprint: F);
+
+    /// Reports that the fraction `fraction` of `phase` is done, and fails if
+    /// the run was cancelled.
+    fn report(&self, phase: Phase, fraction: f64) -> Result<(), Self::Error>;
+
+    /// Returns the saved result of the stage `name` of this run, or computes
+    /// it with `compute` and saves it, reporting the start and end of
+    /// `phase`.
+    fn stage<T, Err, F>(&self, phase: Phase, name: &str, compute: F) -> Result<T, Err>
+    where
+        T: CanonicalSerialize + CanonicalDeserialize,
+        Err: From<Self::Error>,
+        F: FnOnce() -> Result<T, Err>;
+
+    /// Ends a run that succeeded, deleting its saved results.
+    fn finish(&self) -> Result<(), Self::Error>;
+}
+
+/// Stages that are not observed.
+pub(crate) struct Unobserved;
+
+impl Stages for Unobserved {
+    type Error = SynthesisError;
+
+    fn enabled(&self) -> bool {
+        false
+    }
+
+    fn msm_steps(&self) -> usize {
+        1
+    }
+
+    fn begin<F: FnOnce() -> [u8; 64]>(&mut self, _: F) {}
+
+    fn report(&self, _: Phase, _: f64) -> Result<(), SynthesisError> {
+        Ok(())
+    }
+
+    fn stage<T, Err, F>(&self, _: Phase, _: &str, compute: F) -> Result<T, Err>
+    where
+        T: CanonicalSerialize + CanonicalDeserialize,
+        Err: From<SynthesisError>,
+        F: FnOnce() -> Result<T, Err>,
+    {
+        compute()
+    }
+
+    fn finish(&self) -> Result<(), SynthesisError> {
+        Ok(())
+    }
+}
+
+/// The stages of a run observed with [`RunOptions`].
+pub(crate) struct ObservedStages<'a> {
+    options: RunOptions<'a>,
+    #[cfg(feature = "std")]
+    fingerprint: [u8; 64],
+}
+
+impl<'a> Stages for ObservedStages<'a> {
+    type Error = Error;
+
+    fn enabled(&self) -> bool {
+        #[cfg(feature = "std")]
+        if self.options.checkpoints.is_some() || self.options.recorder.is_some() {
+            return true;
+        }
+        self.options.progress.is_some() || self.options.cancellation.is_some()
+    }
+
+    fn msm_steps(&self) -> usize {
+        if self.options.progress.is_some() || self.options.cancellation.is_some() {
+            MSM_STEPS
+        } else {
+            1
+        }
+    }
+
+    fn begin<F: FnOnce() -> [u8; 64]>(&mut self, fingerprint: F) {
+        #[cfg(feature = "std")]
+        if self.options.checkpoints.is_some() {
+            self.fingerprint = fingerprint();
+        }
+        #[cfg(not(feature = "std"))]
+        let _ = fingerprint;
+    }
+
+    fn report(&self, phase: Phase, fraction: f64) -> Result<(), Error> {
+        if let Some(progress) = self.options.progress {
+            progress.report(phase, fraction);
+        }
+        #[cfg(feature = "std")]
+        if let Some(recorder) = self.options.recorder {
+            recorder.report(phase, fraction);
+        }
+        match self.options.cancellation {
+            Some(cancellation) if cancellation.is_cancelled() => Err(Error::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    fn stage<T, Err, F>(&self, phase: Phase, name: &str, compute: F) -> Result<T, Err>
+    where
+        T: CanonicalSerialize + CanonicalDeserialize,
+        Err: From<Error>,
+        F: FnOnce() -> Result<T, Err>,
+    {
+        self.report(phase, 0.0)?;
+        #[cfg(feature = "std")]
+        let value = match self.options.checkpoints {
+            Some(checkpoints) => match checkpoints.load(name, &self.fingerprint)? {
+                Some(value) => value,
+                None => {
+                    let value = compute()?;
+                    checkpoints.save(name, &self.fingerprint, &value)?;
+                    value
+                },
+            },
+            None => compute()?,
+        };
+        #[cfg(not(feature = "std"))]
+        let value = {
+            let _ = name;
+            compute()?
+        };
+        self.report(phase, 1.0)?;
+        Ok(value)
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        #[cfg(feature = "std")]
+        if let Some(checkpoints) = self.options.checkpoints {
+            checkpoints.remove()?;
+        }
+        Ok(())
+    }
+}
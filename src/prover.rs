@@ -1,14 +1,27 @@
-use crate::{r1cs_to_qap::R1CStoQAP, Proof, ProvingKey};
-use ark_ec::{msm::VariableBaseMSM, AffineCurve, PairingEngine, ProjectiveCurve};
-use ark_ff::{Field, PrimeField, UniformRand, Zero, One, to_bytes};
-use ark_poly::GeneralEvaluationDomain;
+use crate::{
+    challenge::proof_challenge,
+    checkpoint::Fingerprint,
+    msm::{ArkworksMsm, MsmBackend},
+    progress::{Phase, Query, RunOptions, Stages, Unobserved},
+    r1cs_to_qap::{self, R1CStoQAP},
+    Error, Proof, ProvingKey, VerifyingKey,
+};
+#[cfg(feature = "std")]
+use crate::{
+    checkpoint::Checkpoints,
+    stats::{CircuitStats, ProofStats, Recorder},
+};
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{Field, One, PrimeField, UniformRand, Zero};
+use ark_poly::{EvaluationDomain, GeneralEvaluationDomain};
 use ark_relations::r1cs::{
-    ConstraintSynthesizer, ConstraintSystem, OptimizationGoal, Result as R1CSResult,
+    ConstraintMatrices, ConstraintSynthesizer, ConstraintSystem, OptimizationGoal,
+    Result as R1CSResult, SynthesisError, SynthesisMode,
 };
 use ark_std::rand::Rng;
-use ark_std::{cfg_into_iter, cfg_iter, vec::Vec};
-
-use blake2::{Blake2b, Digest};
+use ark_std::{borrow::Cow, cfg_into_iter, cfg_iter, ops::Range, vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::time::Instant;
 
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
@@ -25,15 +38,98 @@ where
     E: PairingEngine,
     C: ConstraintSynthesizer<E::Fr>,
     R: Rng,
+{
+    let randomness = ProofRandomness::precompute(pk, rng);
+
+    create_proof_with_precomputed::<E, C>(circuit, pk, randomness)
+}
+
+/// Sample the randomness `r`, `s` and the non-zero `zeta` used by the prover.
+fn sample_randomness<E, R>(rng: &mut R) -> (E::Fr, E::Fr, E::Fr)
+where
+    E: PairingEngine,
+    R: Rng,
 {
     let r = E::Fr::rand(rng);
     let s = E::Fr::rand(rng);
     let mut zeta = E::Fr::zero();
-    while zeta.is_zero(){
+    while zeta.is_zero() {
         zeta = E::Fr::rand(rng);
     }
 
-    create_proof::<E, C>(circuit, pk, r, s, zeta)
+    (r, s, zeta)
+}
+
+/// The randomness of a proof together with the group elements that depend
+/// only on it and the proving key, which can be computed ahead of time.
+///
+/// This holds `delta' = zeta * delta` in both groups and its multiples
+/// `r * delta'` and `s * delta'` in `E::G1`, `s * delta'` in `E::G2`, and
+/// `zeta * r * s * delta'` in `E::G1`. Proving consumes it, since reusing
+/// the randomness of a proof for another one would leak the witness.
+pub struct ProofRandomness<E: PairingEngine> {
+    r: E::Fr,
+    s: E::Fr,
+    zeta: E::Fr,
+    /// The `delta_g1` of the proving key this was computed for.
+    delta_g1: E::G1Affine,
+    delta_prime_g2: E::G2Affine,
+    r_delta_prime_g1: E::G1Projective,
+    s_delta_prime_g1: E::G1Projective,
+    s_delta_prime_g2: E::G2Projective,
+    rs_delta_prime_g1: E::G1Projective,
+}
+
+impl<E: PairingEngine> ProofRandomness<E> {
+    /// Sample the randomness of a proof via `rng` and precompute the group
+    /// elements that depend on it for the proving key `pk`.
+    pub fn precompute<S, R>(pk: &S, rng: &mut R) -> Self
+    where
+        S: ProvingKeySource<E>,
+        R: Rng,
+    {
+        let (r, s, zeta) = sample_randomness::<E, R>(rng);
+
+        Self::new(pk, r, s, zeta)
+    }
+
+    /// Precompute the group elements that depend on the randomness `r`, `s`
+    /// and `zeta` for the proving key `pk`.
+    pub fn new<S: ProvingKeySource<E>>(pk: &S, r: E::Fr, s: E::Fr, zeta: E::Fr) -> Self {
+        let precomputation_time = start_phase!("Precompute proof randomness");
+        let delta_g1 = pk.delta_g1();
+        let (g1, (delta_prime_g2, s_delta_prime_g2)) = crate::join(
+            || {
+                let delta_prime_g1 = delta_g1.mul(zeta);
+                (
+                    delta_prime_g1.mul(&r.into_repr()),
+                    delta_prime_g1.mul(&s.into_repr()),
+                    delta_prime_g1.mul(&(zeta * r * s).into_repr()),
+                )
+            },
+            || {
+                let delta_prime_g2 = pk.vk().delta_g2.mul(zeta);
+                (
+                    delta_prime_g2.into_affine(),
+                    delta_prime_g2.mul(&s.into_repr()),
+                )
+            },
+        );
+        let (r_delta_prime_g1, s_delta_prime_g1, rs_delta_prime_g1) = g1;
+        end_phase!(precomputation_time);
+
+        Self {
+            r,
+            s,
+            zeta,
+            delta_g1,
+            delta_prime_g2,
+            r_delta_prime_g1,
+            s_delta_prime_g1,
+            s_delta_prime_g2,
+            rs_delta_prime_g1,
+        }
+    }
 }
 
 /// Create a proof that is *not* zero-knowledge.
@@ -55,148 +151,1428 @@ pub fn create_proof<E, C>(
     s: E::Fr,
     zeta: E::Fr,
 ) -> R1CSResult<Proof<E>>
+where
+    E: PairingEngine,
+    C: ConstraintSynthesizer<E::Fr>,
+{
+    let randomness = ProofRandomness::new(pk, r, s, zeta);
+
+    create_proof_with_precomputed::<E, C>(circuit, pk, randomness)
+}
+
+/// Create a proof using randomness that was precomputed for `pk` with
+/// [`ProofRandomness::precompute`], consuming it.
+pub fn create_proof_with_precomputed<E, C>(
+    circuit: C,
+    pk: &ProvingKey<E>,
+    randomness: ProofRandomness<E>,
+) -> R1CSResult<Proof<E>>
+where
+    E: PairingEngine,
+    C: ConstraintSynthesizer<E::Fr>,
+{
+    let prover_time = start_phase!("BPR20::Prover");
+    let prepared = synthesize_and_prepare_witness(circuit, pk, !randomness.r.is_zero())?;
+    let proof = prepared.prove(pk, randomness)?;
+    end_phase!(prover_time);
+
+    Ok(proof)
+}
+
+/// Synthesize `circuit` and compute the sums over the proving key `pk` that
+/// depend only on its witness, so that [`reprove`] can create many proofs
+/// of it cheaply.
+pub fn prepare_witness<E, C>(circuit: C, pk: &ProvingKey<E>) -> R1CSResult<PreparedWitness<E>>
+where
+    E: PairingEngine,
+    C: ConstraintSynthesizer<E::Fr>,
+{
+    synthesize_and_prepare_witness(circuit, pk, true)
+}
+
+/// Create a fresh zero-knowledge proof of a witness prepared with
+/// [`prepare_witness`], sampling new randomness via `rng`.
+///
+/// Only the group elements that depend on the randomness are computed, so
+/// this costs a few scalar multiplications instead of the prover's MSMs.
+#[inline]
+pub fn reprove<E, S, R>(
+    prepared: &PreparedWitness<E>,
+    pk: &S,
+    rng: &mut R,
+) -> Result<Proof<E>, S::Error>
+where
+    E: PairingEngine,
+    S: ProvingKeySource<E>,
+    R: Rng,
+{
+    let randomness = ProofRandomness::precompute(pk, rng);
+
+    prepared.prove(pk, randomness)
+}
+
+/// Synthesize `circuit` and compute its witness sums over `pk`, computing
+/// the B-query sum in `E::G1` only if `compute_b_g1` is set.
+fn synthesize_and_prepare_witness<E, C>(
+    circuit: C,
+    pk: &ProvingKey<E>,
+    compute_b_g1: bool,
+) -> R1CSResult<PreparedWitness<E>>
 where
     E: PairingEngine,
     C: ConstraintSynthesizer<E::Fr>,
 {
     type D<F> = GeneralEvaluationDomain<F>;
 
-    let prover_time = start_timer!(|| "BPR20::Prover");
     let cs = ConstraintSystem::new_ref();
 
     // Set the optimization goal
     cs.set_optimization_goal(OptimizationGoal::Constraints);
 
     // Synthesize the circuit.
-    let synthesis_time = start_timer!(|| "Constraint synthesis");
+    let synthesis_time = start_phase!("Constraint synthesis");
     circuit.generate_constraints(cs.clone())?;
     debug_assert!(cs.is_satisfied().unwrap());
-    end_timer!(synthesis_time);
+    end_phase!(synthesis_time);
 
-    let lc_time = start_timer!(|| "Inlining LCs");
+    let lc_time = start_phase!("Inlining LCs");
     cs.finalize();
-    end_timer!(lc_time);
+    end_phase!(lc_time);
 
-    let witness_map_time = start_timer!(|| "R1CS to QAP witness map");
+    let witness_map_time = start_phase!("R1CS to QAP witness map");
     let h = R1CStoQAP::witness_map::<E::Fr, D<E::Fr>>(cs.clone())?;
-    end_timer!(witness_map_time);
+    end_phase!(witness_map_time);
 
-    let c_acc_time = start_timer!(|| "Compute C");
+    let prover = cs.borrow().unwrap();
+    prepare_witness_with_assignment::<E, _, _, _, _>(
+        pk,
+        h,
+        &prover.instance_assignment,
+        &prover.witness_assignment,
+        None,
+        &ArkworksMsm,
+        compute_b_g1,
+        &Unobserved,
+    )
+}
+
+/// Synthesize `circuit` and return its constraint matrices, for use with
+/// [`ProverContext::new`]. The circuit does not need to carry a witness.
+pub fn constraint_matrices<F, C>(circuit: C) -> R1CSResult<ConstraintMatrices<F>>
+where
+    F: PrimeField,
+    C: ConstraintSynthesizer<F>,
+{
+    let cs = ConstraintSystem::new_ref();
+    cs.set_optimization_goal(OptimizationGoal::Constraints);
+    cs.set_mode(SynthesisMode::Setup);
+
+    let synthesis_time = start_phase!("Constraint synthesis");
+    circuit.generate_constraints(cs.clone())?;
+    end_phase!(synthesis_time);
+
+    let lc_time = start_phase!("Inlining LCs");
+    cs.finalize();
+    end_phase!(lc_time);
+
+    cs.to_matrices().ok_or(SynthesisError::MissingCS)
+}
+
+/// Precomputed, circuit-specific state for creating many proofs with the same
+/// proving key.
+///
+/// The constraint matrices and the evaluation domain are built once, so each
+/// proof only has to synthesize the witness, which is done in
+/// `SynthesisMode::Prove { construct_matrices: false }`.
+///
+/// The proving key can be any [`ProvingKeySource`], such as a [`ProvingKey`]
+/// held in memory or a [`ChunkedProvingKey`](crate::chunked::ChunkedProvingKey)
+/// that is streamed from disk. The MSMs run on the [`MsmBackend`] `B`.
+pub struct ProverContext<
+    'a,
+    E: PairingEngine,
+    S: ProvingKeySource<E> = ProvingKey<E>,
+    B: MsmBackend = ArkworksMsm,
+> {
+    pk: &'a S,
+    matrices: ConstraintMatrices<E::Fr>,
+    domain: GeneralEvaluationDomain<E::Fr>,
+    memory_budget: Option<usize>,
+    backend: B,
+}
+
+impl<'a, E: PairingEngine, S: ProvingKeySource<E>> ProverContext<'a, E, S> {
+    /// Create a prover context from the proving key `pk` and the constraint
+    /// matrices of the circuit it was generated for.
+    pub fn new(pk: &'a S, matrices: ConstraintMatrices<E::Fr>) -> R1CSResult<Self> {
+        let num_variables = matrices.num_instance_variables + matrices.num_witness_variables;
+        if pk.num_variables() != num_variables
+            || pk.vk().gamma_abc_g1.len() != matrices.num_instance_variables
+        {
+            return Err(SynthesisError::MalformedVerifyingKey);
+        }
+
+        let domain = GeneralEvaluationDomain::new(
+            matrices.num_constraints + matrices.num_instance_variables,
+        )
+        .ok_or(SynthesisError::PolynomialDegreeTooLarge)?;
+
+        Ok(Self {
+            pk,
+            matrices,
+            domain,
+            memory_budget: None,
+            backend: ArkworksMsm,
+        })
+    }
+}
+
+impl<'a, E: PairingEngine, S: ProvingKeySource<E>, B: MsmBackend> ProverContext<'a, E, S, B> {
+    /// Run the prover's MSMs on `backend` instead of the current backend.
+    pub fn with_backend<B2: MsmBackend>(self, backend: B2) -> ProverContext<'a, E, S, B2> {
+        ProverContext {
+            pk: self.pk,
+            matrices: self.matrices,
+            domain: self.domain,
+            memory_budget: self.memory_budget,
+            backend,
+        }
+    }
+
+    /// Limit the working memory of the witness map and the MSMs to about
+    /// `bytes`.
+    ///
+    /// When evaluating the QAP polynomials over the whole coset would exceed
+    /// the budget, the witness map evaluates them a chunk of the coset at a
+    /// time, with in-place FFTs over a subdomain, and adds each chunk to the
+    /// coefficients of `h`. The FFTs then run in the prover rather than
+    /// through the proving key source. When converting all MSM scalars up
+    /// front would exceed the budget, the prover converts them a chunk at a
+    /// time and runs a smaller MSM over the matching range of the proving key
+    /// for each chunk. This trades some performance for a lower peak memory on
+    /// very large circuits.
+    ///
+    /// The budget does not cover the assignment and the coefficients of `h`,
+    /// which are held in full.
+    pub fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// The proving key this context was built for.
+    pub fn proving_key(&self) -> &'a S {
+        self.pk
+    }
+
+    /// Create a proof that is zero-knowledge.
+    /// This method samples randomness for zero knowledges via `rng`.
+    #[inline]
+    pub fn create_random_proof<C, R>(&self, circuit: C, rng: &mut R) -> Result<Proof<E>, S::Error>
+    where
+        C: ConstraintSynthesizer<E::Fr>,
+        R: Rng,
+    {
+        let randomness = ProofRandomness::precompute(self.pk, rng);
+
+        self.create_proof_with_precomputed(circuit, randomness)
+    }
+
+    /// Create a proof using randomness `r`, `s` and `zeta`.
+    pub fn create_proof<C>(
+        &self,
+        circuit: C,
+        r: E::Fr,
+        s: E::Fr,
+        zeta: E::Fr,
+    ) -> Result<Proof<E>, S::Error>
+    where
+        C: ConstraintSynthesizer<E::Fr>,
+    {
+        let randomness = ProofRandomness::new(self.pk, r, s, zeta);
+
+        self.create_proof_with_precomputed(circuit, randomness)
+    }
+
+    /// Create a proof using randomness `r`, `s` and `zeta`, saving the sum
+    /// over each query to `checkpoints` as it is computed.
+    ///
+    /// A proof resumes from the sums that an interrupted proof of the same
+    /// assignment with the same proving key saved to `checkpoints`, and is
+    /// the same as the one [`ProverContext::create_proof`] creates. **The sums
+    /// are as secret as the witness**; they are deleted once the proof
+    /// succeeds.
+    ///
+    /// There is no checkpoint after the witness map. The coefficients of `h`
+    /// are field elements derived from the witness, which unlike the sums
+    /// would reveal it without any discrete logarithm, so a resumed proof
+    /// computes them again.
+    #[cfg(feature = "std")]
+    pub fn create_proof_with_checkpoints<C>(
+        &self,
+        circuit: C,
+        r: E::Fr,
+        s: E::Fr,
+        zeta: E::Fr,
+        checkpoints: &Checkpoints,
+    ) -> Result<Proof<E>, Error>
+    where
+        C: ConstraintSynthesizer<E::Fr>,
+        S::Error: Into<Error>,
+    {
+        self.create_proof_with_options(
+            circuit,
+            r,
+            s,
+            zeta,
+            RunOptions::new().with_checkpoints(checkpoints),
+        )
+    }
+
+    /// Create a proof using randomness `r`, `s` and `zeta`, observing the
+    /// run with `options`.
+    ///
+    /// With a [`Progress`](crate::Progress), the proof reports synthesis,
+    /// the FFTs and the MSM over each query. With a
+    /// [`CancellationToken`](crate::CancellationToken), it returns
+    /// [`Error::Cancelled`] soon after the token is cancelled. The proof is
+    /// the same as the one [`ProverContext::create_proof`] creates.
+    pub fn create_proof_with_options<C>(
+        &self,
+        circuit: C,
+        r: E::Fr,
+        s: E::Fr,
+        zeta: E::Fr,
+        options: RunOptions<'_>,
+    ) -> Result<Proof<E>, Error>
+    where
+        C: ConstraintSynthesizer<E::Fr>,
+        S::Error: Into<Error>,
+    {
+        let prover_time = start_phase!("BPR20::Prover with options");
+        let mut stages = options.stages();
+        stages.report(Phase::Synthesis, 0.0)?;
+        let (instance_assignment, witness_assignment) = self.witness(circuit)?;
+        stages.report(Phase::Synthesis, 1.0)?;
+        let randomness = ProofRandomness::new(self.pk, r, s, zeta);
+
+        stages.begin(|| {
+            Fingerprint::new(b"BPR20 proof")
+                .update(self.pk.vk())
+                .update(&self.pk.beta_g1())
+                .update(&self.pk.delta_g1())
+                .update(&instance_assignment)
+                .update(&witness_assignment)
+                .finish()
+        });
+        let prepared = self.prepare_witness_with_assignment::<_, Error>(
+            &instance_assignment,
+            &witness_assignment,
+            !randomness.r.is_zero(),
+            &stages,
+        )?;
+        let proof = prepared.prove(self.pk, randomness).map_err(Into::into)?;
+        stages.finish()?;
+        end_phase!(prover_time);
+
+        Ok(proof)
+    }
+
+    /// Create a proof using randomness `r`, `s` and `zeta`, together with
+    /// statistics of the proof.
+    ///
+    /// The proof is the same as the one [`ProverContext::create_proof`]
+    /// creates.
+    #[cfg(feature = "std")]
+    pub fn create_proof_with_stats<C>(
+        &self,
+        circuit: C,
+        r: E::Fr,
+        s: E::Fr,
+        zeta: E::Fr,
+    ) -> Result<(Proof<E>, ProofStats), Error>
+    where
+        C: ConstraintSynthesizer<E::Fr>,
+        S::Error: Into<Error>,
+    {
+        let start = Instant::now();
+        let recorder = Recorder::default();
+        let options = RunOptions::new().with_recorder(&recorder);
+        let proof = self.create_proof_with_options(circuit, r, s, zeta, options)?;
+        let total_duration = start.elapsed();
+
+        let num_variables =
+            self.matrices.num_instance_variables + self.matrices.num_witness_variables;
+        let mut msm_sizes = vec![(Query::A, num_variables)];
+        if !r.is_zero() {
+            msm_sizes.push((Query::BG1, num_variables));
+        }
+        msm_sizes.extend_from_slice(&[
+            (Query::BG2, num_variables),
+            (Query::H, self.domain.size() - 1),
+            (Query::L, self.matrices.num_witness_variables),
+        ]);
+        let stats = ProofStats {
+            circuit: CircuitStats {
+                num_constraints: self.matrices.num_constraints,
+                num_instance_variables: self.matrices.num_instance_variables,
+                num_witness_variables: self.matrices.num_witness_variables,
+                domain_size: self.domain.size(),
+            },
+            msm_sizes,
+            durations: recorder.into_durations(),
+            total_duration,
+        };
+
+        Ok((proof, stats))
+    }
+
+    /// Create a proof using randomness that was precomputed for the proving
+    /// key of this context with [`ProofRandomness::precompute`], consuming
+    /// it.
+    pub fn create_proof_with_precomputed<C>(
+        &self,
+        circuit: C,
+        randomness: ProofRandomness<E>,
+    ) -> Result<Proof<E>, S::Error>
+    where
+        C: ConstraintSynthesizer<E::Fr>,
+    {
+        let prover_time = start_phase!("BPR20::Prover with context");
+        let (instance_assignment, witness_assignment) = self.witness(circuit)?;
+        let proof = self.create_proof_with_assignment(
+            &instance_assignment,
+            &witness_assignment,
+            randomness,
+        )?;
+        end_phase!(prover_time);
+
+        Ok(proof)
+    }
+
+    /// Synthesize `circuit` and compute the sums over the proving key that
+    /// depend only on its witness, for use with [`reprove`].
+    pub fn prepare_witness<C>(&self, circuit: C) -> Result<PreparedWitness<E>, S::Error>
+    where
+        C: ConstraintSynthesizer<E::Fr>,
+    {
+        let (instance_assignment, witness_assignment) = self.witness(circuit)?;
+
+        self.prepare_witness_with_assignment(
+            &instance_assignment,
+            &witness_assignment,
+            true,
+            &Unobserved,
+        )
+    }
+
+    /// Commit to the values of the first `prefix.len()` witness variables
+    /// before the rest of the witness is known, computing their sums over
+    /// the A-, B- and L-queries.
+    ///
+    /// The proof is finished with [`ProverContext::finish_proof`] once the
+    /// whole witness is known. Fails with [`Error::WitnessPrefixMismatch`] if
+    /// `prefix` is longer than the witness.
+    pub fn commit_witness_prefix(&self, prefix: Vec<E::Fr>) -> Result<WitnessPrefix<E>, Error>
+    where
+        S::Error: Into<Error>,
+    {
+        if prefix.len() > self.matrices.num_witness_variables {
+            return Err(Error::WitnessPrefixMismatch);
+        }
+
+        let prefix_time = start_phase!("Commit to witness prefix");
+        let num_instance_variables = self.matrices.num_instance_variables;
+        let sums = query_sums(
+            self.pk,
+            &self.backend,
+            self.chunked_scalars([&[], &prefix], num_instance_variables),
+            self.chunked_scalars([&[], &prefix], 0),
+            self.chunked_scalars([&[], &[]], 0),
+            true,
+        )
+        .map_err(Into::into)?;
+        end_phase!(prefix_time);
+
+        Ok(WitnessPrefix {
+            values: prefix,
+            sums,
+        })
+    }
+
+    /// Finish a proof of `circuit` whose witness starts with the committed
+    /// `prefix`, consuming the precomputed `randomness`.
+    ///
+    /// Only the sums over the rest of the witness, the instance and the H
+    /// polynomial are computed. The proof is identical to the one
+    /// [`ProverContext::create_proof_with_precomputed`] creates. Fails with
+    /// [`Error::WitnessPrefixMismatch`] if the witness of `circuit` does not
+    /// start with `prefix`.
+    pub fn finish_proof<C>(
+        &self,
+        prefix: WitnessPrefix<E>,
+        circuit: C,
+        randomness: ProofRandomness<E>,
+    ) -> Result<Proof<E>, Error>
+    where
+        C: ConstraintSynthesizer<E::Fr>,
+        S::Error: Into<Error>,
+    {
+        let prover_time = start_phase!("BPR20::Prover with witness prefix");
+        let (instance_assignment, witness_assignment) = self.witness(circuit)?;
+        let num_committed = prefix.values.len();
+        if witness_assignment[..num_committed] != prefix.values[..] {
+            return Err(Error::WitnessPrefixMismatch);
+        }
+
+        let h =
+            self.witness_map::<_, Error>(&instance_assignment, &witness_assignment, &Unobserved)?;
+
+        let compute_b_g1 = !randomness.r.is_zero();
+        let rest = &witness_assignment[num_committed..];
+        let (rest_sums, instance_sums) = crate::join(
+            || {
+                query_sums(
+                    self.pk,
+                    &self.backend,
+                    self.chunked_scalars(
+                        [&[], rest],
+                        self.matrices.num_instance_variables + num_committed,
+                    ),
+                    self.chunked_scalars([&[], rest], num_committed),
+                    self.chunked_scalars([&[], &[]], 0),
+                    compute_b_g1,
+                )
+            },
+            || {
+                query_sums(
+                    self.pk,
+                    &self.backend,
+                    self.chunked_scalars([&instance_assignment, &[]], 0),
+                    self.chunked_scalars([&[], &[]], 0),
+                    self.chunked_scalars([&h, &[]], 0),
+                    compute_b_g1,
+                )
+            },
+        );
+        let prepared = prefix
+            .sums
+            .combine(rest_sums.map_err(Into::into)?)
+            .combine(instance_sums.map_err(Into::into)?);
+        let proof = prepared.prove(self.pk, randomness).map_err(Into::into)?;
+        end_phase!(prover_time);
+
+        Ok(proof)
+    }
+
+    /// Create zero-knowledge proofs for all `circuits`, sampling the
+    /// randomness for each proof via `rng`.
+    ///
+    /// Witnesses are synthesized sequentially, a batch at a time, and the
+    /// proofs of a batch are then created in parallel on the rayon pool.
+    pub fn create_random_proofs_batch<C, I, R>(
+        &self,
+        circuits: I,
+        rng: &mut R,
+    ) -> Result<Vec<Proof<E>>, S::Error>
+    where
+        C: ConstraintSynthesizer<E::Fr>,
+        I: IntoIterator<Item = C>,
+        R: Rng,
+    {
+        #[cfg(feature = "parallel")]
+        let batch_size = rayon::current_num_threads();
+        #[cfg(not(feature = "parallel"))]
+        let batch_size = 1;
+
+        let mut circuits = circuits.into_iter();
+        let mut proofs = Vec::new();
+        loop {
+            let batch = circuits
+                .by_ref()
+                .take(batch_size)
+                .map(|circuit| {
+                    let assignment = self.witness(circuit)?;
+                    Ok((assignment, sample_randomness::<E, R>(rng)))
+                })
+                .collect::<Result<Vec<_>, S::Error>>()?;
+            if batch.is_empty() {
+                break;
+            }
+
+            let batch_proofs = cfg_into_iter!(batch)
+                .map(
+                    |((instance_assignment, witness_assignment), (r, s, zeta))| {
+                        let randomness = ProofRandomness::new(self.pk, r, s, zeta);
+                        self.create_proof_with_assignment(
+                            &instance_assignment,
+                            &witness_assignment,
+                            randomness,
+                        )
+                    },
+                )
+                .collect::<Result<Vec<_>, S::Error>>()?;
+            proofs.extend(batch_proofs);
+        }
+
+        Ok(proofs)
+    }
 
-    // Compute C
+    /// Synthesize the instance and witness assignments of `circuit`, without
+    /// constructing its constraint matrices.
+    #[allow(clippy::type_complexity)]
+    fn witness<C>(&self, circuit: C) -> R1CSResult<(Vec<E::Fr>, Vec<E::Fr>)>
+    where
+        C: ConstraintSynthesizer<E::Fr>,
+    {
+        let cs = ConstraintSystem::new_ref();
+        cs.set_optimization_goal(OptimizationGoal::Constraints);
+        cs.set_mode(SynthesisMode::Prove {
+            construct_matrices: false,
+        });
+
+        // Synthesize the witness only; the matrices are already known.
+        let synthesis_time = start_phase!("Witness generation");
+        circuit.generate_constraints(cs.clone())?;
+        end_phase!(synthesis_time);
+
+        if cs.num_constraints() != self.matrices.num_constraints
+            || cs.num_instance_variables() != self.matrices.num_instance_variables
+            || cs.num_witness_variables() != self.matrices.num_witness_variables
+        {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+
+        // Move the assignments out instead of copying them, and release the
+        // spare capacity left over from synthesis.
+        let mut prover = cs.borrow_mut().unwrap();
+        let instance_assignment = core::mem::take(&mut prover.instance_assignment);
+        let mut witness_assignment = core::mem::take(&mut prover.witness_assignment);
+        witness_assignment.shrink_to_fit();
+        // Without its own matrices `cs` cannot check the assignment, so it is
+        // checked against the known ones.
+        debug_assert!(r1cs_to_qap::is_satisfied(
+            &self.matrices,
+            &instance_assignment,
+            &witness_assignment
+        ));
+
+        Ok((instance_assignment, witness_assignment))
+    }
+
+    fn create_proof_with_assignment(
+        &self,
+        instance_assignment: &[E::Fr],
+        witness_assignment: &[E::Fr],
+        randomness: ProofRandomness<E>,
+    ) -> Result<Proof<E>, S::Error> {
+        let prepared = self.prepare_witness_with_assignment::<_, S::Error>(
+            instance_assignment,
+            witness_assignment,
+            !randomness.r.is_zero(),
+            &Unobserved,
+        )?;
+
+        prepared.prove(self.pk, randomness)
+    }
+
+    fn prepare_witness_with_assignment<St, Err>(
+        &self,
+        instance_assignment: &[E::Fr],
+        witness_assignment: &[E::Fr],
+        compute_b_g1: bool,
+        stages: &St,
+    ) -> Result<PreparedWitness<E>, Err>
+    where
+        St: Stages,
+        S::Error: Into<Err>,
+        Err: From<St::Error> + Send,
+    {
+        // The coefficients of `h` are derived from the witness, so they are
+        // never saved, and are computed again by resumed proofs.
+        stages.report(Phase::Fft, 0.0)?;
+        let h = self.witness_map(instance_assignment, witness_assignment, stages)?;
+
+        // The MSMs can only be split into steps if their scalars are
+        // converted as they are needed.
+        let msm_chunk_size = match self.msm_chunk_size() {
+            None if stages.msm_steps() > 1 => Some(usize::MAX),
+            chunk_size => chunk_size,
+        };
+        prepare_witness_with_assignment::<E, S, B, St, Err>(
+            self.pk,
+            h,
+            instance_assignment,
+            witness_assignment,
+            msm_chunk_size,
+            &self.backend,
+            compute_b_g1,
+            stages,
+        )
+    }
+
+    /// Computes the coefficients of `h` for an assignment, with the FFTs done
+    /// by the proving key source and their progress reported to `stages`.
+    fn witness_map<St, Err>(
+        &self,
+        instance_assignment: &[E::Fr],
+        witness_assignment: &[E::Fr],
+        stages: &St,
+    ) -> Result<Vec<E::Fr>, Err>
+    where
+        St: Stages,
+        S::Error: Into<Err>,
+        Err: From<St::Error>,
+    {
+        let witness_map_time = start_phase!("R1CS to QAP witness map");
+        if let Some(subdomain) = self.fft_subdomain() {
+            let h = R1CStoQAP::witness_map_in_chunks(
+                &self.domain,
+                &subdomain,
+                &self.matrices,
+                instance_assignment,
+                witness_assignment,
+                |done| stages.report(Phase::Fft, done),
+            )?;
+            end_phase!(witness_map_time);
+
+            return Ok(h);
+        }
+
+        // There are four transforms: those of `a`, `b` and `c` to the coset,
+        // and that of `h` back from it.
+        let transforms_done = core::cell::Cell::new(0);
+        let report = |evaluations: usize| -> Result<(), Err> {
+            transforms_done.set(transforms_done.get() + evaluations);
+            stages.report(Phase::Fft, transforms_done.get() as f64 / 4.0)?;
+            Ok(())
+        };
+        let h = R1CStoQAP::witness_map_with_ffts(
+            &self.domain,
+            &self.matrices,
+            instance_assignment,
+            witness_assignment,
+            |evaluations| {
+                self.pk
+                    .evaluate_on_coset(&self.domain, evaluations)
+                    .map_err(Into::into)?;
+                report(evaluations.len())
+            },
+            |evaluations| {
+                self.pk
+                    .interpolate_on_coset(&self.domain, evaluations)
+                    .map_err(Into::into)?;
+                report(1)
+            },
+        )?;
+        end_phase!(witness_map_time);
+
+        Ok(h)
+    }
+
+    /// The subdomain over whose cosets the witness map evaluates the QAP
+    /// polynomials a chunk at a time, if holding another vector of domain size
+    /// would exceed the memory budget.
+    fn fft_subdomain(&self) -> Option<GeneralEvaluationDomain<E::Fr>> {
+        let budget = self.memory_budget?;
+        let element_size = core::mem::size_of::<E::Fr>();
+        let domain_size = self.domain.size();
+        if domain_size * element_size <= budget {
+            return None;
+        }
+
+        // Each chunk holds the evaluations of `a`, `b` and `c`, and a slice
+        // of one of them.
+        let max_chunk_size = core::cmp::max(budget / (4 * element_size), 1);
+        let mut chunk_size = 1;
+        while chunk_size * 2 <= max_chunk_size && chunk_size * 2 < domain_size {
+            chunk_size *= 2;
+        }
+        R1CStoQAP::subdomain(&self.domain, chunk_size)
+    }
+
+    /// The number of MSM scalars to convert at a time, if converting all of
+    /// them up front would exceed the memory budget.
+    fn msm_chunk_size(&self) -> Option<usize> {
+        let budget = self.memory_budget?;
+        let scalar_size = core::mem::size_of::<<E::Fr as PrimeField>::BigInt>();
+        let num_scalars = self.matrices.num_instance_variables
+            + self.matrices.num_witness_variables
+            + self.domain.size();
+        if num_scalars * scalar_size <= budget {
+            return None;
+        }
+
+        // Up to five MSMs run concurrently, each holding one chunk.
+        Some(core::cmp::max(budget / (5 * scalar_size), 1))
+    }
+
+    /// The scalars `parts` starting at index `start`, converted a chunk at a
+    /// time if there is a memory budget and all at once otherwise.
+    fn chunked_scalars<'b>(&self, parts: [&'b [E::Fr]; 2], start: usize) -> MsmScalars<'b, E::Fr> {
+        MsmScalars::Chunked {
+            parts,
+            start,
+            chunk_size: self.msm_chunk_size().unwrap_or(usize::MAX),
+        }
+    }
+}
+
+/// Create zero-knowledge proofs for many circuits that share the proving key
+/// `pk`. This method samples randomness for zero knowledges via `rng`.
+///
+/// The constraint matrices are taken from the first circuit and, together
+/// with the evaluation domain, shared by all proofs; see
+/// [`ProverContext::create_random_proofs_batch`].
+pub fn create_random_proofs_batch<E, C, I, R>(
+    circuits: I,
+    pk: &ProvingKey<E>,
+    rng: &mut R,
+) -> R1CSResult<Vec<Proof<E>>>
+where
+    E: PairingEngine,
+    C: ConstraintSynthesizer<E::Fr>,
+    I: IntoIterator<Item = C>,
+    R: Rng,
+{
+    let mut circuits = circuits.into_iter();
+    let first = match circuits.next() {
+        Some(circuit) => circuit,
+        None => return Ok(Vec::new()),
+    };
+
+    // Synthesize the first circuit with its matrices, so that the circuit
+    // does not have to be synthesized a second time.
+    let cs = ConstraintSystem::new_ref();
+    cs.set_optimization_goal(OptimizationGoal::Constraints);
+    first.generate_constraints(cs.clone())?;
+    debug_assert!(cs.is_satisfied().unwrap());
+    cs.finalize();
+
+    let matrices = cs.to_matrices().ok_or(SynthesisError::MissingCS)?;
+    let ctx = ProverContext::new(pk, matrices)?;
+
+    let randomness = ProofRandomness::precompute(pk, rng);
     let prover = cs.borrow().unwrap();
+    let mut proofs = vec![ctx.create_proof_with_assignment(
+        &prover.instance_assignment,
+        &prover.witness_assignment,
+        randomness,
+    )?];
+    drop(prover);
+    drop(cs);
 
-    let aux_assignment = cfg_iter!(prover.witness_assignment)
-        .map(|s| s.into_repr())
-        .collect::<Vec<_>>();
-    
- 
-    
-    let delta_prime_g1 = pk.delta_g1.clone().mul(zeta).into_affine();
-    let delta_prime_g2 = pk.vk.delta_g2.clone().mul(zeta).into_affine();
+    proofs.extend(ctx.create_random_proofs_batch(circuits, rng)?);
 
+    Ok(proofs)
+}
 
-    let r_s_delta_g1 = delta_prime_g1.mul((&(r*s)).into_repr());
-    
-    end_timer!(c_acc_time);
+/// A prefix of the witness that was committed to before the rest of the
+/// witness was known, together with its sums over the proving key.
+///
+/// Like a [`PreparedWitness`], this must be kept as secret as the witness.
+pub struct WitnessPrefix<E: PairingEngine> {
+    values: Vec<E::Fr>,
+    sums: PreparedWitness<E>,
+}
 
-    let input_assignment = prover.instance_assignment[1..]
-        .iter()
-        .map(|s| s.into_repr())
-        .collect::<Vec<_>>();
+impl<E: PairingEngine> WitnessPrefix<E> {
+    /// The number of witness variables in the prefix.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
 
-    
-    
+    /// Returns `true` if the prefix has no witness variables.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// The sums over a proving key that depend only on a witness, from which
+/// proofs of that witness are created with [`reprove`].
+///
+/// These determine the witness as much as the witness itself does, so they
+/// must be kept as secret as the witness.
+pub struct PreparedWitness<E: PairingEngine> {
+    /// The `delta_g1` of the proving key this was computed for.
+    delta_g1: E::G1Affine,
+    /// `sum_i a_i * a_query[i]`, including the `one` variable.
+    a_sum: E::G1Projective,
+    /// `sum_i a_i * b_g1_query[i]`, or zero if it was not needed.
+    b_g1_sum: E::G1Projective,
+    /// `sum_i a_i * b_g2_query[i]`.
+    b_g2_sum: E::G2Projective,
+    /// The unscaled sum of the H- and L-query MSMs.
+    h_l_sum: E::G1Projective,
+}
+
+impl<E: PairingEngine> PreparedWitness<E> {
+    /// The sums of two disjoint parts of an assignment, for the same
+    /// proving key.
+    fn combine(self, other: Self) -> Self {
+        debug_assert!(self.delta_g1 == other.delta_g1);
+        Self {
+            delta_g1: self.delta_g1,
+            a_sum: self.a_sum + &other.a_sum,
+            b_g1_sum: self.b_g1_sum + &other.b_g1_sum,
+            b_g2_sum: self.b_g2_sum + &other.b_g2_sum,
+            h_l_sum: self.h_l_sum + &other.h_l_sum,
+        }
+    }
+
+    /// Create a proof from these sums, consuming the precomputed
+    /// `randomness`.
+    ///
+    /// `H` and `L` are computed unscaled, and `C` is scaled by
+    /// `1/(zeta + m)` once at the end.
+    fn prove<S: ProvingKeySource<E>>(
+        &self,
+        pk: &S,
+        randomness: ProofRandomness<E>,
+    ) -> Result<Proof<E>, S::Error> {
+        if randomness.delta_g1 != pk.delta_g1() || self.delta_g1 != pk.delta_g1() {
+            return Err(SynthesisError::MalformedVerifyingKey.into());
+        }
+        let vk = pk.vk();
+        let ProofRandomness {
+            r,
+            s,
+            zeta,
+            delta_prime_g2,
+            r_delta_prime_g1,
+            s_delta_prime_g1,
+            s_delta_prime_g2,
+            rs_delta_prime_g1,
+            ..
+        } = randomness;
+
+        let c_time = start_phase!("Finish C");
+        let mut g_a = r_delta_prime_g1 + &self.a_sum;
+        g_a.add_assign_mixed(&vk.alpha_g1);
+        let g_a = g_a.into_affine();
 
-    let assignment = [&input_assignment[..], &aux_assignment[..]].concat();
-    drop(aux_assignment);
+        let g1_b = if !r.is_zero() {
+            let mut g1_b = s_delta_prime_g1 + &self.b_g1_sum;
+            g1_b.add_assign_mixed(&pk.beta_g1());
+            g1_b
+        } else {
+            E::G1Projective::zero()
+        };
 
-    // Compute A
-    let a_acc_time = start_timer!(|| "Compute A");
-    let r_g1 = delta_prime_g1.mul(r);
+        let mut g2_b = s_delta_prime_g2 + &self.b_g2_sum;
+        g2_b.add_assign_mixed(&vk.beta_g2);
+        let g2_b = g2_b.into_affine();
 
-    let g_a = calculate_coeff(r_g1, &pk.a_query, pk.vk.alpha_g1, &assignment);
+        let m_fr = proof_challenge::<E>(&g_a, &g2_b, &delta_prime_g2);
+        let zeta_m_inv = (zeta + m_fr)
+            .inverse()
+            .ok_or(SynthesisError::UnexpectedIdentity)?;
 
-    let s_g_a = g_a.mul(&s.into_repr());
-    end_timer!(a_acc_time);
+        // C = (zeta * (s * A + r * B - r * s * delta') + L + H) / (zeta + m)
+        let mut g_c = g_a.mul(zeta * s);
+        g_c += &g1_b.mul(&(zeta * r).into_repr());
+        g_c -= &rs_delta_prime_g1;
+        g_c += &self.h_l_sum;
+        let g_c = g_c.mul(&zeta_m_inv.into_repr());
+        end_phase!(c_time);
+
+        Ok(Proof {
+            a: g_a,
+            b: g2_b,
+            c: g_c.into_affine(),
+            delta_prime: delta_prime_g2,
+        })
+    }
+}
+
+/// Compute the witness sums from the witness polynomial `h` and the full
+/// variable assignment, computing the B-query sum in `E::G1` only if
+/// `compute_b_g1` is set.
+///
+/// The MSMs over the A-, B-, H- and L-queries all run concurrently. If
+/// `msm_chunk_size` is set, the MSM scalars are converted to bigints that
+/// many at a time instead of all up front. The MSMs run on `backend`, and
+/// their results are saved as `stages`.
+#[allow(clippy::too_many_arguments)]
+fn prepare_witness_with_assignment<E, S, B, St, Err>(
+    pk: &S,
+    h: Vec<E::Fr>,
+    instance_assignment: &[E::Fr],
+    witness_assignment: &[E::Fr],
+    msm_chunk_size: Option<usize>,
+    backend: &B,
+    compute_b_g1: bool,
+    stages: &St,
+) -> Result<PreparedWitness<E>, Err>
+where
+    E: PairingEngine,
+    S: ProvingKeySource<E>,
+    B: MsmBackend,
+    St: Stages,
+    S::Error: Into<Err>,
+    Err: From<St::Error> + Send,
+{
+    // The full assignment, starting with the `one` variable, whose witness
+    // part is shared with the L-query. Without a chunk size the scalars are
+    // converted to bigints up front.
+    let num_instance_variables = instance_assignment.len();
+    let assignment;
+    let h_assignment;
+    let (scalars, aux_scalars, h_scalars) = match msm_chunk_size {
+        None => {
+            assignment = cfg_iter!(instance_assignment)
+                .chain(cfg_iter!(witness_assignment))
+                .map(|s| s.into_repr())
+                .collect::<Vec<_>>();
+            h_assignment = cfg_into_iter!(h).map(|s| s.into_repr()).collect::<Vec<_>>();
+            (
+                MsmScalars::Converted(&assignment),
+                MsmScalars::Converted(&assignment[num_instance_variables..]),
+                MsmScalars::Converted(&h_assignment),
+            )
+        },
+        Some(chunk_size) => (
+            MsmScalars::Chunked {
+                parts: [instance_assignment, witness_assignment],
+                start: 0,
+                chunk_size,
+            },
+            MsmScalars::Chunked {
+                parts: [&[], witness_assignment],
+                start: 0,
+                chunk_size,
+            },
+            MsmScalars::Chunked {
+                parts: [&h, &[]],
+                start: 0,
+                chunk_size,
+            },
+        ),
+    };
 
-    // Compute B in G1 if needed
-    let g1_b = if !r.is_zero() {
-        let b_g1_acc_time = start_timer!(|| "Compute B in G1");
-        let s_g1 = delta_prime_g1.mul(s);
-        let g1_b = calculate_coeff(s_g1, &pk.b_g1_query, pk.beta_g1, &assignment);
+    query_sums_in_stages(
+        pk,
+        backend,
+        scalars,
+        aux_scalars,
+        h_scalars,
+        compute_b_g1,
+        stages,
+    )
+}
 
-        end_timer!(b_g1_acc_time);
+/// Compute the sums of `scalars` over the A- and B-queries, `aux_scalars`
+/// over the L-query and `h_scalars` over the H-query concurrently on
+/// `backend`, computing the B-query sum in `E::G1` only if `compute_b_g1` is
+/// set.
+fn query_sums<E, S, B>(
+    pk: &S,
+    backend: &B,
+    scalars: MsmScalars<'_, E::Fr>,
+    aux_scalars: MsmScalars<'_, E::Fr>,
+    h_scalars: MsmScalars<'_, E::Fr>,
+    compute_b_g1: bool,
+) -> Result<PreparedWitness<E>, S::Error>
+where
+    E: PairingEngine,
+    S: ProvingKeySource<E>,
+    B: MsmBackend,
+{
+    query_sums_in_stages(
+        pk,
+        backend,
+        scalars,
+        aux_scalars,
+        h_scalars,
+        compute_b_g1,
+        &Unobserved,
+    )
+}
 
-        g1_b
-    } else {
-        E::G1Projective::zero()
+/// Compute the sums like [`query_sums`], saving the sum over each query as
+/// a stage of `stages`.
+fn query_sums_in_stages<E, S, B, St, Err>(
+    pk: &S,
+    backend: &B,
+    scalars: MsmScalars<'_, E::Fr>,
+    aux_scalars: MsmScalars<'_, E::Fr>,
+    h_scalars: MsmScalars<'_, E::Fr>,
+    compute_b_g1: bool,
+    stages: &St,
+) -> Result<PreparedWitness<E>, Err>
+where
+    E: PairingEngine,
+    S: ProvingKeySource<E>,
+    B: MsmBackend,
+    St: Stages,
+    S::Error: Into<Err>,
+    Err: From<St::Error> + Send,
+{
+    let g1_sum = |name: &str, query: G1Query, scalars: MsmScalars<'_, E::Fr>| {
+        let phase = Phase::Msm(match query {
+            G1Query::A => Query::A,
+            G1Query::BG1 => Query::BG1,
+            G1Query::H => Query::H,
+            G1Query::L => Query::L,
+        });
+        stages
+            .stage(phase, name, || {
+                msm_in_steps(stages, phase, scalars, |scalars| {
+                    pk.msm_g1(backend, query, scalars).map_err(Into::into)
+                })
+                .map(|sum| sum.into_affine())
+            })
+            .map(|sum| sum.into_projective())
     };
 
-    // Compute B in G2
-    let b_g2_acc_time = start_timer!(|| "Compute B in G2");
-    let s_g2 = delta_prime_g2.mul(s);
-    let g2_b = calculate_coeff(s_g2, &pk.b_g2_query, pk.vk.beta_g2, &assignment);
-    let r_g1_b = g1_b.mul(&r.into_repr());
-    drop(assignment);
-
-    end_timer!(b_g2_acc_time);
-
-    let c_time = start_timer!(|| "Finish C");
-
-    //Compute the hash message
-    let hash = Blake2b::new()
-    .chain(to_bytes!(&g_a.into_affine()).unwrap())
-    .chain(to_bytes!(&g2_b.into_affine()).unwrap())
-    .chain(to_bytes!(&delta_prime_g2).unwrap());
-    let mut output = [0u8; 64];
-    output.copy_from_slice(&hash.finalize());
-    
-    let m_fr = E::Fr::from_le_bytes_mod_order(&output);
-    //println!("m_fr prover {0}", m_fr);
-    let factor = zeta * (zeta + m_fr).inverse().unwrap();   
-    let zeta_m_inv = (zeta + m_fr).inverse().unwrap();
-
-    let h_assignment = cfg_into_iter!(h).map(|s| (s*zeta_m_inv).into()).collect::<Vec<_>>();
-    let h_acc = VariableBaseMSM::multi_scalar_mul(&pk.h_query, &h_assignment);
-    let aux_assignment_unscaled = cfg_iter!(prover.witness_assignment)
-        .map(|s| (*s*zeta_m_inv).into()) 
-        .collect::<Vec<_>>();
-
-    let l_aux_acc = VariableBaseMSM::multi_scalar_mul(&pk.l_query, &aux_assignment_unscaled);
-    
-    let mut g_c = s_g_a.mul(&factor.into_repr());
-    g_c += &r_g1_b.mul(&factor.into_repr());
-    g_c -= &r_s_delta_g1.mul(&factor.into_repr());
-    g_c += &l_aux_acc;
-    g_c += &h_acc;
-    end_timer!(c_time);
-
-    end_timer!(prover_time);
-    
-    
-    Ok(Proof {
-        a: g_a.into_affine(),
-        b: g2_b.into_affine(),
-        c: g_c.into_affine(),
-        delta_prime: delta_prime_g2,
+    let msm_time = start_phase!("Compute A, B, H and L");
+    let ((a_sum, b_g1_sum), (b_g2_sum, (h_sum, l_sum))) = crate::join(
+        || {
+            crate::join(
+                || g1_sum("a_sum", G1Query::A, scalars),
+                || {
+                    if compute_b_g1 {
+                        g1_sum("b_g1_sum", G1Query::BG1, scalars)
+                    } else {
+                        Ok(E::G1Projective::zero())
+                    }
+                },
+            )
+        },
+        || {
+            crate::join(
+                || {
+                    let phase = Phase::Msm(Query::BG2);
+                    stages
+                        .stage(phase, "b_g2_sum", || {
+                            msm_in_steps(stages, phase, scalars, |scalars| {
+                                pk.msm_b_g2(backend, scalars).map_err(Into::into)
+                            })
+                            .map(|sum| sum.into_affine())
+                        })
+                        .map(|sum| sum.into_projective())
+                },
+                || {
+                    crate::join(
+                        || g1_sum("h_sum", G1Query::H, h_scalars),
+                        || g1_sum("l_sum", G1Query::L, aux_scalars),
+                    )
+                },
+            )
+        },
+    );
+    end_phase!(msm_time);
+
+    Ok(PreparedWitness {
+        delta_g1: pk.delta_g1(),
+        a_sum: a_sum?,
+        b_g1_sum: b_g1_sum?,
+        b_g2_sum: b_g2_sum?,
+        h_l_sum: h_sum? + &l_sum?,
     })
 }
 
+/// Computes the MSM `msm` over `scalars` in as many steps as `stages` asks
+/// for, reporting the progress of `phase` after each step. The MSM is only
+/// split if its scalars are converted a chunk at a time.
+fn msm_in_steps<F, G, St, Err>(
+    stages: &St,
+    phase: Phase,
+    scalars: MsmScalars<'_, F>,
+    msm: impl Fn(MsmScalars<'_, F>) -> Result<G, Err>,
+) -> Result<G, Err>
+where
+    F: PrimeField,
+    G: ProjectiveCurve,
+    St: Stages,
+    Err: From<St::Error>,
+{
+    let steps = stages.msm_steps();
+    if steps == 1 || scalars.chunk_size().is_none() {
+        return msm(scalars);
+    }
+
+    let (start, len) = (scalars.start(), scalars.len());
+    let step_size = core::cmp::max((len - start + steps - 1) / steps, 1);
+    let mut sum = G::zero();
+    for lo in (start..len).step_by(step_size) {
+        let hi = core::cmp::min(lo + step_size, len);
+        sum += &msm(scalars.slice(lo..hi))?;
+        stages.report(phase, (hi - start) as f64 / (len - start) as f64)?;
+    }
+    Ok(sum)
+}
+
+/// The queries of a proving key in `E::G1`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum G1Query {
+    /// The elements `a_i * G`.
+    A,
+    /// The elements `b_i * G`.
+    BG1,
+    /// The elements `h_i * G`.
+    H,
+    /// The elements `l_i * G`.
+    L,
+}
+
+/// A proving key from which the prover reads the elements it needs.
+///
+/// The MSMs over the queries go through the source, so that the queries do
+/// not have to be held in memory.
+pub trait ProvingKeySource<E: PairingEngine>: Sync {
+    /// The error returned when the proving key cannot be read.
+    type Error: From<SynthesisError> + Send;
+
+    /// The underlying verification key.
+    fn vk(&self) -> &VerifyingKey<E>;
+
+    /// The element `beta * G` in `E::G1`.
+    fn beta_g1(&self) -> E::G1Affine;
+
+    /// The element `delta * G` in `E::G1`.
+    fn delta_g1(&self) -> E::G1Affine;
+
+    /// The number of instance and witness variables of the circuit, which is
+    /// the length of the A-query.
+    fn num_variables(&self) -> usize;
 
-fn calculate_coeff<G: AffineCurve>(
-    initial: G::Projective,
-    query: &[G],
-    vk_param: G,
-    assignment: &[<G::ScalarField as PrimeField>::BigInt],
-) -> G::Projective {
-    let el = query[0];
-    let acc = VariableBaseMSM::multi_scalar_mul(&query[1..], assignment);
+    /// Computes `sum_i scalars[i] * query[i]` on `backend` for a query in
+    /// `E::G1`.
+    fn msm_g1<B: MsmBackend>(
+        &self,
+        backend: &B,
+        query: G1Query,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> Result<E::G1Projective, Self::Error>;
 
-    let mut res = initial;
-    res.add_assign_mixed(&el);
-    res += &acc;
-    res.add_assign_mixed(&vk_param);
+    /// Computes `sum_i scalars[i] * b_g2_query[i]` on `backend`.
+    fn msm_b_g2<B: MsmBackend>(
+        &self,
+        backend: &B,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> Result<E::G2Projective, Self::Error>;
 
-    res
+    /// Interpolates each of `evaluations` over `domain` and evaluates it over
+    /// the coset of `domain`, in place, for the witness map.
+    fn evaluate_on_coset(
+        &self,
+        domain: &GeneralEvaluationDomain<E::Fr>,
+        evaluations: &mut [Vec<E::Fr>],
+    ) -> Result<(), Self::Error> {
+        for evaluations in evaluations.iter_mut() {
+            domain.ifft_in_place(evaluations);
+            domain.coset_fft_in_place(evaluations);
+        }
+        Ok(())
+    }
+
+    /// Interpolates `evaluations` over the coset of `domain`, in place, for
+    /// the witness map.
+    fn interpolate_on_coset(
+        &self,
+        domain: &GeneralEvaluationDomain<E::Fr>,
+        evaluations: &mut Vec<E::Fr>,
+    ) -> Result<(), Self::Error> {
+        domain.coset_ifft_in_place(evaluations);
+        Ok(())
+    }
+}
+
+impl<E: PairingEngine> ProvingKeySource<E> for ProvingKey<E> {
+    type Error = SynthesisError;
+
+    fn vk(&self) -> &VerifyingKey<E> {
+        &self.vk
+    }
+
+    fn beta_g1(&self) -> E::G1Affine {
+        self.beta_g1
+    }
+
+    fn delta_g1(&self) -> E::G1Affine {
+        self.delta_g1
+    }
+
+    fn num_variables(&self) -> usize {
+        self.a_query.len()
+    }
+
+    fn msm_g1<B: MsmBackend>(
+        &self,
+        backend: &B,
+        query: G1Query,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> R1CSResult<E::G1Projective> {
+        let query = match query {
+            G1Query::A => &self.a_query,
+            G1Query::BG1 => &self.b_g1_query,
+            G1Query::H => &self.h_query,
+            G1Query::L => &self.l_query,
+        };
+        Ok(scalars.msm(backend, query))
+    }
+
+    fn msm_b_g2<B: MsmBackend>(
+        &self,
+        backend: &B,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> R1CSResult<E::G2Projective> {
+        Ok(scalars.msm(backend, &self.b_g2_query))
+    }
+}
+
+/// The scalars of an MSM, either converted to their bigint representation up
+/// front or converted a chunk at a time to bound the prover's memory.
+#[derive(Clone, Copy, Debug)]
+pub enum MsmScalars<'a, F: PrimeField> {
+    /// Scalars that are already converted to their bigint representation.
+    Converted(&'a [F::BigInt]),
+    /// Scalars that are converted `chunk_size` at a time.
+    Chunked {
+        /// The scalars from index `start` on are the concatenation of
+        /// `parts`.
+        parts: [&'a [F]; 2],
+        /// The index of the first scalar in `parts`. The scalars before it
+        /// are zero.
+        start: usize,
+        /// The number of scalars to convert at a time.
+        chunk_size: usize,
+    },
+}
+
+impl<'a, F: PrimeField> MsmScalars<'a, F> {
+    /// The number of scalars.
+    pub fn len(&self) -> usize {
+        match self {
+            MsmScalars::Converted(scalars) => scalars.len(),
+            MsmScalars::Chunked { parts, start, .. } => start + parts[0].len() + parts[1].len(),
+        }
+    }
+
+    /// The index of the first scalar that may be non-zero, from which on
+    /// sources need to read the bases.
+    pub fn start(&self) -> usize {
+        match self {
+            MsmScalars::Converted(_) => 0,
+            MsmScalars::Chunked { start, .. } => *start,
+        }
+    }
+
+    /// Returns `true` if there are no scalars.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of scalars to convert at a time, if they are not converted
+    /// up front.
+    pub fn chunk_size(&self) -> Option<usize> {
+        match self {
+            MsmScalars::Converted(_) => None,
+            MsmScalars::Chunked { chunk_size, .. } => Some(*chunk_size),
+        }
+    }
+
+    /// The bigint representation of the scalars in `range`, which is only
+    /// computed if the scalars are not converted already.
+    pub fn to_repr(&self, range: Range<usize>) -> Cow<'a, [F::BigInt]> {
+        match self {
+            MsmScalars::Converted(scalars) => Cow::Borrowed(&scalars[range]),
+            MsmScalars::Chunked { parts, start, .. } => {
+                // The leading zeros, and the rest of the range within `parts`.
+                let num_zeros = range.end.min(*start).saturating_sub(range.start);
+                let range = range.start.max(*start) - start..range.end.max(*start) - start;
+                let n = parts[0].len();
+                let first = &parts[0][range.start.min(n)..range.end.min(n)];
+                let second = &parts[1][range.start.saturating_sub(n)..range.end.saturating_sub(n)];
+                let mut scalars = vec![F::BigInt::default(); num_zeros];
+                scalars.extend(
+                    cfg_iter!(first)
+                        .chain(cfg_iter!(second))
+                        .map(|s| s.into_repr())
+                        .collect::<Vec<_>>(),
+                );
+                Cow::Owned(scalars)
+            },
+        }
+    }
+
+    /// The scalars in `range`, with the scalars before it set to zero. Only
+    /// scalars that are converted a chunk at a time can be sliced.
+    pub(crate) fn slice(&self, range: Range<usize>) -> Self {
+        match self {
+            MsmScalars::Converted(_) => panic!("converted scalars cannot be sliced"),
+            MsmScalars::Chunked {
+                parts,
+                start,
+                chunk_size,
+            } => {
+                // The range within `parts`.
+                let lo = range.start.saturating_sub(*start);
+                let hi = range.end.saturating_sub(*start);
+                let n = parts[0].len();
+                MsmScalars::Chunked {
+                    parts: [
+                        &parts[0][lo.min(n)..hi.min(n)],
+                        &parts[1][lo.saturating_sub(n)..hi.saturating_sub(n)],
+                    ],
+                    start: range.start.max(*start),
+                    chunk_size: *chunk_size,
+                }
+            },
+        }
+    }
+
+    /// Computes `sum_i scalars[i] * bases[i]` on `backend`, ignoring the
+    /// scalars beyond the end of `bases` and vice versa.
+    pub fn msm<G, B>(self, backend: &B, bases: &[G]) -> G::Projective
+    where
+        G: AffineCurve<ScalarField = F>,
+        B: MsmBackend,
+    {
+        self.msm_range(backend, bases, 0)
+    }
+
+    /// Computes `sum_i scalars[offset + i] * bases[i]` on `backend`, for
+    /// computing an MSM over a range of a query that starts at `offset`.
+    pub fn msm_range<G, B>(self, backend: &B, bases: &[G], offset: usize) -> G::Projective
+    where
+        G: AffineCurve<ScalarField = F>,
+        B: MsmBackend,
+    {
+        match self {
+            MsmScalars::Converted(scalars) => {
+                let scalars = scalars.get(offset..).unwrap_or(&[]);
+                backend.variable_base_msm(bases, scalars)
+            },
+            MsmScalars::Chunked {
+                parts,
+                start,
+                chunk_size,
+            } => {
+                let mut acc = G::Projective::zero();
+                let mut start = start;
+                for part in parts.iter() {
+                    // The overlap of this part with `offset..offset + bases.len()`.
+                    let end = start + part.len();
+                    let lo = core::cmp::max(start, offset);
+                    let hi = core::cmp::min(end, offset + bases.len());
+                    if lo < hi {
+                        let part_bases = &bases[lo - offset..hi - offset];
+                        let part_scalars = &part[lo - start..hi - start];
+                        for (bases, scalars) in part_bases
+                            .chunks(chunk_size)
+                            .zip(part_scalars.chunks(chunk_size))
+                        {
+                            let scalars = cfg_iter!(scalars)
+                                .map(|s| s.into_repr())
+                                .collect::<Vec<_>>();
+                            acc += &backend.variable_base_msm(bases, &scalars);
+                        }
+                    }
+                    start = end;
+                }
+                acc
+            },
+        }
+    }
 }
@@ -1,16 +1,24 @@
 use ark_ff::{One, PrimeField, Zero};
 use ark_poly::EvaluationDomain;
-use ark_std::{cfg_iter, cfg_iter_mut, vec};
+use ark_std::{cfg_chunks_mut, cfg_iter, cfg_iter_mut, vec};
 
 use crate::Vec;
-use ark_relations::r1cs::{ConstraintSystemRef, Result as R1CSResult, SynthesisError};
-use core::ops::{AddAssign, Deref};
+use ark_relations::r1cs::{
+    ConstraintMatrices, ConstraintSystemRef, Result as R1CSResult, SynthesisError,
+};
+use core::ops::{AddAssign, Deref, Range};
 
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
 
+/// Evaluates a row of a constraint matrix over the assignment whose first
+/// variables are `instance` and whose remaining variables are `witness`.
 #[inline]
-fn evaluate_constraint<'a, LHS, RHS, R>(terms: &'a [(LHS, usize)], assignment: &'a [RHS]) -> R
+fn evaluate_constraint<'a, LHS, RHS, R>(
+    terms: &'a [(LHS, usize)],
+    instance: &'a [RHS],
+    witness: &'a [RHS],
+) -> R
 where
     LHS: One + Send + Sync + PartialEq,
     RHS: Send + Sync + core::ops::Mul<&'a LHS, Output = RHS> + Copy,
@@ -23,7 +31,11 @@ where
     let zero = R::zero();
 
     let res = cfg_iter!(terms).fold(zero, |mut sum, (coeff, index)| {
-        let val = &assignment[*index];
+        let val = if *index < instance.len() {
+            &instance[*index]
+        } else {
+            &witness[*index - instance.len()]
+        };
 
         if coeff.is_one() {
             sum += *val;
@@ -41,6 +53,22 @@ where
     return res;
 }
 
+/// Returns `true` if the assignment whose first variables are `instance` and
+/// whose remaining variables are `witness` satisfies the constraints of
+/// `matrices`.
+pub(crate) fn is_satisfied<F: PrimeField>(
+    matrices: &ConstraintMatrices<F>,
+    instance: &[F],
+    witness: &[F],
+) -> bool {
+    (0..matrices.num_constraints).all(|i| {
+        let a: F = evaluate_constraint(&matrices.a[i], instance, witness);
+        let b: F = evaluate_constraint(&matrices.b[i], instance, witness);
+        let c: F = evaluate_constraint(&matrices.c[i], instance, witness);
+        a * b == c
+    })
+}
+
 pub(crate) struct R1CStoQAP;
 
 impl R1CStoQAP {
@@ -58,9 +86,9 @@ impl R1CStoQAP {
         let zt = domain.evaluate_vanishing_polynomial(*t);
 
         // Evaluate all Lagrange polynomials
-        let coefficients_time = start_timer!(|| "Evaluate Lagrange coefficients");
+        let coefficients_time = start_phase!("Evaluate Lagrange coefficients");
         let u = domain.evaluate_all_lagrange_coefficients(*t);
-        end_timer!(coefficients_time);
+        end_phase!(coefficients_time);
 
         let qap_num_variables = (cs.num_instance_variables() - 1) + cs.num_witness_variables();
 
@@ -90,25 +118,123 @@ impl R1CStoQAP {
         Ok((a, b, c, zt, qap_num_variables, domain_size))
     }
 
+    /// Evaluates the QAP polynomials `a`, `b` and `c` of the variables in
+    /// `variables` at the point where the Lagrange coefficients of the domain
+    /// are `u`, as [`R1CStoQAP::instance_map_with_evaluation`] does for all
+    /// variables at once.
+    ///
+    /// All constraints are scanned for the variables in the range, so that
+    /// only the evaluations of the range are held.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn evaluate_variables<F: PrimeField>(
+        matrices: &ConstraintMatrices<F>,
+        u: &[F],
+        variables: Range<usize>,
+    ) -> (Vec<F>, Vec<F>, Vec<F>) {
+        let mut a = vec![F::zero(); variables.len()];
+        let mut b = vec![F::zero(); variables.len()];
+        let mut c = vec![F::zero(); variables.len()];
+
+        // The instance variables are also the inputs of the rows after the
+        // constraints.
+        let num_constraints = matrices.num_constraints;
+        let inputs =
+            variables.start..core::cmp::min(variables.end, matrices.num_instance_variables);
+        for index in inputs {
+            a[index - variables.start] = u[num_constraints + index];
+        }
+
+        for (i, u_i) in u.iter().enumerate().take(num_constraints) {
+            for (matrix, evaluations) in [
+                (&matrices.a, &mut a),
+                (&matrices.b, &mut b),
+                (&matrices.c, &mut c),
+            ] {
+                for &(ref coeff, index) in &matrix[i] {
+                    if variables.contains(&index) {
+                        evaluations[index - variables.start] += &(*u_i * coeff);
+                    }
+                }
+            }
+        }
+
+        (a, b, c)
+    }
+
     #[inline]
     pub(crate) fn witness_map<F: PrimeField, D: EvaluationDomain<F>>(
         prover: ConstraintSystemRef<F>,
     ) -> R1CSResult<Vec<F>> {
         let matrices = prover.to_matrices().unwrap();
-        let zero = F::zero();
         let num_inputs = prover.num_instance_variables();
         let num_constraints = prover.num_constraints();
         let cs = prover.borrow().unwrap();
         let prover = cs.deref();
 
-        let full_assignment = [
-            prover.instance_assignment.as_slice(),
-            prover.witness_assignment.as_slice(),
-        ]
-        .concat();
-
         let domain =
             D::new(num_constraints + num_inputs).ok_or(SynthesisError::PolynomialDegreeTooLarge)?;
+
+        Ok(Self::witness_map_from_matrices(
+            &domain,
+            &matrices,
+            &prover.instance_assignment,
+            &prover.witness_assignment,
+        ))
+    }
+
+    /// Computes the coefficients of `h` from precomputed constraint matrices
+    /// and evaluation domain, so that repeated proofs for one circuit do not
+    /// have to rebuild either.
+    #[inline]
+    pub(crate) fn witness_map_from_matrices<F: PrimeField, D: EvaluationDomain<F>>(
+        domain: &D,
+        matrices: &ConstraintMatrices<F>,
+        instance_assignment: &[F],
+        witness_assignment: &[F],
+    ) -> Vec<F> {
+        let result = Self::witness_map_with_ffts::<_, _, core::convert::Infallible>(
+            domain,
+            matrices,
+            instance_assignment,
+            witness_assignment,
+            |polys| {
+                for poly in polys {
+                    domain.ifft_in_place(poly);
+                    domain.coset_fft_in_place(poly);
+                }
+                Ok(())
+            },
+            |poly| {
+                domain.coset_ifft_in_place(poly);
+                Ok(())
+            },
+        );
+        match result {
+            Ok(h) => h,
+            Err(e) => match e {},
+        }
+    }
+
+    /// Computes the coefficients of `h` like
+    /// [`R1CStoQAP::witness_map_from_matrices`], but with the FFTs done by
+    /// `evaluate_on_coset`, which maps evaluations over `domain` to
+    /// evaluations over its coset, and `interpolate_on_coset`, which maps
+    /// evaluations over the coset to coefficients.
+    pub(crate) fn witness_map_with_ffts<F, D, E>(
+        domain: &D,
+        matrices: &ConstraintMatrices<F>,
+        instance_assignment: &[F],
+        witness_assignment: &[F],
+        evaluate_on_coset: impl Fn(&mut [Vec<F>]) -> Result<(), E>,
+        interpolate_on_coset: impl FnOnce(&mut Vec<F>) -> Result<(), E>,
+    ) -> Result<Vec<F>, E>
+    where
+        F: PrimeField,
+        D: EvaluationDomain<F>,
+    {
+        let zero = F::zero();
+        let num_inputs = matrices.num_instance_variables;
+        let num_constraints = matrices.num_constraints;
         let domain_size = domain.size();
 
         let mut a = vec![zero; domain_size];
@@ -119,43 +245,193 @@ impl R1CStoQAP {
             .zip(cfg_iter!(&matrices.a))
             .zip(cfg_iter!(&matrices.b))
             .for_each(|(((a, b), at_i), bt_i)| {
-                *a = evaluate_constraint(&at_i, &full_assignment);
-                *b = evaluate_constraint(&bt_i, &full_assignment);
+                *a = evaluate_constraint(at_i, instance_assignment, witness_assignment);
+                *b = evaluate_constraint(bt_i, instance_assignment, witness_assignment);
             });
 
         {
             let start = num_constraints;
             let end = start + num_inputs;
-            a[start..end].clone_from_slice(&full_assignment[..num_inputs]);
+            a[start..end].clone_from_slice(instance_assignment);
         }
 
-        domain.ifft_in_place(&mut a);
-        domain.ifft_in_place(&mut b);
-
-        domain.coset_fft_in_place(&mut a);
-        domain.coset_fft_in_place(&mut b);
+        let mut a_b = [a, b];
+        evaluate_on_coset(&mut a_b)?;
+        let [mut a, b] = a_b;
 
-        let mut ab = domain.mul_polynomials_in_evaluation_domain(&a, &b);
-        drop(a);
-        drop(b);
+        // Multiply in place and reuse the buffer of `b` for `c`, so that at most
+        // two vectors of domain size are alive at once.
+        cfg_iter_mut!(a)
+            .zip(cfg_iter!(b))
+            .for_each(|(a_i, b_i)| *a_i *= b_i);
+        let mut ab = a;
 
-        let mut c = vec![zero; domain_size];
-        cfg_iter_mut!(c[..prover.num_constraints])
+        let mut c = b;
+        cfg_iter_mut!(c).for_each(|c_i| *c_i = zero);
+        cfg_iter_mut!(c[..num_constraints])
             .enumerate()
             .for_each(|(i, c)| {
-                *c = evaluate_constraint(&matrices.c[i], &full_assignment);
+                *c = evaluate_constraint(&matrices.c[i], instance_assignment, witness_assignment);
             });
 
-        domain.ifft_in_place(&mut c);
-        domain.coset_fft_in_place(&mut c);
+        evaluate_on_coset(core::slice::from_mut(&mut c))?;
 
         cfg_iter_mut!(ab)
             .zip(c)
             .for_each(|(ab_i, c_i)| *ab_i -= &c_i);
 
         domain.divide_by_vanishing_poly_on_coset_in_place(&mut ab);
-        domain.coset_ifft_in_place(&mut ab);
+        interpolate_on_coset(&mut ab)?;
 
         Ok(ab)
     }
+
+    /// Returns the subdomain of `domain` with `size` elements, if `size`
+    /// divides the size of `domain` and the generator of the subdomain is the
+    /// matching power of that of `domain`, as
+    /// [`R1CStoQAP::witness_map_in_chunks`] requires.
+    pub(crate) fn subdomain<F: PrimeField, D: EvaluationDomain<F>>(
+        domain: &D,
+        size: usize,
+    ) -> Option<D> {
+        if size == 0 || domain.size() % size != 0 {
+            return None;
+        }
+        let subdomain = D::new(size)?;
+        let chunks = domain.size() / size;
+        if subdomain.size() != size || subdomain.element(1) != domain.element(chunks) {
+            return None;
+        }
+
+        Some(subdomain)
+    }
+
+    /// Computes the coefficients of `h` like
+    /// [`R1CStoQAP::witness_map_from_matrices`], but evaluates `a`, `b` and
+    /// `c` over the coset one chunk at a time, so that besides `h` only a few
+    /// vectors of the size of `subdomain` are alive at once.
+    ///
+    /// With `k` chunks, the coset of `domain` is split into the `k` cosets
+    /// of `subdomain` it contains. The evaluations of a polynomial over each
+    /// of them are folded from the transforms of the `k` slices of its
+    /// evaluations over `domain`, which are computed again for every chunk,
+    /// and each chunk of `h` is added to its coefficients in the same way.
+    /// This takes about `k` times the work of the whole transforms. The
+    /// progress is reported to `report` as the fraction of chunks done.
+    pub(crate) fn witness_map_in_chunks<F, D, E>(
+        domain: &D,
+        subdomain: &D,
+        matrices: &ConstraintMatrices<F>,
+        instance_assignment: &[F],
+        witness_assignment: &[F],
+        mut report: impl FnMut(f64) -> Result<(), E>,
+    ) -> Result<Vec<F>, E>
+    where
+        F: PrimeField,
+        D: EvaluationDomain<F>,
+    {
+        let zero = F::zero();
+        let domain_size = domain.size();
+        let chunk_size = subdomain.size();
+        let num_chunks = domain_size / chunk_size;
+        let num_constraints = matrices.num_constraints;
+
+        // The evaluations over `domain` of the rows `offset + num_chunks * i`
+        // of the polynomial with the constraints `matrix`, followed by `inputs`.
+        let evaluate_slice =
+            |matrix: &[Vec<(F, usize)>], inputs: &[F], offset: usize, slice: &mut [F]| {
+                cfg_iter_mut!(slice).enumerate().for_each(|(i, e)| {
+                    let row = offset + num_chunks * i;
+                    *e = if row < num_constraints {
+                        evaluate_constraint(&matrix[row], instance_assignment, witness_assignment)
+                    } else {
+                        inputs.get(row - num_constraints).copied().unwrap_or(zero)
+                    };
+                });
+            };
+
+        let g = F::multiplicative_generator();
+        let omega = domain.element(1);
+        let omega_inv = omega.inverse().unwrap();
+        // `zeta` has order `num_chunks`.
+        let zeta = omega.pow([chunk_size as u64]);
+        let zeta_inv = zeta.inverse().unwrap();
+        let g_chunk = g.pow([chunk_size as u64]);
+        let num_chunks_inv = F::from(num_chunks as u64).inverse().unwrap();
+        // The evaluations over the coset carry a factor of `lambda`, which is
+        // left out until `h` is computed.
+        let lambda = (g.pow([domain_size as u64]) - F::one()) * num_chunks_inv;
+
+        let mut h = vec![zero; domain_size];
+        let mut slice = vec![zero; chunk_size];
+        let mut a = vec![zero; chunk_size];
+        let mut b = vec![zero; chunk_size];
+        let mut c = vec![zero; chunk_size];
+
+        // `offset` is `g * omega^j` for the chunk `j`, the coset
+        // `offset * subdomain` of `domain`.
+        let mut offset = g;
+        let mut zeta_j = F::one();
+        for j in 0..num_chunks {
+            for v in [&mut a, &mut b, &mut c] {
+                cfg_iter_mut!(v).for_each(|v_i| *v_i = zero);
+            }
+
+            let mut shift = offset;
+            let mut zeta_shift = zeta_j;
+            for l in 0..num_chunks {
+                // The slice `l` contributes its coefficients over
+                // `subdomain`, shifted to the chunk `j` and weighted by
+                // `1 / (g^chunk_size * zeta^(j - l) - 1)`.
+                let weight = (g_chunk * zeta_shift - F::one()).inverse().unwrap();
+                for (v, matrix, inputs) in [
+                    (&mut a, &matrices.a, instance_assignment),
+                    (&mut b, &matrices.b, &[][..]),
+                    (&mut c, &matrices.c, &[][..]),
+                ] {
+                    evaluate_slice(matrix, inputs, l, &mut slice);
+                    subdomain.ifft_in_place(&mut slice);
+                    D::distribute_powers_and_mul_by_const(&mut slice, shift, weight);
+                    cfg_iter_mut!(v)
+                        .zip(cfg_iter!(slice))
+                        .for_each(|(v_i, s_i)| *v_i += s_i);
+                }
+                shift *= omega_inv;
+                zeta_shift *= zeta_inv;
+            }
+
+            for v in [&mut a, &mut b, &mut c] {
+                subdomain.fft_in_place(v);
+            }
+
+            // The chunk `j` of the evaluations of `h` over the coset, up to a
+            // factor of `num_chunks`.
+            cfg_iter_mut!(a)
+                .zip(cfg_iter!(b))
+                .zip(cfg_iter!(c))
+                .for_each(|((a_i, b_i), c_i)| *a_i = lambda * *a_i * b_i - c_i);
+            subdomain.ifft_in_place(&mut a);
+
+            // The chunk adds `a_i * (offset^-1)^(i + t * chunk_size)` to each
+            // coefficient `i + t * chunk_size` of `h`.
+            let offset_inv = offset.inverse().unwrap();
+            let offset_inv_chunk = offset_inv.pow([chunk_size as u64]);
+            let scale = num_chunks_inv.square();
+            cfg_chunks_mut!(h, chunk_size)
+                .enumerate()
+                .for_each(|(t, h_t)| {
+                    let mut power = scale * offset_inv_chunk.pow([t as u64]);
+                    for (h_i, a_i) in h_t.iter_mut().zip(&a) {
+                        *h_i += power * a_i;
+                        power *= offset_inv;
+                    }
+                });
+
+            offset *= omega;
+            zeta_j *= zeta;
+            report((j + 1) as f64 / num_chunks as f64)?;
+        }
+
+        Ok(h)
+    }
 }
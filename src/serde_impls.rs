@@ -0,0 +1,144 @@
+use crate::container::Hex;
+use crate::{PreparedVerifyingKey, Proof, ProvingKey, SerializablePreparedG2, VerifyingKey};
+use ark_ec::PairingEngine;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_std::{fmt, vec::Vec};
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// Serializes `value` as its compressed encoding, which is a hex string in
+/// human-readable formats and bytes in the others.
+fn serialize_compressed<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: CanonicalSerialize + ?Sized,
+    S: Serializer,
+{
+    let mut bytes = Vec::with_capacity(value.serialized_size());
+    value
+        .serialize(&mut bytes)
+        .map_err(serde::ser::Error::custom)?;
+    if serializer.is_human_readable() {
+        serializer.collect_str(&Hex(&bytes))
+    } else {
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+/// Deserializes a value serialized with [`serialize_compressed`], checking
+/// that its elements are in their groups and that the encoding has no
+/// trailing bytes.
+fn deserialize_compressed<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: CanonicalDeserialize,
+    D: Deserializer<'de>,
+{
+    let bytes = if deserializer.is_human_readable() {
+        deserializer.deserialize_str(BytesVisitor)?
+    } else {
+        deserializer.deserialize_byte_buf(BytesVisitor)?
+    };
+    let mut reader = &bytes[..];
+    let value = T::deserialize(&mut reader).map_err(de::Error::custom)?;
+    if !reader.is_empty() {
+        return Err(de::Error::invalid_length(
+            bytes.len(),
+            &"the compressed encoding of the value",
+        ));
+    }
+    Ok(value)
+}
+
+/// Reads a hex string or bytes.
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a hex string or bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        if v.len() % 2 != 0 {
+            return Err(E::invalid_length(v.len(), &"an even number of hex digits"));
+        }
+        let digit = |c: u8| match c {
+            b'0'..=b'9' => Ok(c - b'0'),
+            b'a'..=b'f' => Ok(c - b'a' + 10),
+            b'A'..=b'F' => Ok(c - b'A' + 10),
+            _ => Err(E::invalid_value(de::Unexpected::Str(v), &self)),
+        };
+        v.as_bytes()
+            .chunks(2)
+            .map(|pair| Ok(digit(pair[0])? << 4 | digit(pair[1])?))
+            .collect()
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(v.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(v)
+    }
+
+    // Formats without a byte type write bytes as a sequence of integers.
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(b) = seq.next_element()? {
+            bytes.push(b);
+        }
+        Ok(bytes)
+    }
+}
+
+macro_rules! impl_serde_compressed {
+    ($ty:ident $(where $($bound:tt)+)?) => {
+        impl<E: PairingEngine> Serialize for $ty<E> $(where $($bound)+)? {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serialize_compressed(self, serializer)
+            }
+        }
+
+        impl<'de, E: PairingEngine> Deserialize<'de> for $ty<E> $(where $($bound)+)? {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserialize_compressed(deserializer)
+            }
+        }
+    };
+}
+
+impl_serde_compressed!(Proof);
+impl_serde_compressed!(VerifyingKey);
+impl_serde_compressed!(ProvingKey);
+// A deserialized prepared key is checked against the elements derived from
+// its verifying key, which costs about as much as preparing the key again.
+impl_serde_compressed!(PreparedVerifyingKey where E::G2Prepared: SerializablePreparedG2);
+
+/// `serde` functions for a vector of public inputs, as its compressed
+/// encoding, for fields declared with
+/// `#[serde(with = "ark_bpr20::serde_public_inputs")]`.
+pub mod serde_public_inputs {
+    use super::{deserialize_compressed, serialize_compressed};
+    use ark_ff::PrimeField;
+    use ark_std::vec::Vec;
+    use serde::{Deserializer, Serializer};
+
+    /// Serializes `inputs` as the compressed encoding of the vector.
+    pub fn serialize<F: PrimeField, S: Serializer>(
+        inputs: &[F],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serialize_compressed(inputs, serializer)
+    }
+
+    /// Deserializes a vector of public inputs serialized with [`serialize`],
+    /// checking that each is in the field.
+    pub fn deserialize<'de, F: PrimeField, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<F>, D::Error> {
+        deserialize_compressed(deserializer)
+    }
+}
@@ -0,0 +1,281 @@
+use crate::{
+    data_structures::Encoding,
+    msm::MsmBackend,
+    prover::{G1Query, MsmScalars, ProvingKeySource},
+    ProvingKey, VerifyingKey,
+};
+use ark_ec::{AffineCurve, PairingEngine};
+use ark_ff::Zero;
+use ark_relations::r1cs::Result as R1CSResult;
+use ark_serialize::*;
+use ark_std::{vec, vec::Vec};
+
+/// The variables that appear in a query, i.e. whose query element is not
+/// the identity, stored as a bit per variable.
+#[derive(Clone, Debug, Default, PartialEq, Eq, CanonicalSerialize)]
+pub struct QueryDensity {
+    num_variables: usize,
+    words: Vec<u64>,
+}
+
+impl QueryDensity {
+    /// The density of the variables `0..num_variables` for which `dense` is
+    /// `true`.
+    pub fn new(num_variables: usize, dense: impl Fn(usize) -> bool) -> Self {
+        let mut words = vec![0u64; (num_variables + 63) / 64];
+        for i in (0..num_variables).filter(|&i| dense(i)) {
+            words[i / 64] |= 1 << (i % 64);
+        }
+        Self {
+            num_variables,
+            words,
+        }
+    }
+
+    /// The number of variables, including those that do not appear.
+    pub fn num_variables(&self) -> usize {
+        self.num_variables
+    }
+
+    /// Returns `true` if the variable `i` appears in the query.
+    pub fn contains(&self, i: usize) -> bool {
+        i < self.num_variables
+            && self
+                .words
+                .get(i / 64)
+                .map_or(false, |w| w >> (i % 64) & 1 == 1)
+    }
+
+    /// The number of variables that appear in the query.
+    pub fn count(&self) -> usize {
+        self.rank(self.num_variables)
+    }
+
+    /// The elements of `query` of the variables that appear in the query.
+    fn select<G: Copy>(&self, query: &[G]) -> Vec<G> {
+        (0..query.len())
+            .filter(|&i| self.contains(i))
+            .map(|i| query[i])
+            .collect()
+    }
+
+    /// The number of variables before `i` that appear in the query, which
+    /// is the position of the query element of the variable `i`.
+    fn rank(&self, i: usize) -> usize {
+        let i = core::cmp::min(i, self.num_variables);
+        let full_words = self.words.iter().take(i / 64);
+        let ones = full_words.map(|w| w.count_ones() as usize).sum::<usize>();
+        let partial = match (i % 64, self.words.get(i / 64)) {
+            (0, _) | (_, None) => 0,
+            (bits, Some(w)) => (w & ((1 << bits) - 1)).count_ones() as usize,
+        };
+        ones + partial
+    }
+}
+
+impl CanonicalDeserialize for QueryDensity {
+    /// Reads a density, checking that it has a bit for each of its variables
+    /// and no bits set beyond them.
+    fn deserialize<R: Read>(mut reader: R) -> Result<Self, SerializationError> {
+        let num_variables = usize::deserialize(&mut reader)?;
+        let words = Vec::<u64>::deserialize(&mut reader)?;
+        let unused_bits = match (num_variables % 64, words.last()) {
+            (0, _) | (_, None) => 0,
+            (bits, Some(w)) => w >> bits,
+        };
+        let len = num_variables / 64 + usize::from(num_variables % 64 != 0);
+        if words.len() != len || unused_bits != 0 {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(Self {
+            num_variables,
+            words,
+        })
+    }
+}
+
+/// A proving key that omits the elements of the A- and B-queries that are
+/// the identity, which are those of the variables that do not appear in a
+/// query, and whose prover skips the matching assignment entries in its
+/// MSMs.
+///
+/// Circuits with many variables that only appear in some of the queries,
+/// such as boolean or auxiliary variables, have much smaller keys and
+/// prove faster this way. The proofs are the same as with the
+/// [`ProvingKey`] the sparse key is built from.
+///
+/// The generator only emits dense keys: [`SparseProvingKey::new`] is the only
+/// way to get a sparse key, and needs the dense key in memory while it runs.
+/// Reading a sparse key checks that its queries have as many elements as its
+/// densities have variables, like [`SparseProvingKey::check`].
+#[derive(Clone, Debug, PartialEq, CanonicalSerialize)]
+pub struct SparseProvingKey<E: PairingEngine> {
+    /// The underlying verification key.
+    pub vk: VerifyingKey<E>,
+    /// The element `beta * G` in `E::G1`.
+    pub beta_g1: E::G1Affine,
+    /// The element `delta * G` in `E::G1`.
+    pub delta_g1: E::G1Affine,
+    /// The variables that appear in the A-query.
+    pub a_density: QueryDensity,
+    /// The elements `a_i * G` in `E::G1` of the variables in `a_density`.
+    pub a_query: Vec<E::G1Affine>,
+    /// The variables that appear in the B-query.
+    pub b_density: QueryDensity,
+    /// The elements `b_i * G` in `E::G1` of the variables in `b_density`.
+    pub b_g1_query: Vec<E::G1Affine>,
+    /// The elements `b_i * H` in `E::G2` of the variables in `b_density`.
+    pub b_g2_query: Vec<E::G2Affine>,
+    /// The elements `h_i * G` in `E::G1`.
+    pub h_query: Vec<E::G1Affine>,
+    /// The elements `l_i * G` in `E::G1`.
+    pub l_query: Vec<E::G1Affine>,
+}
+
+impl<E: PairingEngine> SparseProvingKey<E> {
+    /// The sparse form of `pk`, without the identity elements of its A- and
+    /// B-queries.
+    pub fn new(pk: &ProvingKey<E>) -> Self {
+        let a_density = QueryDensity::new(pk.a_query.len(), |i| !pk.a_query[i].is_zero());
+        let b_density = QueryDensity::new(pk.b_g2_query.len(), |i| {
+            !pk.b_g2_query[i].is_zero() || pk.b_g1_query.get(i).map_or(false, |g| !g.is_zero())
+        });
+        Self {
+            vk: pk.vk.clone(),
+            beta_g1: pk.beta_g1,
+            delta_g1: pk.delta_g1,
+            a_query: a_density.select(&pk.a_query),
+            b_g1_query: b_density.select(&pk.b_g1_query),
+            b_g2_query: b_density.select(&pk.b_g2_query),
+            a_density,
+            b_density,
+            h_query: pk.h_query.clone(),
+            l_query: pk.l_query.clone(),
+        }
+    }
+
+    /// Checks that the A- and B-queries hold an element for each variable
+    /// that appears in `a_density` and `b_density`, and that both densities
+    /// have the same number of variables.
+    pub fn check(&self) -> Result<(), SerializationError> {
+        let a_count = self.a_density.count();
+        let b_count = self.b_density.count();
+        if self.a_density.num_variables() != self.b_density.num_variables()
+            || self.a_query.len() != a_count
+            || self.b_g1_query.len() != b_count
+            || self.b_g2_query.len() != b_count
+        {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(())
+    }
+
+    fn read<R: Read>(mut reader: R, encoding: Encoding) -> Result<Self, SerializationError> {
+        let pk = Self {
+            vk: encoding.read(&mut reader)?,
+            beta_g1: encoding.read(&mut reader)?,
+            delta_g1: encoding.read(&mut reader)?,
+            a_density: encoding.read(&mut reader)?,
+            a_query: encoding.read(&mut reader)?,
+            b_density: encoding.read(&mut reader)?,
+            b_g1_query: encoding.read(&mut reader)?,
+            b_g2_query: encoding.read(&mut reader)?,
+            h_query: encoding.read(&mut reader)?,
+            l_query: encoding.read(&mut reader)?,
+        };
+        pk.check()?;
+        Ok(pk)
+    }
+}
+
+impl<E: PairingEngine> CanonicalDeserialize for SparseProvingKey<E> {
+    fn deserialize<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::read(reader, Encoding::Compressed)
+    }
+
+    fn deserialize_uncompressed<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::read(reader, Encoding::Uncompressed)
+    }
+
+    fn deserialize_unchecked<R: Read>(reader: R) -> Result<Self, SerializationError> {
+        Self::read(reader, Encoding::Unchecked)
+    }
+}
+
+/// Computes `sum_i scalars[i] * query[i]` on `backend`, where `query` holds
+/// the elements of the variables in `density` only, skipping the scalars of
+/// the other variables.
+fn sparse_msm<G: AffineCurve, B: MsmBackend>(
+    backend: &B,
+    density: &QueryDensity,
+    query: &[G],
+    scalars: MsmScalars<'_, G::ScalarField>,
+) -> G::Projective {
+    // Scalars beyond the end of the query are ignored and vice versa.
+    let len = core::cmp::min(density.num_variables(), scalars.len());
+    let chunk_size = scalars.chunk_size().unwrap_or(len).clamp(1, len.max(1));
+
+    let mut acc = G::Projective::zero();
+    let mut start = core::cmp::min(scalars.start(), len);
+    let mut position = density.rank(start);
+    while start < len && position < query.len() {
+        let end = core::cmp::min(start + chunk_size, len);
+        let chunk_scalars = scalars.to_repr(start..end);
+        let dense_scalars = (start..end)
+            .filter(|&i| density.contains(i))
+            .map(|i| chunk_scalars[i - start])
+            .collect::<Vec<_>>();
+        let bases = &query[position..core::cmp::min(position + dense_scalars.len(), query.len())];
+        acc += &backend.variable_base_msm(bases, &dense_scalars);
+        position += dense_scalars.len();
+        start = end;
+    }
+    acc
+}
+
+impl<E: PairingEngine> ProvingKeySource<E> for SparseProvingKey<E> {
+    type Error = ark_relations::r1cs::SynthesisError;
+
+    fn vk(&self) -> &VerifyingKey<E> {
+        &self.vk
+    }
+
+    fn beta_g1(&self) -> E::G1Affine {
+        self.beta_g1
+    }
+
+    fn delta_g1(&self) -> E::G1Affine {
+        self.delta_g1
+    }
+
+    fn num_variables(&self) -> usize {
+        self.a_density.num_variables()
+    }
+
+    fn msm_g1<B: MsmBackend>(
+        &self,
+        backend: &B,
+        query: G1Query,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> R1CSResult<E::G1Projective> {
+        Ok(match query {
+            G1Query::A => sparse_msm(backend, &self.a_density, &self.a_query, scalars),
+            G1Query::BG1 => sparse_msm(backend, &self.b_density, &self.b_g1_query, scalars),
+            G1Query::H => scalars.msm(backend, &self.h_query),
+            G1Query::L => scalars.msm(backend, &self.l_query),
+        })
+    }
+
+    fn msm_b_g2<B: MsmBackend>(
+        &self,
+        backend: &B,
+        scalars: MsmScalars<'_, E::Fr>,
+    ) -> R1CSResult<E::G2Projective> {
+        Ok(sparse_msm(
+            backend,
+            &self.b_density,
+            &self.b_g2_query,
+            scalars,
+        ))
+    }
+}
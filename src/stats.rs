@@ -0,0 +1,108 @@
+use crate::progress::{Phase, Query};
+use ark_std::vec::Vec;
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use std::{sync::Mutex, time::Instant};
+
+/// The size of a circuit and of its evaluation domain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CircuitStats {
+    /// The number of constraints.
+    pub num_constraints: usize,
+    /// The number of instance variables, including the `one` variable.
+    pub num_instance_variables: usize,
+    /// The number of witness variables.
+    pub num_witness_variables: usize,
+    /// The size of the evaluation domain of the QAP.
+    pub domain_size: usize,
+}
+
+/// Statistics of a setup, returned by
+/// [`generate_parameters_with_stats`](crate::generate_parameters_with_stats).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SetupStats {
+    /// The size of the circuit.
+    pub circuit: CircuitStats,
+    /// The number of elements of each query, computed with one fixed-base
+    /// MSM each.
+    pub msm_sizes: Vec<(Query, usize)>,
+    /// How long each phase took, in the order in which they finished.
+    pub durations: Vec<(Phase, Duration)>,
+    /// How long the whole setup took.
+    pub total_duration: Duration,
+}
+
+impl SetupStats {
+    /// How long `phase` took, if it ran.
+    pub fn duration(&self, phase: Phase) -> Option<Duration> {
+        find(&self.durations, &phase)
+    }
+
+    /// The size of the MSM over `query`, if it ran.
+    pub fn msm_size(&self, query: Query) -> Option<usize> {
+        find(&self.msm_sizes, &query)
+    }
+}
+
+/// Statistics of a proof, returned by
+/// [`ProverContext::create_proof_with_stats`](crate::ProverContext::create_proof_with_stats).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProofStats {
+    /// The size of the circuit.
+    pub circuit: CircuitStats,
+    /// The number of scalars of each variable-base MSM over a query.
+    pub msm_sizes: Vec<(Query, usize)>,
+    /// How long each phase took, in the order in which they finished.
+    pub durations: Vec<(Phase, Duration)>,
+    /// How long the whole proof took.
+    pub total_duration: Duration,
+}
+
+impl ProofStats {
+    /// How long `phase` took, if it ran.
+    pub fn duration(&self, phase: Phase) -> Option<Duration> {
+        find(&self.durations, &phase)
+    }
+
+    /// The size of the MSM over `query`, if it ran.
+    pub fn msm_size(&self, query: Query) -> Option<usize> {
+        find(&self.msm_sizes, &query)
+    }
+}
+
+fn find<K: PartialEq, V: Copy>(entries: &[(K, V)], key: &K) -> Option<V> {
+    entries.iter().find(|(k, _)| k == key).map(|(_, v)| *v)
+}
+
+/// Records how long each phase of a run takes, from the report of its start
+/// to the first report of its end.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub(crate) struct Recorder {
+    started: Mutex<Vec<(Phase, Instant)>>,
+    durations: Mutex<Vec<(Phase, Duration)>>,
+}
+
+#[cfg(feature = "std")]
+impl Recorder {
+    pub(crate) fn report(&self, phase: Phase, fraction: f64) {
+        let mut started = self.started.lock().unwrap();
+        if fraction == 0.0 {
+            started.push((phase, Instant::now()));
+        } else if fraction >= 1.0 {
+            if let Some(i) = started.iter().position(|(p, _)| *p == phase) {
+                let (_, start) = started.swap_remove(i);
+                self.durations
+                    .lock()
+                    .unwrap()
+                    .push((phase, start.elapsed()));
+            }
+        }
+    }
+
+    /// The durations of the phases that finished.
+    pub(crate) fn into_durations(self) -> Vec<(Phase, Duration)> {
+        self.durations.into_inner().unwrap()
+    }
+}
@@ -1,19 +1,40 @@
+use crate::checkpoint::Checkpoints;
+use crate::chunked::{
+    generate_chunked_parameters, generate_chunked_parameters_with_options, ChunkedProvingKey,
+};
+use crate::container::{ContainerError, KeyContainer, Section, CONTAINER_VERSION};
+use crate::envelope::{EnvelopeError, ProofEnvelope};
+use crate::progress::Stages;
 use crate::{
-    create_random_proof, generate_random_parameters, prepare_verifying_key,
-    verify_proof,
+    add_input_tables, constraint_matrices, create_proof, create_proof_with_precomputed,
+    create_random_proof, create_random_proofs_batch, generate_parameters,
+    generate_parameters_with_backend, generate_parameters_with_checkpoints,
+    generate_parameters_with_options, generate_parameters_with_stats, generate_random_parameters,
+    prepare_fixed_inputs, prepare_inputs, prepare_remaining_inputs, prepare_verifying_key,
+    prepare_verifying_key_with_window, prepare_witness, reprove, verify_proof,
+    verify_proof_with_prepared_inputs, ArkworksMsm, CancellationToken, CircuitStats, Error,
+    MsmBackend, Phase, PrecomputedProvingKey, PreparedVerifyingKey, Proof, ProofRandomness,
+    ProverContext, Query, QueryDensity, RunOptions, SerializablePreparedG2, SparseProvingKey,
+    VerifyingKey,
+};
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{BigInteger, One, PrimeField, UniformRand, Zero};
+use ark_std::{
+    rand::{rngs::StdRng, Rng, SeedableRng},
+    test_rng,
+    vec::Vec,
 };
-use ark_ec::PairingEngine;
-use ark_ff::UniformRand;
-use ark_std::test_rng;
 
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
 use core::ops::MulAssign;
 
-use ark_ff::{Field};
+use ark_ff::Field;
 use ark_relations::{
     lc,
-    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError, Variable},
 };
 
+#[derive(Clone, Copy)]
 struct MySillyCircuit<F: Field> {
     a: Option<F>,
     b: Option<F>,
@@ -45,6 +66,26 @@ impl<ConstraintF: Field> ConstraintSynthesizer<ConstraintF> for MySillyCircuit<C
     }
 }
 
+/// A circuit whose public inputs are copies of witness variables.
+#[derive(Clone)]
+struct InputsCircuit<F: Field> {
+    inputs: Vec<Option<F>>,
+}
+
+impl<ConstraintF: Field> ConstraintSynthesizer<ConstraintF> for InputsCircuit<ConstraintF> {
+    fn generate_constraints(
+        self,
+        cs: ConstraintSystemRef<ConstraintF>,
+    ) -> Result<(), SynthesisError> {
+        for input in self.inputs {
+            let w = cs.new_witness_variable(|| input.ok_or(SynthesisError::AssignmentMissing))?;
+            let x = cs.new_input_variable(|| input.ok_or(SynthesisError::AssignmentMissing))?;
+            cs.enforce_constraint(lc!() + w, lc!() + Variable::One, lc!() + x)?;
+        }
+        Ok(())
+    }
+}
+
 fn test_prove_and_verify<E>(n_iters: usize)
 where
     E: PairingEngine,
@@ -77,8 +118,1794 @@ where
     }
 }
 
+fn test_verify_both_ways<E>(n_iters: usize)
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+
+    for _ in 0..n_iters {
+        let a = E::Fr::rand(rng);
+        let b = E::Fr::rand(rng);
+        let mut c = a;
+        c.mul_assign(&b);
+
+        let proof = create_random_proof(
+            MySillyCircuit {
+                a: Some(a),
+                b: Some(b),
+            },
+            &params,
+            rng,
+        )
+        .unwrap();
+        let mut other_c = proof.clone();
+        other_c.c = (proof.c.into_projective() + params.delta_g1.into_projective()).into_affine();
+        let mut other_delta_prime = proof.clone();
+        other_delta_prime.delta_prime = params.vk.delta_g2;
+
+        for (proof, inputs, valid) in [
+            (&proof, c, true),
+            (&proof, a, false),
+            (&other_c, c, false),
+            (&other_delta_prime, c, false),
+        ] {
+            let prepared_inputs = prepare_inputs(&pvk, &[inputs]).unwrap();
+            assert_eq!(
+                verify_proof_with_prepared_inputs(&pvk, proof, &prepared_inputs).unwrap(),
+                valid
+            );
+        }
+    }
+}
+
+fn test_prepare_inputs<E>()
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+
+    for &num_inputs in &[0, 1, 5, 40] {
+        let vk = VerifyingKey::<E> {
+            gamma_abc_g1: (0..=num_inputs)
+                .map(|_| E::G1Projective::rand(rng).into_affine())
+                .collect(),
+            ..VerifyingKey::default()
+        };
+        let inputs = (0..num_inputs)
+            .map(|_| E::Fr::rand(rng))
+            .collect::<Vec<_>>();
+
+        let mut expected = vk.gamma_abc_g1[0].into_projective();
+        for (i, b) in inputs.iter().zip(&vk.gamma_abc_g1[1..]) {
+            expected += &b.mul(i.into_repr());
+        }
+
+        let mut pvk = prepare_verifying_key(&vk);
+        assert_eq!(prepare_inputs(&pvk, &inputs).unwrap(), expected);
+        for &window in &[1, 4] {
+            add_input_tables(&mut pvk, window);
+            assert_eq!(pvk.gamma_abc_g1_tables.len(), num_inputs);
+            assert_eq!(prepare_inputs(&pvk, &inputs).unwrap(), expected);
+            assert!(prepare_inputs(&pvk, &[inputs.clone(), vec![E::Fr::one()]].concat()).is_err());
+        }
+    }
+
+    // Proofs verify the same with the tables.
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+    let mut pvk = prepare_verifying_key::<E>(&params.vk);
+    add_input_tables(&mut pvk, 3);
+    let a = E::Fr::rand(rng);
+    let b = E::Fr::rand(rng);
+    let mut c = a;
+    c.mul_assign(&b);
+    let proof = create_random_proof(
+        MySillyCircuit {
+            a: Some(a),
+            b: Some(b),
+        },
+        &params,
+        rng,
+    )
+    .unwrap();
+    assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
+    assert!(!verify_proof(&pvk, &proof, &[a]).unwrap());
+}
+
+fn test_specialize<E>(n_iters: usize)
+where
+    E: PairingEngine,
+{
+    const NUM_INPUTS: usize = 6;
+    let rng = &mut test_rng();
+
+    let params = generate_random_parameters::<E, _, _>(
+        InputsCircuit {
+            inputs: vec![None; NUM_INPUTS],
+        },
+        rng,
+    )
+    .unwrap();
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+    let mut pvk_with_tables = pvk.clone();
+    add_input_tables(&mut pvk_with_tables, 3);
+
+    for iter in 0..n_iters {
+        let inputs = (0..NUM_INPUTS)
+            .map(|_| E::Fr::rand(rng))
+            .collect::<Vec<_>>();
+        let proof = create_random_proof(
+            InputsCircuit {
+                inputs: inputs.iter().copied().map(Some).collect(),
+            },
+            &params,
+            rng,
+        )
+        .unwrap();
+
+        // No inputs, all inputs and random subsets of them, in random order.
+        let mut indices = match iter {
+            0 => Vec::new(),
+            1 => (0..NUM_INPUTS).collect(),
+            _ => (0..NUM_INPUTS).filter(|_| rng.gen()).collect::<Vec<_>>(),
+        };
+        if indices.len() > 1 && rng.gen() {
+            indices.reverse();
+        }
+        let fixed = indices.iter().map(|&i| (i, inputs[i])).collect::<Vec<_>>();
+        let remaining = (0..NUM_INPUTS)
+            .filter(|i| !indices.contains(i))
+            .map(|i| inputs[i])
+            .collect::<Vec<_>>();
+
+        let specialized = prepare_verifying_key(&params.vk.specialize(&fixed).unwrap());
+        assert_eq!(specialized.vk.gamma_abc_g1.len(), remaining.len() + 1);
+        let expected = prepare_inputs(&pvk, &inputs).unwrap();
+        assert_eq!(prepare_inputs(&specialized, &remaining).unwrap(), expected);
+        for pvk in &[&pvk, &pvk_with_tables] {
+            let prepared_fixed = prepare_fixed_inputs(pvk, &fixed).unwrap();
+            assert_eq!(
+                prepare_remaining_inputs(pvk, &prepared_fixed, &remaining).unwrap(),
+                expected
+            );
+            let too_many = [remaining.clone(), vec![E::Fr::one()]].concat();
+            assert!(prepare_remaining_inputs(pvk, &prepared_fixed, &too_many).is_err());
+        }
+
+        // Specialized and full verification agree, on valid and invalid inputs.
+        assert!(verify_proof(&pvk, &proof, &inputs).unwrap());
+        assert!(verify_proof(&specialized, &proof, &remaining).unwrap());
+        if let Some(first) = remaining.first() {
+            let mut other_remaining = remaining.clone();
+            other_remaining[0] = *first + E::Fr::one();
+            assert!(!verify_proof(&specialized, &proof, &other_remaining).unwrap());
+        }
+        if let Some(&(index, value)) = fixed.first() {
+            let mut other_fixed = fixed.clone();
+            other_fixed[0] = (index, value + E::Fr::one());
+            let other = prepare_verifying_key(&params.vk.specialize(&other_fixed).unwrap());
+            assert!(!verify_proof(&other, &proof, &remaining).unwrap());
+            let mut other_inputs = inputs.clone();
+            other_inputs[index] += E::Fr::one();
+            assert!(!verify_proof(&pvk, &proof, &other_inputs).unwrap());
+        }
+    }
+
+    // Invalid indices are rejected.
+    let one = E::Fr::one();
+    assert!(params.vk.specialize(&[(NUM_INPUTS, one)]).is_err());
+    assert!(params.vk.specialize(&[(1, one), (1, one)]).is_err());
+    assert!(prepare_fixed_inputs(&pvk, &[(NUM_INPUTS, one)]).is_err());
+    assert!(prepare_fixed_inputs(&pvk, &[(0, one), (0, one)]).is_err());
+
+    // So are keys without `gamma_abc_g1` or with tables that do not match it.
+    let empty = PreparedVerifyingKey::<E>::default();
+    let prepared_fixed = prepare_fixed_inputs(&pvk, &[]).unwrap();
+    assert!(VerifyingKey::<E>::default().specialize(&[]).is_err());
+    assert!(prepare_fixed_inputs(&empty, &[]).is_err());
+    assert!(prepare_remaining_inputs(&empty, &prepared_fixed, &[one; NUM_INPUTS]).is_err());
+    let mut mismatched = pvk_with_tables.clone();
+    mismatched.gamma_abc_g1_tables.pop();
+    assert!(prepare_inputs(&mismatched, &[one; NUM_INPUTS]).is_err());
+    assert!(prepare_fixed_inputs(&mismatched, &[(0, one)]).is_err());
+    assert!(prepare_remaining_inputs(&mismatched, &prepared_fixed, &[one; NUM_INPUTS]).is_err());
+}
+
+fn test_prove_with_context<E>(n_iters: usize)
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+    let matrices = constraint_matrices(MySillyCircuit::<E::Fr> { a: None, b: None }).unwrap();
+    let ctx = ProverContext::new(&params, matrices).unwrap();
+
+    for _ in 0..n_iters {
+        let a = E::Fr::rand(rng);
+        let b = E::Fr::rand(rng);
+        let mut c = a;
+        c.mul_assign(&b);
+
+        let circuit = MySillyCircuit {
+            a: Some(a),
+            b: Some(b),
+        };
+        let r = E::Fr::rand(rng);
+        let s = E::Fr::rand(rng);
+        let zeta = E::Fr::rand(rng);
+
+        let proof = ctx.create_proof(circuit, r, s, zeta).unwrap();
+        assert_eq!(proof, create_proof(circuit, &params, r, s, zeta).unwrap());
+
+        assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
+        assert!(!verify_proof(&pvk, &proof, &[a]).unwrap());
+    }
+}
+
+fn test_prove_batch<E>(n_proofs: usize)
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+
+    let circuits = (0..n_proofs)
+        .map(|_| MySillyCircuit {
+            a: Some(E::Fr::rand(rng)),
+            b: Some(E::Fr::rand(rng)),
+        })
+        .collect::<Vec<_>>();
+
+    // The batch prover consumes `rng` exactly like a loop of single proofs.
+    let batch_rng = &mut test_rng();
+    let proofs = create_random_proofs_batch(circuits.iter().copied(), &params, batch_rng).unwrap();
+    let loop_rng = &mut test_rng();
+    assert_eq!(proofs.len(), n_proofs);
+
+    for (circuit, proof) in circuits.into_iter().zip(&proofs) {
+        let mut c = circuit.a.unwrap();
+        c.mul_assign(&circuit.b.unwrap());
+
+        assert_eq!(
+            proof,
+            &create_random_proof(circuit, &params, loop_rng).unwrap()
+        );
+        assert!(verify_proof(&pvk, proof, &[c]).unwrap());
+    }
+}
+
+fn test_prove_with_chunked_key<E>(n_iters: usize)
+where
+    E: PairingEngine,
+{
+    use blake2::{Blake2b, Digest};
+
+    let rng = &mut test_rng();
+
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+    let matrices = constraint_matrices(MySillyCircuit::<E::Fr> { a: None, b: None }).unwrap();
+
+    // Chunks of one element and of more elements than some queries hold.
+    for chunk_size in [1, 3] {
+        let path = std::env::temp_dir().join(format!(
+            "bpr20-chunked-pk-{}-{}",
+            std::process::id(),
+            chunk_size
+        ));
+        params
+            .write_chunked(std::fs::File::create(&path).unwrap(), chunk_size)
+            .unwrap();
+        let chunked_pk = ChunkedProvingKey::<E>::open(&path).unwrap();
+        assert_eq!(chunked_pk.chunk_size(), chunk_size);
+
+        let ctx = ProverContext::new(&chunked_pk, matrices.clone()).unwrap();
+        let low_memory_ctx = ProverContext::new(&chunked_pk, matrices.clone())
+            .unwrap()
+            .with_memory_budget(1);
+
+        for _ in 0..n_iters {
+            let a = E::Fr::rand(rng);
+            let b = E::Fr::rand(rng);
+            let mut c = a;
+            c.mul_assign(&b);
+
+            let circuit = MySillyCircuit {
+                a: Some(a),
+                b: Some(b),
+            };
+            let r = E::Fr::rand(rng);
+            let s = E::Fr::rand(rng);
+            let zeta = E::Fr::rand(rng);
+
+            let proof = ctx.create_proof(circuit, r, s, zeta).unwrap();
+            assert_eq!(proof, create_proof(circuit, &params, r, s, zeta).unwrap());
+            assert_eq!(
+                proof,
+                low_memory_ctx.create_proof(circuit, r, s, zeta).unwrap()
+            );
+
+            assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
+        }
+
+        // A damaged header is detected when the key is opened, and a damaged
+        // chunk when it is read.
+        let bytes = std::fs::read(&path).unwrap();
+        let mut damaged = bytes.clone();
+        damaged[26] ^= 1;
+        std::fs::write(&path, &damaged).unwrap();
+        assert!(ChunkedProvingKey::<E>::open(&path).is_err());
+
+        // A key for another curve is rejected before its header is read.
+        let mut other_curve = bytes.clone();
+        other_curve[8] ^= 1;
+        std::fs::write(&path, &other_curve).unwrap();
+        assert!(matches!(
+            ChunkedProvingKey::<E>::open(&path),
+            Err(Error::Container(ContainerError::CurveMismatch { .. }))
+        ));
+
+        // So is a header with a matching hash but a chunk size or query
+        // lengths that overflow or do not fit in the file.
+        let header_len =
+            32 + params.vk.uncompressed_size() + 2 * params.beta_g1.uncompressed_size() + 8 * 5;
+        let forged_fields = [
+            (24, u64::MAX),
+            (header_len - 40, u64::MAX),
+            (header_len - 8, 1 << 60),
+        ];
+        for (field, value) in forged_fields {
+            let mut forged = bytes.clone();
+            forged[field..field + 8].copy_from_slice(&value.to_le_bytes());
+            let checksum = Blake2b::digest(&forged[..header_len]);
+            forged[header_len..header_len + checksum.len()].copy_from_slice(&checksum);
+            std::fs::write(&path, &forged).unwrap();
+            assert!(matches!(
+                ChunkedProvingKey::<E>::open(&path),
+                Err(Error::Serialization(SerializationError::InvalidData))
+            ));
+        }
+
+        let mut damaged = bytes;
+        let last = damaged.len() - 1;
+        damaged[last] ^= 1;
+        std::fs::write(&path, &damaged).unwrap();
+        let damaged_pk = ChunkedProvingKey::<E>::open(&path).unwrap();
+        let circuit = MySillyCircuit {
+            a: Some(E::Fr::rand(rng)),
+            b: Some(E::Fr::rand(rng)),
+        };
+        let zero = E::Fr::zero();
+        let result = ProverContext::new(&damaged_pk, matrices.clone())
+            .unwrap()
+            .create_proof(circuit, zero, zero, zero);
+        assert!(matches!(
+            result,
+            Err(Error::Serialization(SerializationError::InvalidData))
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+fn test_generate_chunked_parameters<E>()
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+    let (alpha, beta, gamma, delta) = (
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+    );
+    let g1_generator = E::G1Projective::rand(rng);
+    let g2_generator = E::G2Projective::rand(rng);
+    let setup_circuit = MySillyCircuit::<E::Fr> { a: None, b: None };
+    let params = generate_parameters::<E, _, _>(
+        setup_circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        &mut StdRng::seed_from_u64(0),
+    )
+    .unwrap();
+
+    // The streamed key is the one `write_chunked` writes, for chunks of one
+    // element, of some elements and of more elements than any query holds.
+    for chunk_size in [1, 3, 100] {
+        let mut expected = Vec::new();
+        params.write_chunked(&mut expected, chunk_size).unwrap();
+        let mut bytes = Vec::new();
+        let vk = generate_chunked_parameters::<E, _, _, _>(
+            setup_circuit,
+            alpha,
+            beta,
+            gamma,
+            delta,
+            g1_generator,
+            g2_generator,
+            &mut StdRng::seed_from_u64(0),
+            &mut bytes,
+            chunk_size,
+        )
+        .unwrap();
+        assert_eq!(vk, params.vk);
+        assert!(bytes == expected);
+    }
+
+    // An observed run reports each query as its chunks are written, and
+    // stops part way through a query once it is cancelled.
+    let generate = |writer: &mut Vec<u8>, options: RunOptions<'_>| {
+        generate_chunked_parameters_with_options::<E, _, _, _>(
+            setup_circuit,
+            alpha,
+            beta,
+            gamma,
+            delta,
+            g1_generator,
+            g2_generator,
+            &mut StdRng::seed_from_u64(0),
+            writer,
+            1,
+            options,
+        )
+    };
+    let reports = std::sync::Mutex::new(Vec::new());
+    let record = |phase: Phase, fraction: f64| reports.lock().unwrap().push((phase, fraction));
+    let mut expected = Vec::new();
+    params.write_chunked(&mut expected, 1).unwrap();
+    let mut bytes = Vec::new();
+    let vk = generate(&mut bytes, RunOptions::new().with_progress(&record)).unwrap();
+    assert_eq!(vk, params.vk);
+    assert!(bytes == expected);
+    let mut phases = vec![Phase::Synthesis, Phase::Qap, Phase::Msm(Query::GammaAbc)];
+    phases.extend(
+        [Query::A, Query::BG1, Query::BG2, Query::H, Query::L]
+            .iter()
+            .map(|&query| Phase::Msm(query)),
+    );
+    check_reports(&reports.lock().unwrap(), &phases);
+    let b_g2_reports = reports
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(phase, _)| *phase == Phase::Msm(Query::BG2))
+        .count();
+    assert_eq!(b_g2_reports, params.b_g2_query.len() + 2);
+
+    let cancellation = CancellationToken::new();
+    let cancel = |phase: Phase, fraction: f64| {
+        if phase == Phase::Msm(Query::BG2) && fraction > 0.0 {
+            cancellation.cancel();
+        }
+    };
+    let options = RunOptions::new()
+        .with_progress(&cancel)
+        .with_cancellation(&cancellation);
+    let mut bytes = Vec::new();
+    assert!(matches!(
+        generate(&mut bytes, options),
+        Err(Error::Cancelled)
+    ));
+    assert!(bytes.len() < expected.len());
+
+    // A streamed key proves like the key it was written from.
+    let path = std::env::temp_dir().join(format!("bpr20-streamed-pk-{}", std::process::id()));
+    let vk = generate_chunked_parameters::<E, _, _, _>(
+        setup_circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        &mut StdRng::seed_from_u64(0),
+        std::io::BufWriter::new(std::fs::File::create(&path).unwrap()),
+        2,
+    )
+    .unwrap();
+    let chunked_pk = ChunkedProvingKey::<E>::open(&path).unwrap();
+    let matrices = constraint_matrices(setup_circuit).unwrap();
+    let ctx = ProverContext::new(&chunked_pk, matrices).unwrap();
+    let a = E::Fr::rand(rng);
+    let b = E::Fr::rand(rng);
+    let mut c = a;
+    c.mul_assign(&b);
+    let circuit = MySillyCircuit {
+        a: Some(a),
+        b: Some(b),
+    };
+    let (r, s, zeta) = (E::Fr::rand(rng), E::Fr::rand(rng), E::Fr::rand(rng));
+    let proof = ctx.create_proof(circuit, r, s, zeta).unwrap();
+    assert_eq!(proof, create_proof(circuit, &params, r, s, zeta).unwrap());
+    assert!(verify_proof(&prepare_verifying_key(&vk), &proof, &[c]).unwrap());
+
+    // A chunk size of zero is rejected.
+    let result = generate_chunked_parameters::<E, _, _, _>(
+        setup_circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        rng,
+        Vec::new(),
+        0,
+    );
+    assert!(result.is_err());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// The reference MSM backend, which computes every scalar multiplication
+/// on its own.
+struct NaiveMsm;
+
+impl MsmBackend for NaiveMsm {
+    fn variable_base_msm<G: AffineCurve>(
+        &self,
+        bases: &[G],
+        scalars: &[<G::ScalarField as PrimeField>::BigInt],
+    ) -> G::Projective {
+        bases
+            .iter()
+            .zip(scalars)
+            .fold(G::Projective::zero(), |acc, (base, scalar)| {
+                acc + &base.mul(*scalar)
+            })
+    }
+
+    fn fixed_base_msm<G: ProjectiveCurve>(
+        &self,
+        base: G,
+        scalars: &[&[G::ScalarField]],
+    ) -> Vec<Vec<G>> {
+        scalars
+            .iter()
+            .map(|s| s.iter().map(|s| base.mul(s.into_repr())).collect())
+            .collect()
+    }
+}
+
+fn test_key_container<E>()
+where
+    E: PairingEngine,
+{
+    use blake2::{Blake2b, Digest};
+    use std::io::Cursor;
+
+    let rng = &mut test_rng();
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+
+    for &compressed in &[false, true] {
+        let mut bytes = Vec::new();
+        params.write_container(&mut bytes, compressed).unwrap();
+
+        let mut container = KeyContainer::<E, _>::open(Cursor::new(&bytes)).unwrap();
+        assert_eq!(container.version(), CONTAINER_VERSION);
+        assert_eq!(container.is_compressed(), compressed);
+        assert_eq!(container.fingerprint(), params.vk.fingerprint());
+        assert!(container.has_section(Section::Query(Query::L)));
+        assert_eq!(container.read_g1_query(Query::H).unwrap(), params.h_query);
+        assert_eq!(container.read_b_g2_query().unwrap(), params.b_g2_query);
+        assert!(matches!(
+            container.read_g1_query(Query::BG2),
+            Err(Error::Container(ContainerError::NotInG1(Query::BG2)))
+        ));
+        assert_eq!(
+            container.read_g1_query(Query::GammaAbc).unwrap(),
+            params.vk.gamma_abc_g1
+        );
+        assert_eq!(container.read_verifying_key().unwrap(), params.vk);
+        assert_eq!(container.read_proving_key().unwrap(), params);
+
+        // A damaged query only fails the reads of that query.
+        let mut damaged = bytes.clone();
+        let last = damaged.len() - 1;
+        damaged[last] ^= 1;
+        let mut container = KeyContainer::<E, _>::open(Cursor::new(&damaged)).unwrap();
+        assert_eq!(container.read_verifying_key().unwrap(), params.vk);
+        assert_eq!(container.read_g1_query(Query::A).unwrap(), params.a_query);
+        assert!(matches!(
+            container.read_proving_key(),
+            Err(Error::Container(ContainerError::CorruptedSection(
+                Section::Query(Query::L)
+            )))
+        ));
+
+        // So does a truncated one.
+        let mut container =
+            KeyContainer::<E, _>::open(Cursor::new(&bytes[..bytes.len() - 1])).unwrap();
+        assert!(matches!(
+            container.read_g1_query(Query::L),
+            Err(Error::Container(ContainerError::CorruptedSection(
+                Section::Query(Query::L)
+            )))
+        ));
+    }
+
+    let mut bytes = Vec::new();
+    params.vk.write_container(&mut bytes, true).unwrap();
+    let mut container = KeyContainer::<E, _>::open(Cursor::new(&bytes)).unwrap();
+    assert!(!container.has_section(Section::Query(Query::A)));
+    assert_eq!(container.read_verifying_key().unwrap(), params.vk);
+    assert!(matches!(
+        container.read_g1_query(Query::A),
+        Err(Error::Container(ContainerError::MissingSection(
+            Section::Query(Query::A)
+        )))
+    ));
+
+    let open = |bytes: &[u8]| KeyContainer::<E, _>::open(Cursor::new(bytes.to_vec())).err();
+    let mut damaged = bytes.clone();
+    damaged[0] ^= 1;
+    assert!(matches!(
+        open(&damaged),
+        Some(Error::Container(ContainerError::NotAContainer))
+    ));
+    assert!(matches!(
+        open(&bytes[..4]),
+        Some(Error::Container(ContainerError::NotAContainer))
+    ));
+    let mut damaged = bytes.clone();
+    damaged[8] = 2;
+    assert!(matches!(
+        open(&damaged),
+        Some(Error::Container(ContainerError::UnsupportedVersion(2)))
+    ));
+    let mut damaged = bytes.clone();
+    damaged[40] ^= 1;
+    assert!(matches!(
+        open(&damaged),
+        Some(Error::Container(ContainerError::CorruptedHeader))
+    ));
+
+    // The header of a container for another curve, with a valid checksum.
+    let header_len = 100 + 84;
+    let mut other_curve = bytes.clone();
+    other_curve[16..32].copy_from_slice(&[7u8; 16]);
+    let checksum = Blake2b::digest(&other_curve[..header_len]);
+    other_curve[header_len..header_len + 64].copy_from_slice(&checksum);
+    match open(&other_curve) {
+        Some(Error::Container(ContainerError::CurveMismatch { expected, found })) => {
+            assert_eq!(expected, crate::container::curve_id::<E>());
+            assert_eq!(found, [7u8; 16]);
+        },
+        _ => panic!("expected a curve mismatch"),
+    }
+}
+
+fn test_proof_envelope<E, const N: usize>()
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+    let other_params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+
+    let a = E::Fr::rand(rng);
+    let b = E::Fr::rand(rng);
+    let mut c = a;
+    c.mul_assign(&b);
+    let proof = create_random_proof(
+        MySillyCircuit {
+            a: Some(a),
+            b: Some(b),
+        },
+        &params,
+        rng,
+    )
+    .unwrap();
+
+    let envelope = ProofEnvelope::new(&params.vk, proof.clone(), vec![c]);
+    let bytes = envelope.to_bytes();
+    let decoded = ProofEnvelope::from_bytes(&bytes, &params.vk).unwrap();
+    assert_eq!(decoded, envelope);
+    assert!(decoded.verify(&pvk).unwrap());
+    let wrong_inputs = ProofEnvelope::new(&params.vk, proof.clone(), vec![a]);
+    assert!(!wrong_inputs.verify(&pvk).unwrap());
+
+    // A proof for another verifying key is rejected before it is verified.
+    let reject = |bytes: &[u8]| ProofEnvelope::from_bytes(bytes, &params.vk).err();
+    assert!(matches!(
+        ProofEnvelope::from_bytes(&bytes, &other_params.vk),
+        Err(Error::Envelope(EnvelopeError::VerifyingKeyMismatch))
+    ));
+    let other_envelope = ProofEnvelope::new(&other_params.vk, proof.clone(), vec![c]);
+    assert!(matches!(
+        other_envelope.verify(&pvk),
+        Err(Error::Envelope(EnvelopeError::VerifyingKeyMismatch))
+    ));
+
+    let mut damaged = bytes.clone();
+    damaged[0] ^= 1;
+    assert!(matches!(
+        reject(&damaged),
+        Some(Error::Envelope(EnvelopeError::NotAnEnvelope))
+    ));
+    let mut damaged = bytes.clone();
+    damaged[8] = 2;
+    assert!(matches!(
+        reject(&damaged),
+        Some(Error::Envelope(EnvelopeError::UnsupportedVersion(2)))
+    ));
+    let mut damaged = bytes.clone();
+    damaged[12..28].copy_from_slice(&[7u8; 16]);
+    match reject(&damaged) {
+        Some(Error::Envelope(e @ EnvelopeError::CurveMismatch { .. })) => {
+            let expected = crate::container::curve_id::<E>();
+            assert_eq!(
+                e,
+                EnvelopeError::CurveMismatch {
+                    expected,
+                    found: [7u8; 16]
+                }
+            );
+            let display = format!("the proof is over the curve {}, not", "07".repeat(16));
+            assert!(e.to_string().starts_with(&display));
+        },
+        _ => panic!("expected a curve mismatch"),
+    }
+    for len in &[20, 100, bytes.len() - 1] {
+        assert!(matches!(
+            reject(&bytes[..*len]),
+            Some(Error::Envelope(EnvelopeError::Malformed))
+        ));
+    }
+    let mut extended = bytes.clone();
+    extended.push(0);
+    assert!(matches!(
+        reject(&extended),
+        Some(Error::Envelope(EnvelopeError::Malformed))
+    ));
+
+    assert_eq!(Proof::<E>::fixed_size(), N);
+    assert_eq!(proof.serialized_size(), N);
+    let fixed: [u8; N] = proof.to_fixed_bytes().unwrap();
+    assert!(proof.to_fixed_bytes::<7>().is_err());
+    assert_eq!(Proof::<E>::from_fixed_bytes(&fixed).unwrap(), proof);
+    assert!(Proof::<E>::from_fixed_bytes(&[0u8; 7]).is_err());
+}
+
+fn test_prepared_verifying_key_serialization<E>()
+where
+    E: PairingEngine,
+    E::G2Prepared: SerializablePreparedG2,
+{
+    let rng = &mut test_rng();
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+
+    let a = E::Fr::rand(rng);
+    let b = E::Fr::rand(rng);
+    let mut c = a;
+    c.mul_assign(&b);
+    let proof = create_random_proof(
+        MySillyCircuit {
+            a: Some(a),
+            b: Some(b),
+        },
+        &params,
+        rng,
+    )
+    .unwrap();
+
+    // The prepared elements are compared through their serialization.
+    let prepared_bytes = |pvk: &PreparedVerifyingKey<E>| {
+        let mut bytes = Vec::new();
+        pvk.gamma_g2_neg_pc.serialize_prepared(&mut bytes).unwrap();
+        pvk.delta_g2_pc.serialize_prepared(&mut bytes).unwrap();
+        assert_eq!(
+            bytes.len(),
+            pvk.gamma_g2_neg_pc.prepared_size() + pvk.delta_g2_pc.prepared_size()
+        );
+        bytes
+    };
+    let same = |a: &PreparedVerifyingKey<E>, b: &PreparedVerifyingKey<E>| {
+        a.vk == b.vk
+            && prepared_bytes(a) == prepared_bytes(b)
+            && a.delta_g2_window == b.delta_g2_window
+            && a.delta_g2_table == b.delta_g2_table
+            && a.gamma_abc_g1_window == b.gamma_abc_g1_window
+            && a.gamma_abc_g1_tables == b.gamma_abc_g1_tables
+    };
+
+    let mut compressed = Vec::new();
+    pvk.serialize(&mut compressed).unwrap();
+    assert_eq!(compressed.len(), pvk.serialized_size());
+    let mut uncompressed = Vec::new();
+    pvk.serialize_uncompressed(&mut uncompressed).unwrap();
+    assert_eq!(uncompressed.len(), pvk.uncompressed_size());
+
+    let read = PreparedVerifyingKey::<E>::deserialize(&compressed[..]).unwrap();
+    assert!(same(&read, &pvk));
+    assert!(verify_proof(&read, &proof, &[c]).unwrap());
+    assert!(!verify_proof(&read, &proof, &[a]).unwrap());
+    let read = PreparedVerifyingKey::<E>::deserialize_uncompressed(&uncompressed[..]).unwrap();
+    assert!(same(&read, &pvk));
+    let read = PreparedVerifyingKey::<E>::deserialize_unchecked(&uncompressed[..]).unwrap();
+    assert!(same(&read, &pvk));
+
+    // Other windows give the same verifications.
+    for &window in &[1, 3, 8] {
+        let pvk = prepare_verifying_key_with_window::<E>(&params.vk, window);
+        assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
+        assert!(!verify_proof(&pvk, &proof, &[a]).unwrap());
+        let mut bytes = Vec::new();
+        pvk.serialize(&mut bytes).unwrap();
+        assert!(same(
+            &PreparedVerifyingKey::<E>::deserialize(&bytes[..]).unwrap(),
+            &pvk
+        ));
+    }
+
+    // Damaged keys are rejected.
+    for &i in &[compressed.len() / 2, compressed.len() - 1] {
+        let mut damaged = compressed.clone();
+        damaged[i] ^= 1;
+        assert!(PreparedVerifyingKey::<E>::deserialize(&damaged[..]).is_err());
+    }
+    assert!(PreparedVerifyingKey::<E>::deserialize(&compressed[..compressed.len() - 1]).is_err());
+    let mut damaged = compressed.clone();
+    damaged[pvk.vk.serialized_size() + 40] ^= 1;
+    assert!(PreparedVerifyingKey::<E>::deserialize(&damaged[..]).is_err());
+    let mut damaged = uncompressed.clone();
+    damaged[uncompressed.len() / 2] ^= 1;
+    assert!(PreparedVerifyingKey::<E>::deserialize_unchecked(&damaged[..]).is_err());
+
+    // So is a table of another shape, even with a matching checksum.
+    let mut truncated = pvk.clone();
+    truncated.delta_g2_table.pop();
+    let mut bytes = Vec::new();
+    truncated.serialize(&mut bytes).unwrap();
+    assert!(PreparedVerifyingKey::<E>::deserialize(&bytes[..]).is_err());
+    let mut other_base = prepare_verifying_key::<E>(&params.vk);
+    other_base.vk.delta_g2 = params.vk.gamma_g2;
+    let mut bytes = Vec::new();
+    other_base.serialize_uncompressed(&mut bytes).unwrap();
+    assert!(PreparedVerifyingKey::<E>::deserialize_unchecked(&bytes[..]).is_err());
+
+    // A checked read also rejects tables and prepared elements that are not
+    // derived from the verification key, which an unchecked read trusts.
+    let mut other_entry = pvk.clone();
+    other_entry.delta_g2_table[1][2] = params.vk.gamma_g2;
+    let mut other_prepared = pvk.clone();
+    other_prepared.delta_g2_pc = params.vk.gamma_g2.into();
+    for forged in [other_entry, other_prepared] {
+        let mut bytes = Vec::new();
+        forged.serialize_uncompressed(&mut bytes).unwrap();
+        assert!(PreparedVerifyingKey::<E>::deserialize_uncompressed(&bytes[..]).is_err());
+        assert!(PreparedVerifyingKey::<E>::deserialize_unchecked(&bytes[..]).is_ok());
+    }
+
+    // The tables of the public inputs are read back too, and checked.
+    let mut with_tables = pvk.clone();
+    add_input_tables(&mut with_tables, 2);
+    let mut bytes = Vec::new();
+    with_tables.serialize(&mut bytes).unwrap();
+    assert_eq!(bytes.len(), with_tables.serialized_size());
+    let read = PreparedVerifyingKey::<E>::deserialize(&bytes[..]).unwrap();
+    assert!(same(&read, &with_tables));
+    assert!(verify_proof(&read, &proof, &[c]).unwrap());
+    let mut other_tables = with_tables.clone();
+    add_input_tables(&mut other_tables, 3);
+    other_tables.gamma_abc_g1_window = 2;
+    let mut bytes = Vec::new();
+    other_tables.serialize_uncompressed(&mut bytes).unwrap();
+    assert!(PreparedVerifyingKey::<E>::deserialize_unchecked(&bytes[..]).is_err());
+}
+
+/// Checks that `backend` computes the same MSMs as the reference backend,
+/// including for empty inputs, special scalars and mismatched lengths.
+fn test_msm_backend<E, B>(backend: &B)
+where
+    E: PairingEngine,
+    B: MsmBackend,
+{
+    fn check_variable_base<G: AffineCurve, B: MsmBackend>(backend: &B, rng: &mut impl Rng) {
+        let lengths = [(0, 0), (1, 1), (7, 7), (100, 100), (10, 12), (12, 10)];
+        for &(num_bases, num_scalars) in &lengths {
+            let bases = (0..num_bases)
+                .map(|_| G::Projective::rand(rng).into_affine())
+                .collect::<Vec<_>>();
+            let mut scalars = (0..num_scalars)
+                .map(|_| G::ScalarField::rand(rng))
+                .collect::<Vec<_>>();
+            for (scalar, special) in scalars.iter_mut().zip(&[
+                G::ScalarField::zero(),
+                G::ScalarField::one(),
+                -G::ScalarField::one(),
+            ]) {
+                *scalar = *special;
+            }
+            let scalars = scalars.iter().map(|s| s.into_repr()).collect::<Vec<_>>();
+
+            assert_eq!(
+                backend.variable_base_msm(&bases, &scalars),
+                NaiveMsm.variable_base_msm(&bases, &scalars)
+            );
+
+            // Keep the top `num_bits` bits, so that the scalars are less than
+            // `2^num_bits`.
+            let modulus_bits = G::ScalarField::size_in_bits();
+            for &num_bits in &[1, 10, 64, 100, modulus_bits] {
+                let short_scalars = scalars
+                    .iter()
+                    .map(|s| {
+                        let mut s = *s;
+                        s.divn((modulus_bits - num_bits) as u32);
+                        s
+                    })
+                    .collect::<Vec<_>>();
+
+                assert_eq!(
+                    backend.short_scalar_msm(&bases, &short_scalars, num_bits),
+                    NaiveMsm.variable_base_msm(&bases, &short_scalars)
+                );
+            }
+        }
+    }
+
+    fn check_fixed_base<G: ProjectiveCurve, B: MsmBackend>(backend: &B, rng: &mut impl Rng) {
+        let base = G::rand(rng);
+        let batches = [0, 1, 5, 50]
+            .iter()
+            .map(|&n| {
+                let mut scalars = (0..n)
+                    .map(|_| G::ScalarField::rand(rng))
+                    .collect::<Vec<_>>();
+                if let Some(first) = scalars.first_mut() {
+                    *first = G::ScalarField::zero();
+                }
+                scalars
+            })
+            .collect::<Vec<_>>();
+        let batches = batches.iter().map(|s| s.as_slice()).collect::<Vec<_>>();
+
+        let results = backend.fixed_base_msm(base, &batches);
+        assert_eq!(results.len(), batches.len());
+        for (result, expected) in results.iter().zip(NaiveMsm.fixed_base_msm(base, &batches)) {
+            assert_eq!(result.len(), expected.len());
+            for (r, e) in result.iter().zip(&expected) {
+                assert_eq!(r.into_affine(), e.into_affine());
+            }
+        }
+    }
+
+    let rng = &mut test_rng();
+    check_variable_base::<E::G1Affine, B>(backend, rng);
+    check_variable_base::<E::G2Affine, B>(backend, rng);
+    check_fixed_base::<E::G1Projective, B>(backend, rng);
+    check_fixed_base::<E::G2Projective, B>(backend, rng);
+}
+
+fn test_prove_with_backend<E>()
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+
+    let (alpha, beta, gamma, delta) = (
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+    );
+    let g1_generator = E::G1Projective::rand(rng);
+    let g2_generator = E::G2Projective::rand(rng);
+    let setup_circuit = MySillyCircuit::<E::Fr> { a: None, b: None };
+
+    let params = generate_parameters::<E, _, _>(
+        setup_circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        &mut test_rng(),
+    )
+    .unwrap();
+    let naive_params = generate_parameters_with_backend::<E, _, _, _>(
+        setup_circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        &mut test_rng(),
+        &NaiveMsm,
+    )
+    .unwrap();
+    assert_eq!(params, naive_params);
+
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+    let matrices = constraint_matrices(setup_circuit).unwrap();
+    let ctx = ProverContext::new(&params, matrices)
+        .unwrap()
+        .with_backend(NaiveMsm);
+
+    let a = E::Fr::rand(rng);
+    let b = E::Fr::rand(rng);
+    let mut c = a;
+    c.mul_assign(&b);
+    let circuit = MySillyCircuit {
+        a: Some(a),
+        b: Some(b),
+    };
+    let (r, s, zeta) = (E::Fr::rand(rng), E::Fr::rand(rng), E::Fr::rand(rng));
+
+    let proof = ctx.create_proof(circuit, r, s, zeta).unwrap();
+    assert_eq!(proof, create_proof(circuit, &params, r, s, zeta).unwrap());
+    assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
+}
+
+fn test_prove_with_precomputed_key<E>(n_iters: usize)
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+    let matrices = constraint_matrices(MySillyCircuit::<E::Fr> { a: None, b: None }).unwrap();
+
+    // Factors that divide the scalar bit length and ones that do not.
+    for &factor in &[1, 2, 3, 8] {
+        let precomputed_pk = PrecomputedProvingKey::new(&params, factor);
+        assert_eq!(precomputed_pk.factor(), factor);
+
+        let ctx = ProverContext::new(&precomputed_pk, matrices.clone()).unwrap();
+        let low_memory_ctx = ProverContext::new(&precomputed_pk, matrices.clone())
+            .unwrap()
+            .with_memory_budget(1);
+
+        for _ in 0..n_iters {
+            let a = E::Fr::rand(rng);
+            let b = E::Fr::rand(rng);
+            let mut c = a;
+            c.mul_assign(&b);
+
+            let circuit = MySillyCircuit {
+                a: Some(a),
+                b: Some(b),
+            };
+            let r = E::Fr::rand(rng);
+            let s = E::Fr::rand(rng);
+            let zeta = E::Fr::rand(rng);
+
+            let proof = ctx.create_proof(circuit, r, s, zeta).unwrap();
+            assert_eq!(proof, create_proof(circuit, &params, r, s, zeta).unwrap());
+            assert_eq!(
+                proof,
+                low_memory_ctx.create_proof(circuit, r, s, zeta).unwrap()
+            );
+
+            assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
+        }
+    }
+}
+
+fn test_prove_with_sparse_key<E>(n_iters: usize)
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+    let sparse_pk = SparseProvingKey::new(&params);
+
+    // Only `a` is in the A-query, and only `b` is in the B-query, apart from
+    // the instance variables that the QAP adds to the A-query.
+    let num_variables = params.a_query.len();
+    assert_eq!(sparse_pk.a_density.num_variables(), num_variables);
+    assert!(sparse_pk.a_density.contains(2));
+    assert!(!sparse_pk.a_density.contains(3));
+    assert!(!sparse_pk.a_density.contains(num_variables));
+    assert_eq!(sparse_pk.b_density.count(), 1);
+    assert!(sparse_pk.b_density.contains(3));
+    assert_eq!(sparse_pk.a_query.len(), sparse_pk.a_density.count());
+    assert_eq!(sparse_pk.b_g1_query.len(), 1);
+    assert_eq!(sparse_pk.b_g2_query.len(), 1);
+
+    let mut bytes = Vec::new();
+    sparse_pk.serialize(&mut bytes).unwrap();
+    assert!(bytes.len() < params.serialized_size());
+    assert_eq!(
+        SparseProvingKey::<E>::deserialize(&bytes[..]).unwrap(),
+        sparse_pk
+    );
+
+    // Keys whose queries do not match their densities are not read.
+    let mut short_pk = sparse_pk.clone();
+    short_pk.b_g1_query.pop();
+    assert!(short_pk.check().is_err());
+    let mut bytes = Vec::new();
+    short_pk.serialize(&mut bytes).unwrap();
+    assert!(SparseProvingKey::<E>::deserialize(&bytes[..]).is_err());
+    let mut wide_pk = sparse_pk.clone();
+    wide_pk.b_density = QueryDensity::new(num_variables + 1, |i| i == 3);
+    assert!(wide_pk.check().is_err());
+
+    // Nor are densities with bits beyond their variables.
+    let mut bytes = Vec::new();
+    (3usize, vec![0b1001u64]).serialize(&mut bytes).unwrap();
+    assert!(QueryDensity::deserialize(&bytes[..]).is_err());
+    let mut bytes = Vec::new();
+    (3usize, vec![0b101u64, 0]).serialize(&mut bytes).unwrap();
+    assert!(QueryDensity::deserialize(&bytes[..]).is_err());
+    let mut bytes = Vec::new();
+    (3usize, vec![0b101u64]).serialize(&mut bytes).unwrap();
+    assert_eq!(
+        QueryDensity::deserialize(&bytes[..]).unwrap(),
+        QueryDensity::new(3, |i| i != 1)
+    );
+
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+    let matrices = constraint_matrices(MySillyCircuit::<E::Fr> { a: None, b: None }).unwrap();
+    let ctx = ProverContext::new(&sparse_pk, matrices.clone()).unwrap();
+    let low_memory_ctx = ProverContext::new(&sparse_pk, matrices)
+        .unwrap()
+        .with_memory_budget(1);
+
+    for i in 0..n_iters {
+        let a = E::Fr::rand(rng);
+        let b = E::Fr::rand(rng);
+        let mut c = a;
+        c.mul_assign(&b);
+
+        let circuit = MySillyCircuit {
+            a: Some(a),
+            b: Some(b),
+        };
+        // Also without the B-query in `E::G1`.
+        let r = if i == 0 {
+            E::Fr::zero()
+        } else {
+            E::Fr::rand(rng)
+        };
+        let s = E::Fr::rand(rng);
+        let zeta = E::Fr::rand(rng);
+
+        let proof = ctx.create_proof(circuit, r, s, zeta).unwrap();
+        assert_eq!(proof, create_proof(circuit, &params, r, s, zeta).unwrap());
+        assert_eq!(
+            proof,
+            low_memory_ctx.create_proof(circuit, r, s, zeta).unwrap()
+        );
+
+        assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
+    }
+}
+
+fn test_prove_with_precomputed_randomness<E>(n_iters: usize)
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+    let other_params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+    let matrices = constraint_matrices(MySillyCircuit::<E::Fr> { a: None, b: None }).unwrap();
+    let ctx = ProverContext::new(&params, matrices).unwrap();
+
+    for _ in 0..n_iters {
+        let a = E::Fr::rand(rng);
+        let b = E::Fr::rand(rng);
+        let mut c = a;
+        c.mul_assign(&b);
+
+        let circuit = MySillyCircuit {
+            a: Some(a),
+            b: Some(b),
+        };
+        let r = E::Fr::rand(rng);
+        let s = E::Fr::rand(rng);
+        let zeta = E::Fr::rand(rng);
+
+        let expected = create_proof(circuit, &params, r, s, zeta).unwrap();
+        let randomness = ProofRandomness::new(&params, r, s, zeta);
+        let proof = create_proof_with_precomputed(circuit, &params, randomness).unwrap();
+        assert_eq!(proof, expected);
+        let randomness = ProofRandomness::new(&params, r, s, zeta);
+        assert_eq!(
+            ctx.create_proof_with_precomputed(circuit, randomness)
+                .unwrap(),
+            expected
+        );
+        assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
+
+        // Precomputing via `rng` consumes it like `create_random_proof`.
+        let randomness = ProofRandomness::precompute(&params, &mut test_rng());
+        assert_eq!(
+            create_proof_with_precomputed(circuit, &params, randomness).unwrap(),
+            create_random_proof(circuit, &params, &mut test_rng()).unwrap()
+        );
+
+        // Randomness precomputed for another proving key is rejected.
+        let randomness = ProofRandomness::precompute(&other_params, rng);
+        assert!(create_proof_with_precomputed(circuit, &params, randomness).is_err());
+    }
+}
+
+fn test_reprove<E>(n_iters: usize)
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+    let matrices = constraint_matrices(MySillyCircuit::<E::Fr> { a: None, b: None }).unwrap();
+    let ctx = ProverContext::new(&params, matrices).unwrap();
+
+    let a = E::Fr::rand(rng);
+    let b = E::Fr::rand(rng);
+    let mut c = a;
+    c.mul_assign(&b);
+    let circuit = MySillyCircuit {
+        a: Some(a),
+        b: Some(b),
+    };
+
+    let prepared = prepare_witness(circuit, &params).unwrap();
+    let ctx_prepared = ctx.prepare_witness(circuit).unwrap();
+
+    let mut proofs = Vec::new();
+    for i in 0..n_iters as u64 {
+        // A proof from the prepared witness is the same function of the same
+        // randomness as an ordinary proof, so the two are identically
+        // distributed.
+        let proof = reprove(&prepared, &params, &mut StdRng::seed_from_u64(i)).unwrap();
+        assert_eq!(
+            proof,
+            create_random_proof(circuit, &params, &mut StdRng::seed_from_u64(i)).unwrap()
+        );
+        assert_eq!(
+            proof,
+            reprove(&ctx_prepared, &params, &mut StdRng::seed_from_u64(i)).unwrap()
+        );
+        assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
+        assert!(!verify_proof(&pvk, &proof, &[a]).unwrap());
+
+        // Fresh randomness gives proofs that share no group elements.
+        let proof = reprove(&prepared, &params, rng).unwrap();
+        assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
+        proofs.push(proof);
+    }
+    for (i, p) in proofs.iter().enumerate() {
+        for q in &proofs[i + 1..] {
+            assert!(p.a != q.a && p.b != q.b && p.c != q.c && p.delta_prime != q.delta_prime);
+        }
+    }
+}
+
+fn test_prove_incrementally<E>(n_iters: usize)
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+
+    let params =
+        generate_random_parameters::<E, _, _>(MySillyCircuit { a: None, b: None }, rng).unwrap();
+
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+    let matrices = constraint_matrices(MySillyCircuit::<E::Fr> { a: None, b: None }).unwrap();
+    let precomputed_pk = PrecomputedProvingKey::new(&params, 2);
+    let ctx = ProverContext::new(&params, matrices.clone()).unwrap();
+    let low_memory_ctx = ProverContext::new(&params, matrices.clone())
+        .unwrap()
+        .with_memory_budget(1);
+    let precomputed_ctx = ProverContext::new(&precomputed_pk, matrices.clone()).unwrap();
+    let path = std::env::temp_dir().join(format!("bpr20-incremental-pk-{}", std::process::id()));
+    params
+        .write_chunked(std::fs::File::create(&path).unwrap(), 1)
+        .unwrap();
+    let chunked_pk = ChunkedProvingKey::<E>::open(&path).unwrap();
+    let chunked_ctx = ProverContext::new(&chunked_pk, matrices).unwrap();
+
+    for _ in 0..n_iters {
+        let a = E::Fr::rand(rng);
+        let b = E::Fr::rand(rng);
+        let mut c = a;
+        c.mul_assign(&b);
+
+        let circuit = MySillyCircuit {
+            a: Some(a),
+            b: Some(b),
+        };
+        let r = E::Fr::rand(rng);
+        let s = E::Fr::rand(rng);
+        let zeta = E::Fr::rand(rng);
+        let expected = create_proof(circuit, &params, r, s, zeta).unwrap();
+
+        // Prefixes of every length of the witness `(a, b)`.
+        let witness = [a, b];
+        for len in 0..=witness.len() {
+            let prefix = ctx.commit_witness_prefix(witness[..len].to_vec()).unwrap();
+            assert_eq!(prefix.len(), len);
+            let randomness = ProofRandomness::new(&params, r, s, zeta);
+            let proof = ctx.finish_proof(prefix, circuit, randomness).unwrap();
+            assert_eq!(proof, expected);
+
+            let prefix = low_memory_ctx
+                .commit_witness_prefix(witness[..len].to_vec())
+                .unwrap();
+            let randomness = ProofRandomness::new(&params, r, s, zeta);
+            assert_eq!(
+                low_memory_ctx
+                    .finish_proof(prefix, circuit, randomness)
+                    .unwrap(),
+                expected
+            );
+
+            let prefix = precomputed_ctx
+                .commit_witness_prefix(witness[..len].to_vec())
+                .unwrap();
+            let randomness = ProofRandomness::new(&precomputed_pk, r, s, zeta);
+            assert_eq!(
+                precomputed_ctx
+                    .finish_proof(prefix, circuit, randomness)
+                    .unwrap(),
+                expected
+            );
+
+            let prefix = chunked_ctx
+                .commit_witness_prefix(witness[..len].to_vec())
+                .unwrap();
+            let randomness = ProofRandomness::new(&chunked_pk, r, s, zeta);
+            assert_eq!(
+                chunked_ctx
+                    .finish_proof(prefix, circuit, randomness)
+                    .unwrap(),
+                expected
+            );
+        }
+        assert!(verify_proof(&pvk, &expected, &[c]).unwrap());
+
+        // A witness that does not start with the committed prefix is rejected.
+        let prefix = ctx.commit_witness_prefix(vec![b]).unwrap();
+        let randomness = ProofRandomness::new(&params, r, s, zeta);
+        assert!(matches!(
+            ctx.finish_proof(prefix, circuit, randomness),
+            Err(Error::WitnessPrefixMismatch)
+        ));
+
+        // So is a prefix longer than the witness.
+        assert!(matches!(
+            ctx.commit_witness_prefix(vec![a, b, c]),
+            Err(Error::WitnessPrefixMismatch)
+        ));
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn test_checkpoints<E>()
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+    let dir = std::env::temp_dir().join(format!("bpr20-checkpoints-{}", std::process::id()));
+    let checkpoints = Checkpoints::new(&dir).unwrap();
+    assert_eq!(checkpoints.dir(), dir.as_path());
+
+    // A saved result is resumed instead of computed again, but only by runs
+    // with the same fingerprint.
+    let options = RunOptions::new().with_checkpoints(&checkpoints);
+    let mut stages = options.stages();
+    stages.begin(|| [1; 64]);
+    let value = E::Fr::rand(rng);
+    let saved = stages.stage::<_, Error, _>(Phase::Qap, "value", || Ok(value));
+    assert_eq!(saved.unwrap(), value);
+    let resumed = stages.stage::<E::Fr, Error, _>(Phase::Qap, "value", || unreachable!());
+    assert_eq!(resumed.unwrap(), value);
+    let mut other_stages = options.stages();
+    other_stages.begin(|| [2; 64]);
+    let other_value = E::Fr::rand(rng);
+    let recomputed = other_stages.stage::<_, Error, _>(Phase::Qap, "value", || Ok(other_value));
+    assert_eq!(recomputed.unwrap(), other_value);
+    checkpoints.clear().unwrap();
+
+    let (alpha, beta, gamma, delta) = (
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+    );
+    let g1_generator = E::G1Projective::rand(rng);
+    let g2_generator = E::G2Projective::rand(rng);
+    let setup_circuit = MySillyCircuit::<E::Fr> { a: None, b: None };
+    let generate = |seed: u64, checkpoints: Option<&Checkpoints>| {
+        let rng = &mut StdRng::seed_from_u64(seed);
+        match checkpoints {
+            Some(checkpoints) => generate_parameters_with_checkpoints::<E, _, _>(
+                setup_circuit,
+                alpha,
+                beta,
+                gamma,
+                delta,
+                g1_generator,
+                g2_generator,
+                rng,
+                checkpoints,
+            )
+            .unwrap(),
+            None => generate_parameters::<E, _, _>(
+                setup_circuit,
+                alpha,
+                beta,
+                gamma,
+                delta,
+                g1_generator,
+                g2_generator,
+                rng,
+            )
+            .unwrap(),
+        }
+    };
+
+    // Cancels a run once the stage `name` is saved.
+    let interrupt = |name: &str, run: &dyn Fn(RunOptions<'_>) -> Result<(), Error>| {
+        let path = dir.join(format!("{}.ckpt", name));
+        let token = CancellationToken::new();
+        let cancel = |_: Phase, _: f64| {
+            if path.exists() {
+                token.cancel();
+            }
+        };
+        let options = RunOptions::new()
+            .with_checkpoints(&checkpoints)
+            .with_progress(&cancel)
+            .with_cancellation(&token);
+        assert!(matches!(run(options), Err(Error::Cancelled)));
+        assert!(path.exists());
+    };
+
+    // Runs that resume after each stage, from damaged results and from the
+    // results of a run with another RNG state all create the parameters of
+    // an uninterrupted run. The QAP evaluation is never saved, and a run
+    // that succeeds deletes the directory.
+    let params = generate(0, None);
+    assert_eq!(generate(0, Some(&checkpoints)), params);
+    assert!(!dir.exists());
+    let setup = |options: RunOptions<'_>| {
+        let rng = &mut StdRng::seed_from_u64(0);
+        generate_parameters_with_options::<E, _, _>(
+            setup_circuit,
+            alpha,
+            beta,
+            gamma,
+            delta,
+            g1_generator,
+            g2_generator,
+            rng,
+            options,
+        )
+        .map(|_| ())
+    };
+    let stages = ["b_g2_query", "a_query", "b_g1_query", "h_query", "l_query"];
+    for stage in stages.iter() {
+        interrupt(stage, &setup);
+        assert!(!dir.join("qap.ckpt").exists());
+    }
+    let path = dir.join("h_query.ckpt");
+    let mut bytes = std::fs::read(&path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    std::fs::write(&path, bytes).unwrap();
+    assert_eq!(generate(0, Some(&checkpoints)), params);
+    assert!(!dir.exists());
+    interrupt("a_query", &setup);
+    assert_eq!(generate(1, Some(&checkpoints)), generate(1, None));
+    assert!(!dir.exists());
+
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+    let matrices = constraint_matrices(setup_circuit).unwrap();
+    let ctx = ProverContext::new(&params, matrices).unwrap();
+    for _ in 0..3 {
+        let a = E::Fr::rand(rng);
+        let b = E::Fr::rand(rng);
+        let mut c = a;
+        c.mul_assign(&b);
+        let circuit = MySillyCircuit {
+            a: Some(a),
+            b: Some(b),
+        };
+        let (r, s, zeta) = (E::Fr::rand(rng), E::Fr::rand(rng), E::Fr::rand(rng));
+
+        // Without `r`, the sum over the B-query in `G1` is not saved, and is
+        // computed once a proof needs it.
+        let zero = E::Fr::zero();
+        let proof = ctx.create_proof(circuit, zero, s, zeta).unwrap();
+        let resumed = ctx.create_proof_with_checkpoints(circuit, zero, s, zeta, &checkpoints);
+        assert_eq!(resumed.unwrap(), proof);
+        assert!(!dir.exists());
+
+        // The coefficients of `h` are never saved.
+        let proof = ctx.create_proof(circuit, r, s, zeta).unwrap();
+        let prove = |options: RunOptions<'_>| {
+            ctx.create_proof_with_options(circuit, r, s, zeta, options)
+                .map(|_| ())
+        };
+        for stage in ["b_g2_sum", "a_sum", "b_g1_sum", "h_sum"].iter() {
+            interrupt(stage, &prove);
+            assert!(!dir.join("h.ckpt").exists());
+        }
+        let resumed = ctx.create_proof_with_checkpoints(circuit, r, s, zeta, &checkpoints);
+        assert_eq!(resumed.unwrap(), proof);
+        assert!(!dir.exists());
+        assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
+    }
+
+    // Files other than results are kept, and so is their directory.
+    let other = dir.join("other");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(&other, b"other").unwrap();
+    assert_eq!(generate(0, Some(&checkpoints)), params);
+    assert!(other.exists());
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Checks that the reports of each phase in `reports` start at `0.0`, never
+/// decrease and end at `1.0`, and that they cover exactly `phases`.
+fn check_reports(reports: &[(Phase, f64)], phases: &[Phase]) {
+    for phase in phases.iter() {
+        let fractions = reports
+            .iter()
+            .filter(|(p, _)| p == phase)
+            .map(|(_, fraction)| *fraction)
+            .collect::<Vec<_>>();
+        assert_eq!(fractions.first(), Some(&0.0), "{:?}", phase);
+        assert_eq!(fractions.last(), Some(&1.0), "{:?}", phase);
+        assert!(fractions.windows(2).all(|w| w[0] <= w[1]), "{:?}", phase);
+    }
+    assert!(reports.iter().all(|(p, _)| phases.contains(p)));
+}
+
+fn test_progress_and_cancellation<E>()
+where
+    E: PairingEngine,
+{
+    use std::sync::Mutex;
+
+    let rng = &mut test_rng();
+    let (alpha, beta, gamma, delta) = (
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+    );
+    let g1_generator = E::G1Projective::rand(rng);
+    let g2_generator = E::G2Projective::rand(rng);
+    let setup_circuit = MySillyCircuit::<E::Fr> { a: None, b: None };
+    let generate = |options: RunOptions<'_>| {
+        generate_parameters_with_options::<E, _, _>(
+            setup_circuit,
+            alpha,
+            beta,
+            gamma,
+            delta,
+            g1_generator,
+            g2_generator,
+            &mut StdRng::seed_from_u64(0),
+            options,
+        )
+    };
+    let reports = Mutex::new(Vec::new());
+    let record = |phase: Phase, fraction: f64| reports.lock().unwrap().push((phase, fraction));
+
+    // An observed setup reports each of its phases, and creates the
+    // parameters of an unobserved one.
+    let params = generate(RunOptions::new()).unwrap();
+    assert_eq!(
+        generate(RunOptions::new().with_progress(&record)).unwrap(),
+        params
+    );
+    let msm_phases = [Query::A, Query::BG1, Query::BG2, Query::H, Query::L];
+    let mut setup_phases = vec![Phase::Synthesis, Phase::Qap, Phase::Msm(Query::GammaAbc)];
+    setup_phases.extend(msm_phases.iter().map(|&query| Phase::Msm(query)));
+    check_reports(&reports.lock().unwrap(), &setup_phases);
+
+    // A cancelled setup stops, whether it is cancelled before it starts or
+    // part way through an MSM.
+    let cancellation = CancellationToken::new();
+    cancellation.cancel();
+    let result = generate(RunOptions::new().with_cancellation(&cancellation));
+    assert!(matches!(result, Err(Error::Cancelled)));
+    let cancellation = CancellationToken::new();
+    let cancel = |phase: Phase, fraction: f64| {
+        if phase == Phase::Msm(Query::BG2) && fraction > 0.0 {
+            cancellation.cancel();
+        }
+    };
+    let options = RunOptions::new()
+        .with_progress(&cancel)
+        .with_cancellation(&cancellation);
+    assert!(matches!(generate(options), Err(Error::Cancelled)));
+
+    let pvk = prepare_verifying_key::<E>(&params.vk);
+    let matrices = constraint_matrices(setup_circuit).unwrap();
+    let ctx = ProverContext::new(&params, matrices.clone()).unwrap();
+    let low_memory_ctx = ProverContext::new(&params, matrices)
+        .unwrap()
+        .with_memory_budget(1);
+    let a = E::Fr::rand(rng);
+    let b = E::Fr::rand(rng);
+    let mut c = a;
+    c.mul_assign(&b);
+    let circuit = MySillyCircuit {
+        a: Some(a),
+        b: Some(b),
+    };
+    let (r, s, zeta) = (E::Fr::rand(rng), E::Fr::rand(rng), E::Fr::rand(rng));
+
+    // An observed proof reports each of its phases, and is the same as an
+    // unobserved one.
+    let proof = ctx.create_proof(circuit, r, s, zeta).unwrap();
+    for ctx in [&ctx, &low_memory_ctx].iter() {
+        reports.lock().unwrap().clear();
+        let options = RunOptions::new().with_progress(&record);
+        let observed = ctx.create_proof_with_options(circuit, r, s, zeta, options);
+        assert_eq!(observed.unwrap(), proof);
+        let mut proof_phases = vec![Phase::Synthesis, Phase::Fft];
+        proof_phases.extend(msm_phases.iter().map(|&query| Phase::Msm(query)));
+        check_reports(&reports.lock().unwrap(), &proof_phases);
+    }
+    assert!(verify_proof(&pvk, &proof, &[c]).unwrap());
+
+    // A proof that is cancelled during the FFTs or an MSM stops.
+    for during in [Phase::Fft, Phase::Msm(Query::H)].iter() {
+        let cancellation = CancellationToken::new();
+        let cancel = |phase: Phase, fraction: f64| {
+            if phase == *during && fraction > 0.0 {
+                cancellation.cancel();
+            }
+        };
+        let options = RunOptions::new()
+            .with_progress(&cancel)
+            .with_cancellation(&cancellation);
+        let result = ctx.create_proof_with_options(circuit, r, s, zeta, options);
+        assert!(matches!(result, Err(Error::Cancelled)));
+    }
+}
+
+fn test_stats<E>()
+where
+    E: PairingEngine,
+{
+    let rng = &mut test_rng();
+    let (alpha, beta, gamma, delta) = (
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+        E::Fr::rand(rng),
+    );
+    let g1_generator = E::G1Projective::rand(rng);
+    let g2_generator = E::G2Projective::rand(rng);
+    let setup_circuit = MySillyCircuit::<E::Fr> { a: None, b: None };
+    let params = generate_parameters::<E, _, _>(
+        setup_circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        &mut StdRng::seed_from_u64(0),
+    )
+    .unwrap();
+    let (stats_params, setup_stats) = generate_parameters_with_stats::<E, _, _>(
+        setup_circuit,
+        alpha,
+        beta,
+        gamma,
+        delta,
+        g1_generator,
+        g2_generator,
+        &mut StdRng::seed_from_u64(0),
+    )
+    .unwrap();
+    assert_eq!(stats_params, params);
+
+    // The circuit has six constraints over the `one` variable, `c`, `a` and
+    // `b`.
+    let circuit_stats = CircuitStats {
+        num_constraints: 6,
+        num_instance_variables: 2,
+        num_witness_variables: 2,
+        domain_size: 8,
+    };
+    assert_eq!(setup_stats.circuit, circuit_stats);
+    assert_eq!(setup_stats.msm_size(Query::A), Some(4));
+    assert_eq!(setup_stats.msm_size(Query::H), Some(7));
+    assert_eq!(setup_stats.msm_size(Query::L), Some(2));
+    assert_eq!(setup_stats.msm_size(Query::GammaAbc), Some(2));
+    let mut setup_phases = vec![Phase::Synthesis, Phase::Qap, Phase::Msm(Query::GammaAbc)];
+    let msm_phases = [Query::A, Query::BG1, Query::BG2, Query::H, Query::L];
+    setup_phases.extend(msm_phases.iter().map(|&query| Phase::Msm(query)));
+    assert_eq!(setup_stats.durations.len(), setup_phases.len());
+    for phase in setup_phases.iter() {
+        assert!(setup_stats.duration(*phase).unwrap() <= setup_stats.total_duration);
+    }
+
+    let matrices = constraint_matrices(setup_circuit).unwrap();
+    let ctx = ProverContext::new(&params, matrices).unwrap();
+    let a = E::Fr::rand(rng);
+    let b = E::Fr::rand(rng);
+    let circuit = MySillyCircuit {
+        a: Some(a),
+        b: Some(b),
+    };
+    let (r, s, zeta) = (E::Fr::rand(rng), E::Fr::rand(rng), E::Fr::rand(rng));
+    let proof = ctx.create_proof(circuit, r, s, zeta).unwrap();
+    let (stats_proof, proof_stats) = ctx.create_proof_with_stats(circuit, r, s, zeta).unwrap();
+    assert_eq!(stats_proof, proof);
+    assert_eq!(proof_stats.circuit, circuit_stats);
+    assert_eq!(proof_stats.msm_size(Query::BG1), Some(4));
+    assert_eq!(proof_stats.msm_size(Query::H), Some(7));
+    assert_eq!(proof_stats.msm_size(Query::L), Some(2));
+    let mut proof_phases = vec![Phase::Synthesis, Phase::Fft];
+    proof_phases.extend(msm_phases.iter().map(|&query| Phase::Msm(query)));
+    assert_eq!(proof_stats.durations.len(), proof_phases.len());
+    for phase in proof_phases.iter() {
+        assert!(proof_stats.duration(*phase).unwrap() <= proof_stats.total_duration);
+    }
+
+    // Without `r`, there is no MSM over the B-query in `G1`.
+    let zero = E::Fr::zero();
+    let (_, proof_stats) = ctx.create_proof_with_stats(circuit, zero, s, zeta).unwrap();
+    assert_eq!(proof_stats.msm_size(Query::BG1), None);
+    assert_eq!(proof_stats.duration(Phase::Msm(Query::BG1)), None);
+}
+
 mod bls12_377 {
-    use super::{test_prove_and_verify};
+    use super::{
+        test_checkpoints, test_generate_chunked_parameters, test_key_container, test_msm_backend,
+        test_prepare_inputs, test_prepared_verifying_key_serialization,
+        test_progress_and_cancellation, test_proof_envelope, test_prove_and_verify,
+        test_prove_batch, test_prove_incrementally, test_prove_with_backend,
+        test_prove_with_chunked_key, test_prove_with_context, test_prove_with_precomputed_key,
+        test_prove_with_precomputed_randomness, test_prove_with_sparse_key, test_reprove,
+        test_specialize, test_stats, test_verify_both_ways, ArkworksMsm,
+    };
+    use crate::envelope::BLS12_377_PROOF_LEN;
     use ark_bls12_377::Bls12_377;
 
     #[test]
@@ -86,10 +1913,113 @@ mod bls12_377 {
         test_prove_and_verify::<Bls12_377>(100);
     }
 
+    #[test]
+    fn verify_both_ways() {
+        test_verify_both_ways::<Bls12_377>(10);
+    }
+
+    #[test]
+    fn prepare_inputs() {
+        test_prepare_inputs::<Bls12_377>();
+    }
+
+    #[test]
+    fn specialize() {
+        test_specialize::<Bls12_377>(10);
+    }
+
+    #[test]
+    fn prove_with_context() {
+        test_prove_with_context::<Bls12_377>(10);
+    }
+
+    #[test]
+    fn prove_batch() {
+        test_prove_batch::<Bls12_377>(10);
+    }
+
+    #[test]
+    fn prove_with_chunked_key() {
+        test_prove_with_chunked_key::<Bls12_377>(5);
+    }
+
+    #[test]
+    fn generate_chunked_parameters() {
+        test_generate_chunked_parameters::<Bls12_377>();
+    }
+
+    #[test]
+    fn key_container() {
+        test_key_container::<Bls12_377>();
+    }
+
+    #[test]
+    fn proof_envelope() {
+        test_proof_envelope::<Bls12_377, BLS12_377_PROOF_LEN>();
+    }
+
+    #[test]
+    fn prepared_verifying_key_serialization() {
+        test_prepared_verifying_key_serialization::<Bls12_377>();
+    }
+
+    #[test]
+    fn msm_backends() {
+        test_msm_backend::<Bls12_377, _>(&ArkworksMsm);
+    }
+
+    #[test]
+    fn prove_with_backend() {
+        test_prove_with_backend::<Bls12_377>();
+    }
+
+    #[test]
+    fn prove_with_precomputed_key() {
+        test_prove_with_precomputed_key::<Bls12_377>(3);
+    }
+
+    #[test]
+    fn prove_with_sparse_key() {
+        test_prove_with_sparse_key::<Bls12_377>(3);
+    }
+
+    #[test]
+    fn prove_with_precomputed_randomness() {
+        test_prove_with_precomputed_randomness::<Bls12_377>(5);
+    }
+
+    #[test]
+    fn reprove() {
+        test_reprove::<Bls12_377>(10);
+    }
+
+    #[test]
+    fn prove_incrementally() {
+        test_prove_incrementally::<Bls12_377>(3);
+    }
+
+    #[test]
+    fn checkpoints() {
+        test_checkpoints::<Bls12_377>();
+    }
+
+    #[test]
+    fn progress_and_cancellation() {
+        test_progress_and_cancellation::<Bls12_377>();
+    }
+
+    #[test]
+    fn stats() {
+        test_stats::<Bls12_377>();
+    }
 }
 
 mod cp6_782 {
-    use super::{test_prove_and_verify};
+    use super::{
+        test_proof_envelope, test_prove_and_verify, test_prove_with_context,
+        test_prove_with_sparse_key, test_verify_both_ways,
+    };
+    use crate::envelope::CP6_782_PROOF_LEN;
 
     use ark_cp6_782::CP6_782;
 
@@ -98,4 +2028,23 @@ mod cp6_782 {
         test_prove_and_verify::<CP6_782>(1);
     }
 
+    #[test]
+    fn verify_both_ways() {
+        test_verify_both_ways::<CP6_782>(1);
+    }
+
+    #[test]
+    fn prove_with_context() {
+        test_prove_with_context::<CP6_782>(1);
+    }
+
+    #[test]
+    fn prove_with_sparse_key() {
+        test_prove_with_sparse_key::<CP6_782>(2);
+    }
+
+    #[test]
+    fn proof_envelope() {
+        test_proof_envelope::<CP6_782, CP6_782_PROOF_LEN>();
+    }
 }
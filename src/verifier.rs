@@ -1,41 +1,212 @@
-use ark_ec::msm::FixedBaseMSM;
+use ark_ec::msm::{FixedBaseMSM, VariableBaseMSM};
 use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
-use ark_ff::{PrimeField, to_bytes,};
+use ark_ff::PrimeField;
+use ark_std::{borrow::Cow, cfg_iter, vec, vec::Vec};
 
-use super::{PreparedVerifyingKey, Proof, VerifyingKey};
+use super::{proof_challenge, PreparedFixedInputs, PreparedVerifyingKey, Proof, VerifyingKey};
 
 use ark_relations::r1cs::{Result as R1CSResult, SynthesisError};
 
 use core::ops::{AddAssign, Neg};
 
-use blake2::{Blake2b, Digest};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// The default window size of the table of multiples of `delta * H` in a
+/// [`PreparedVerifyingKey`].
+pub const DELTA_G2_WINDOW: usize = 5;
 
 /// Prepare the verifying key `vk` for use in proof verification.
 pub fn prepare_verifying_key<E: PairingEngine>(vk: &VerifyingKey<E>) -> PreparedVerifyingKey<E> {
+    prepare_verifying_key_with_window(vk, DELTA_G2_WINDOW)
+}
+
+/// Prepare the verifying key `vk` for use in proof verification, with a
+/// table of multiples of `delta * H` of window size `window`. Larger windows
+/// make the multiplication by the challenge of a proof faster, at the cost
+/// of a table of `2^window * ceil(r / window)` elements of `E::G2`, where
+/// `r` is the size of the scalar field.
+///
+/// # Panics
+///
+/// Panics if `window` is 0.
+pub fn prepare_verifying_key_with_window<E: PairingEngine>(
+    vk: &VerifyingKey<E>,
+    window: usize,
+) -> PreparedVerifyingKey<E> {
+    assert!(window > 0, "the window size must be positive");
+    let delta_g2_table = FixedBaseMSM::get_window_table::<E::G2Projective>(
+        E::Fr::size_in_bits(),
+        window,
+        vk.delta_g2.into_projective(),
+    );
     PreparedVerifyingKey {
         vk: vk.clone(),
         gamma_g2_neg_pc: vk.gamma_g2.neg().into(),
+        delta_g2_pc: vk.delta_g2.into(),
+        delta_g2_window: window,
+        delta_g2_table,
+        gamma_abc_g1_window: 0,
+        gamma_abc_g1_tables: Vec::new(),
     }
 }
 
+/// Add to `pvk` a table of multiples of window size `window` for each
+/// element of `gamma_abc_g1` after the first, which [`prepare_inputs`] then
+/// uses instead of a multi-scalar multiplication. The tables take
+/// `2^window * ceil(r / window)` elements of `E::G1` per public input, where
+/// `r` is the size of the scalar field, and pay off most for keys with few
+/// public inputs that verify many proofs; `benches/bench.rs` compares the
+/// ways of preparing inputs.
+///
+/// # Panics
+///
+/// Panics if `window` is 0.
+pub fn add_input_tables<E: PairingEngine>(pvk: &mut PreparedVerifyingKey<E>, window: usize) {
+    assert!(window > 0, "the window size must be positive");
+    let scalar_size = E::Fr::size_in_bits();
+    pvk.gamma_abc_g1_window = window;
+    pvk.gamma_abc_g1_tables = pvk.vk.gamma_abc_g1[1..]
+        .iter()
+        .map(|base| FixedBaseMSM::get_window_table(scalar_size, window, base.into_projective()))
+        .collect();
+}
+
+/// The number of public inputs from which [`prepare_inputs`] runs a multi-scalar multiplication
+/// rather than one scalar multiplication per input.
+const MSM_MIN_INPUTS: usize = 4;
+
 /// Prepare proof inputs for use with [`verify_proof_with_prepared_inputs`], wrt the prepared
-/// verification key `pvk` and instance public inputs.
+/// verification key `pvk` and instance public inputs. This uses the tables of `pvk` added by
+/// [`add_input_tables`] if there are any, and otherwise a multi-scalar multiplication once there
+/// are enough inputs for it to be faster than one scalar multiplication per input.
 pub fn prepare_inputs<E: PairingEngine>(
     pvk: &PreparedVerifyingKey<E>,
     public_inputs: &[E::Fr],
 ) -> R1CSResult<E::G1Projective> {
-    if (public_inputs.len() + 1) != pvk.vk.gamma_abc_g1.len() {
+    if (public_inputs.len() + 1) != pvk.vk.gamma_abc_g1.len() || !pvk.has_valid_input_tables() {
         return Err(SynthesisError::MalformedVerifyingKey);
     }
 
+    let inputs_time = start_phase!("Prepare inputs");
     let mut g_ic = pvk.vk.gamma_abc_g1[0].into_projective();
-    for (i, b) in public_inputs.iter().zip(pvk.vk.gamma_abc_g1.iter().skip(1)) {
-        g_ic.add_assign(&b.mul(i.into_repr()));
+    add_inputs(pvk, &mut g_ic, None, public_inputs);
+    end_phase!(inputs_time);
+
+    Ok(g_ic)
+}
+
+/// Prepare the public inputs at the indices of `fixed` to the values of `fixed`, wrt the prepared
+/// verification key `pvk`, for use with [`prepare_remaining_inputs`]. The result depends only on
+/// `fixed`, so it can be computed once for all the proofs of statements that share these inputs.
+pub fn prepare_fixed_inputs<E: PairingEngine>(
+    pvk: &PreparedVerifyingKey<E>,
+    fixed: &[(usize, E::Fr)],
+) -> R1CSResult<PreparedFixedInputs<E>> {
+    let remaining = remaining_indices(pvk.vk.gamma_abc_g1.len(), fixed)?;
+    if !pvk.has_valid_input_tables() {
+        return Err(SynthesisError::MalformedVerifyingKey);
+    }
+
+    let inputs_time = start_phase!("Prepare fixed inputs");
+    let (indices, values): (Vec<_>, Vec<_>) = fixed.iter().copied().unzip();
+    let mut g_ic = pvk.vk.gamma_abc_g1[0].into_projective();
+    add_inputs(pvk, &mut g_ic, Some(&indices), &values);
+    end_phase!(inputs_time);
+
+    Ok(PreparedFixedInputs { g_ic, remaining })
+}
+
+/// Prepare proof inputs for use with [`verify_proof_with_prepared_inputs`], wrt the prepared
+/// verification key `pvk`, the prepared fixed inputs `fixed` and the other public inputs of the
+/// instance, in order. This gives the same result as [`prepare_inputs`] with all the public
+/// inputs.
+pub fn prepare_remaining_inputs<E: PairingEngine>(
+    pvk: &PreparedVerifyingKey<E>,
+    fixed: &PreparedFixedInputs<E>,
+    remaining_inputs: &[E::Fr],
+) -> R1CSResult<E::G1Projective> {
+    let num_inputs = pvk
+        .vk
+        .gamma_abc_g1
+        .len()
+        .checked_sub(1)
+        .ok_or(SynthesisError::MalformedVerifyingKey)?;
+    if remaining_inputs.len() != fixed.remaining.len()
+        || fixed.remaining.iter().any(|&index| index >= num_inputs)
+        || !pvk.has_valid_input_tables()
+    {
+        return Err(SynthesisError::MalformedVerifyingKey);
     }
 
+    let inputs_time = start_phase!("Prepare remaining inputs");
+    let mut g_ic = fixed.g_ic;
+    add_inputs(pvk, &mut g_ic, Some(&fixed.remaining), remaining_inputs);
+    end_phase!(inputs_time);
+
     Ok(g_ic)
 }
 
+/// The indices of the public inputs that are not in `fixed`, in order, for a key with
+/// `gamma_abc_len` elements in `gamma_abc_g1`. Fails if `fixed` has an index twice or an index
+/// that is not the one of a public input.
+pub(crate) fn remaining_indices<F>(
+    gamma_abc_len: usize,
+    fixed: &[(usize, F)],
+) -> R1CSResult<Vec<usize>> {
+    let num_inputs = gamma_abc_len
+        .checked_sub(1)
+        .ok_or(SynthesisError::MalformedVerifyingKey)?;
+    let mut is_fixed = vec![false; num_inputs];
+    for &(index, _) in fixed {
+        if index >= num_inputs || is_fixed[index] {
+            return Err(SynthesisError::MalformedVerifyingKey);
+        }
+        is_fixed[index] = true;
+    }
+    Ok((0..num_inputs).filter(|&index| !is_fixed[index]).collect())
+}
+
+/// Adds to `g_ic` the products of `inputs` with the elements of `gamma_abc_g1` after the first,
+/// at `indices` or at the positions of `inputs` if there are no `indices`.
+fn add_inputs<E: PairingEngine>(
+    pvk: &PreparedVerifyingKey<E>,
+    g_ic: &mut E::G1Projective,
+    indices: Option<&[usize]>,
+    inputs: &[E::Fr],
+) {
+    let index = |position: usize| indices.map_or(position, |indices| indices[position]);
+    if pvk.gamma_abc_g1_tables.is_empty() && inputs.len() < MSM_MIN_INPUTS {
+        for (position, i) in inputs.iter().enumerate() {
+            g_ic.add_assign(&pvk.vk.gamma_abc_g1[index(position) + 1].mul(i.into_repr()));
+        }
+    } else if pvk.gamma_abc_g1_tables.is_empty() {
+        let bases = match indices {
+            None => Cow::Borrowed(&pvk.vk.gamma_abc_g1[1..]),
+            Some(indices) => Cow::Owned(
+                indices
+                    .iter()
+                    .map(|&index| pvk.vk.gamma_abc_g1[index + 1])
+                    .collect(),
+            ),
+        };
+        let scalars = cfg_iter!(inputs).map(|i| i.into_repr()).collect::<Vec<_>>();
+        g_ic.add_assign(&VariableBaseMSM::multi_scalar_mul(&bases, &scalars));
+    } else {
+        let window = pvk.gamma_abc_g1_window;
+        let outerc = (E::Fr::size_in_bits() + window - 1) / window;
+        let products = cfg_iter!(inputs)
+            .enumerate()
+            .map(|(position, i)| {
+                let table = &pvk.gamma_abc_g1_tables[index(position)];
+                FixedBaseMSM::windowed_mul(outerc, window, table, i)
+            })
+            .collect::<Vec<E::G1Projective>>();
+        for product in &products {
+            g_ic.add_assign(product);
+        }
+    }
+}
 
 /// Verify a proof `proof` against the prepared verification key `pvk` and prepared public
 /// inputs. This should be preferred over [`verify_proof`] if the instance's public inputs are
@@ -45,19 +216,20 @@ pub fn verify_proof_with_prepared_inputs<E: PairingEngine>(
     proof: &Proof<E>,
     prepared_inputs: &E::G1Projective,
 ) -> R1CSResult<bool> {
-
-    let hash = Blake2b::new()
-    .chain(to_bytes!(&proof.a).unwrap())
-    .chain(to_bytes!(&proof.b).unwrap())
-    .chain(to_bytes!(&proof.delta_prime).unwrap());
-    let mut output = [0u8; 64];
-    output.copy_from_slice(&hash.finalize());
-
-    let m_fr = E::Fr::from_le_bytes_mod_order(&output);
+    let verifier_time = start_phase!("BPR20::Verifier");
+    let hash_time = start_phase!("Hash A, B and delta'");
+    let m_fr = proof_challenge::<E>(&proof.a, &proof.b, &proof.delta_prime);
     //println!("m_fr verifier {0}", m_fr);
-    let mut delta_prime_delta_m = pvk.vk.delta_g2.mul(m_fr);
+    let mut delta_prime_delta_m = FixedBaseMSM::windowed_mul::<E::G2Projective>(
+        pvk.delta_g2_table.len(),
+        pvk.delta_g2_window,
+        &pvk.delta_g2_table,
+        &m_fr,
+    );
     delta_prime_delta_m.add_assign_mixed(&proof.delta_prime);
+    end_phase!(hash_time);
 
+    let pairing_time = start_phase!("Pairing check");
 
     let qap = E::miller_loop(
         [
@@ -66,12 +238,17 @@ pub fn verify_proof_with_prepared_inputs<E: PairingEngine>(
                 prepared_inputs.into_affine().into(),
                 pvk.gamma_g2_neg_pc.clone(),
             ),
-            (proof.c.into(), delta_prime_delta_m.into_affine().neg().into()),
+            (
+                proof.c.into(),
+                delta_prime_delta_m.into_affine().neg().into(),
+            ),
         ]
         .iter(),
     );
 
     let test = E::final_exponentiation(&qap).ok_or(SynthesisError::UnexpectedIdentity)?;
+    end_phase!(pairing_time);
+    end_phase!(verifier_time);
 
     Ok(test == pvk.vk.alpha_g1_beta_g2)
 }
@@ -87,10 +264,6 @@ pub fn verify_proof<E: PairingEngine>(
     verify_proof_with_prepared_inputs(pvk, proof, &prepared_inputs)
 }
 
-
-
-
-
 /// Verify a vector of proofs `proofs` against the prepared verification key `pvk` and prepared public
 /// inputs vector.
 pub fn vec_verify_proof_with_prepared_inputs<E: PairingEngine>(
@@ -98,71 +271,64 @@ pub fn vec_verify_proof_with_prepared_inputs<E: PairingEngine>(
     proofs: &Vec<Proof<E>>,
     prepared_inputs: &Vec<E::G1Projective>,
 ) -> R1CSResult<bool> {
-
-
+    let verifier_time = start_phase!("BPR20::Batch verifier");
     let num_proofs = proofs.len();
     let mut m_fr: Vec<E::Fr> = Vec::with_capacity(num_proofs as usize);
-    
+
     let start = ark_std::time::Instant::now();
     for proof in proofs.iter() {
-        let hash = Blake2b::new()
-        .chain(to_bytes!(&proof.a).unwrap())
-        .chain(to_bytes!(&proof.b).unwrap())
-        .chain(to_bytes!(&proof.delta_prime).unwrap());
-        let mut output = [0u8; 64];
-        output.copy_from_slice(&hash.finalize());
-        m_fr.push(E::Fr::from_le_bytes_mod_order(&output));
-        
+        m_fr.push(proof_challenge::<E>(&proof.a, &proof.b, &proof.delta_prime));
     }
 
     let scalar_bits = E::Fr::size_in_bits();
 
-    let delta_g2_window = FixedBaseMSM::get_mul_window_size(num_proofs);
-    let delta_g2_table =
-        FixedBaseMSM::get_window_table::<E::G2Projective>(scalar_bits, delta_g2_window, pvk.vk.delta_g2.into_projective());
-    let elem_g2 =
-        FixedBaseMSM::multi_scalar_mul::<E::G2Projective>(scalar_bits, delta_g2_window, &delta_g2_table, &m_fr);
+    let elem_g2 = FixedBaseMSM::multi_scalar_mul::<E::G2Projective>(
+        scalar_bits,
+        pvk.delta_g2_window,
+        &pvk.delta_g2_table,
+        &m_fr,
+    );
 
     println!(
         "Hashing + Exponentiation (G2) time is {}ns per proof doing {} exponentiations",
         start.elapsed().as_nanos() / num_proofs as u128,
         num_proofs
     );
-   
-
 
-    
     let mut bool_results: Vec<_> = Vec::new();
-    for ((x,y),z) in elem_g2.iter().zip(proofs.iter()).zip(prepared_inputs.iter()){
-       // x -> [m_fr * delta]_2    ;;  y -> proof  ;;  z -> prepared_inputs 
+    for ((x, y), z) in elem_g2
+        .iter()
+        .zip(proofs.iter())
+        .zip(prepared_inputs.iter())
+    {
+        // x -> [m_fr * delta]_2    ;;  y -> proof  ;;  z -> prepared_inputs
 
         let tmp1 = E::final_exponentiation(&E::miller_loop(
             [
                 (y.a.into(), y.b.into()),
+                (z.into_affine().into(), pvk.gamma_g2_neg_pc.clone()),
                 (
-                    z.into_affine().into(),
-                    pvk.gamma_g2_neg_pc.clone(),
+                    y.c.into(),
+                    (*x + y.delta_prime.into_projective())
+                        .neg()
+                        .into_affine()
+                        .into(),
                 ),
-                (y.c.into(), (*x + y.delta_prime.into_projective()).neg().into_affine().into()),
             ]
             .iter(),
-        )).unwrap();
+        ))
+        .unwrap();
         let tmp2 = pvk.vk.alpha_g1_beta_g2;
-        let tmp =  tmp1 == tmp2 ;
+        let tmp = tmp1 == tmp2;
 
         bool_results.push(tmp);
-
     }
-    
-    let result = bool_results.iter().fold(true, |total, next| {total && *next});
+
+    let result = bool_results.iter().fold(true, |total, next| total && *next);
     //println!("result is {:?}", result);
-    
+    end_phase!(verifier_time);
 
-    
     Ok(result)
-    
-    
-
 }
 
 /// Verify a vector of proofs `proofs` against the prepared verification key `pvk`,
@@ -174,7 +340,7 @@ pub fn vec_verify_proof<E: PairingEngine>(
 ) -> R1CSResult<bool> {
     //let pvk = prepare_verifying_key(vk);
     let mut prepared_inputs: Vec<_> = Vec::new();
-    for (_,pub_input) in public_inputs.iter().enumerate(){
+    for (_, pub_input) in public_inputs.iter().enumerate() {
         let pvk = prepare_verifying_key(vk);
         prepared_inputs.push(prepare_inputs(&pvk, pub_input)?);
     }
@@ -0,0 +1,215 @@
+#![warn(unused)]
+#![deny(
+    trivial_casts,
+    trivial_numeric_casts,
+    variant_size_differences,
+    stable_features,
+    non_shorthand_field_patterns,
+    renamed_and_removed_lints
+)]
+
+// Known-answer vectors for the challenge `m` of proofs, which pin down
+// the encoding of its hash input so that other implementations can check
+// that they compute the same challenges.
+
+use ark_ec::{AffineCurve, PairingEngine, ProjectiveCurve};
+use ark_ff::{BigInteger, PrimeField, Zero};
+
+use ark_bpr20::{challenge_input, proof_challenge, CHALLENGE_DOMAIN};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The challenge as the little-endian bytes of its canonical form.
+fn challenge_hex<E: PairingEngine>(
+    a: &E::G1Affine,
+    b: &E::G2Affine,
+    delta_prime: &E::G2Affine,
+) -> String {
+    let m: E::Fr = proof_challenge::<E>(a, b, delta_prime);
+    hex(&m.into_repr().to_bytes_le())
+}
+
+fn challenge_input_layout<E: PairingEngine>(g1_len: usize, g2_len: usize, expected: &str) {
+    let g1 = E::G1Affine::prime_subgroup_generator();
+    let g2 = E::G2Affine::prime_subgroup_generator();
+    let input = challenge_input::<E>(&g1, &g2, &g2);
+
+    let mut lengths = Vec::new();
+    let mut rest = &input[..];
+    while !rest.is_empty() {
+        let mut len = [0u8; 8];
+        len.copy_from_slice(&rest[..8]);
+        let len = u64::from_le_bytes(len) as usize;
+        lengths.push(len);
+        rest = &rest[8 + len..];
+    }
+    assert_eq!(
+        lengths,
+        vec![CHALLENGE_DOMAIN.len(), g1_len, g2_len, g2_len]
+    );
+    assert_eq!(&input[8..8 + CHALLENGE_DOMAIN.len()], CHALLENGE_DOMAIN);
+
+    assert_eq!(hex(&input), expected);
+}
+
+fn challenge_known_answers<E: PairingEngine>(generators: &str, identities: &str, multiples: &str) {
+    let g1 = E::G1Affine::prime_subgroup_generator();
+    let g2 = E::G2Affine::prime_subgroup_generator();
+    let a = g1.mul(2u64).into_affine();
+    let b = g2.mul(3u64).into_affine();
+    let delta_prime = -g2;
+
+    assert_eq!(challenge_hex::<E>(&g1, &g2, &g2), generators);
+    assert_eq!(
+        challenge_hex::<E>(
+            &E::G1Affine::zero(),
+            &E::G2Affine::zero(),
+            &E::G2Affine::zero()
+        ),
+        identities
+    );
+    assert_eq!(challenge_hex::<E>(&a, &b, &delta_prime), multiples);
+}
+
+mod bls12_381 {
+    use ark_bls12_381::Bls12_381;
+
+    #[test]
+    fn challenge_input_layout() {
+        super::challenge_input_layout::<Bls12_381>(48, 96, GENERATORS_INPUT);
+    }
+
+    #[test]
+    fn challenge_known_answers() {
+        super::challenge_known_answers::<Bls12_381>(
+            GENERATORS_CHALLENGE,
+            IDENTITIES_CHALLENGE,
+            MULTIPLES_CHALLENGE,
+        );
+    }
+
+    /// The hash input for the generators of G1 and G2.
+    const GENERATORS_INPUT: &str = concat!(
+        "1600000000000000",
+        "61726b2d62707232302f6368616c6c656e67652f7631",
+        "3000000000000000",
+        "bbc622db0af03afbef1a7af93fe8556c58ac1b173f3a4ea105b974974f8c68c3",
+        "0faca94f8c63952694d79731a7d3f117",
+        "6000000000000000",
+        "b8bd21c1c85680d4efbb05a82603ac0b77d1e37a640b51b4023b40fad47ae4c6",
+        "5110c52d27050826910a8ff0b2a24a027e2b045d057dace5575d941312f14c33",
+        "49507fdcbb61dab51ab62099d0d06b59654f2788a0d3ac7d609f7152602be013",
+        "6000000000000000",
+        "b8bd21c1c85680d4efbb05a82603ac0b77d1e37a640b51b4023b40fad47ae4c6",
+        "5110c52d27050826910a8ff0b2a24a027e2b045d057dace5575d941312f14c33",
+        "49507fdcbb61dab51ab62099d0d06b59654f2788a0d3ac7d609f7152602be013",
+    );
+
+    /// The challenge for the generators of G1 and G2.
+    const GENERATORS_CHALLENGE: &str =
+        "accc72a90a95e38b04efe06b96369377e326b1d0c30b2e3f11c6dbd59fcc9822";
+
+    /// The challenge for the identities.
+    const IDENTITIES_CHALLENGE: &str =
+        "6788b8fea93c921280b6553425ecbf5cfeabecc59ddc39ec4fae9ab217633527";
+
+    /// The challenge for `2 * g1`, `3 * g2` and `-g2`.
+    const MULTIPLES_CHALLENGE: &str =
+        "d0d01185bfdd8c0cb04ab4f50db00f433254e83e4ab6d3be8fa5a801c981015a";
+}
+
+mod bls12_377 {
+    use ark_bls12_377::Bls12_377;
+
+    #[test]
+    fn challenge_input_layout() {
+        super::challenge_input_layout::<Bls12_377>(48, 96, GENERATORS_INPUT);
+    }
+
+    #[test]
+    fn challenge_known_answers() {
+        super::challenge_known_answers::<Bls12_377>(
+            GENERATORS_CHALLENGE,
+            IDENTITIES_CHALLENGE,
+            MULTIPLES_CHALLENGE,
+        );
+    }
+
+    /// The hash input for the generators of G1 and G2.
+    const GENERATORS_INPUT: &str = concat!(
+        "1600000000000000",
+        "61726b2d62707232302f6368616c6c656e67652f7631",
+        "3000000000000000",
+        "efe91bb26eb1b9ea4e39cdff121548d55ccb37bdc8828218bb419daa2c1e9585",
+        "54ff87bf2562fcc8670a74fede488880",
+        "6000000000000000",
+        "9651007c8fe4e374025453bb529f88719b6bdb57f501a57e31503e2071f065c5",
+        "011d84a3a23096c8fe85c771be808401fe6aa16efafe6bb2e66ff7bf8499f85c",
+        "dec99907ce3e22e7cbce5166ee772753d540b1b1515adc70314000e74060ea80",
+        "6000000000000000",
+        "9651007c8fe4e374025453bb529f88719b6bdb57f501a57e31503e2071f065c5",
+        "011d84a3a23096c8fe85c771be808401fe6aa16efafe6bb2e66ff7bf8499f85c",
+        "dec99907ce3e22e7cbce5166ee772753d540b1b1515adc70314000e74060ea80",
+    );
+
+    /// The challenge for the generators of G1 and G2.
+    const GENERATORS_CHALLENGE: &str =
+        "911bbbfe86a18612893b2525ea3dcbf245d6064c96c2670af9e5f36b6c98bd08";
+
+    /// The challenge for the identities.
+    const IDENTITIES_CHALLENGE: &str =
+        "0abf186a0b61f29f00023b59face6f0118e85b2cdf7e0d6dcc0cb74932fa710d";
+
+    /// The challenge for `2 * g1`, `3 * g2` and `-g2`.
+    const MULTIPLES_CHALLENGE: &str =
+        "4490b8a73efa52218b758d000d3ab0381a9e75b54354d84a59908e8fa1b9db0e";
+}
+
+mod mnt4_298 {
+    use ark_mnt4_298::MNT4_298;
+
+    #[test]
+    fn challenge_input_layout() {
+        super::challenge_input_layout::<MNT4_298>(38, 76, GENERATORS_INPUT);
+    }
+
+    #[test]
+    fn challenge_known_answers() {
+        super::challenge_known_answers::<MNT4_298>(
+            GENERATORS_CHALLENGE,
+            IDENTITIES_CHALLENGE,
+            MULTIPLES_CHALLENGE,
+        );
+    }
+
+    /// The hash input for the generators of G1 and G2.
+    const GENERATORS_INPUT: &str = concat!(
+        "1600000000000000",
+        "61726b2d62707232302f6368616c6c656e67652f7631",
+        "2600000000000000",
+        "46afd2c8b9b516a2de48b9929407c46090d6738fba6ae8ae7538cae63f2185ba",
+        "a182af2c7a80",
+        "4c00000000000000",
+        "a4a9096cdf0379ba2de731a9014fb12ca7121e1505f20190f82e2f54ff710566",
+        "c5910478710389a9e00a2ce38b962c712cc0b62b300363572f001f857c69af81",
+        "8083ef5d16da723f9aa54b80",
+        "4c00000000000000",
+        "a4a9096cdf0379ba2de731a9014fb12ca7121e1505f20190f82e2f54ff710566",
+        "c5910478710389a9e00a2ce38b962c712cc0b62b300363572f001f857c69af81",
+        "8083ef5d16da723f9aa54b80",
+    );
+
+    /// The challenge for the generators of G1 and G2.
+    const GENERATORS_CHALLENGE: &str =
+        "bcf67d2342b487df63cabbe073e9057901f2c8d3aed9ab70d0db14ebe45419ab0314b0528b000000";
+
+    /// The challenge for the identities.
+    const IDENTITIES_CHALLENGE: &str =
+        "cfcbe76bd311d098e345df85ecd0d504de0dac538ece9780c34dcc7a134c0e9a933ce0c098000000";
+
+    /// The challenge for `2 * g1`, `3 * g2` and `-g2`.
+    const MULTIPLES_CHALLENGE: &str =
+        "e2d766dd224312b3eabcdeb7fee1bb4cf1e0966b37959d9e7b4949c6a97eaa704f82c1b34f010000";
+}
@@ -0,0 +1,239 @@
+#![warn(unused)]
+#![deny(
+    trivial_casts,
+    trivial_numeric_casts,
+    variant_size_differences,
+    stable_features,
+    non_shorthand_field_patterns,
+    renamed_and_removed_lints,
+    unsafe_code
+)]
+
+use std::{
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{Child, Command},
+};
+
+use ark_bls12_381::{Bls12_381, Fr};
+use ark_ec::PairingEngine;
+use ark_ff::{Field, UniformRand};
+use ark_mnt4_298::MNT4_298;
+use ark_std::test_rng;
+
+use ark_relations::{
+    lc,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+use ark_bpr20::{
+    chunked::ChunkedProvingKey, constraint_matrices, container::ContainerError,
+    distributed::Coordinator, generate_random_parameters, prepare_verifying_key, verify_proof,
+    Error, ProverContext, ProvingKey,
+};
+
+/// A circuit with `num_inputs` public inputs that all equal `a * b`, and
+/// `num_witnesses` further witness variables.
+#[derive(Clone, Copy)]
+struct ProductCircuit<F: Field> {
+    a: Option<F>,
+    b: Option<F>,
+    num_inputs: usize,
+    num_witnesses: usize,
+}
+
+impl<F: Field> ConstraintSynthesizer<F> for ProductCircuit<F> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        let a = cs.new_witness_variable(|| self.a.ok_or(SynthesisError::AssignmentMissing))?;
+        let b = cs.new_witness_variable(|| self.b.ok_or(SynthesisError::AssignmentMissing))?;
+        let product = || {
+            let a = self.a.ok_or(SynthesisError::AssignmentMissing)?;
+            let b = self.b.ok_or(SynthesisError::AssignmentMissing)?;
+
+            Ok(a * b)
+        };
+
+        for _ in 0..self.num_inputs {
+            let c = cs.new_input_variable(product)?;
+            cs.enforce_constraint(lc!() + a, lc!() + b, lc!() + c)?;
+        }
+        for _ in 0..self.num_witnesses {
+            let c = cs.new_witness_variable(product)?;
+            cs.enforce_constraint(lc!() + a, lc!() + b, lc!() + c)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes `pk` in the chunked layout to a fresh file named after `name`.
+fn write_chunked<E: PairingEngine>(pk: &ProvingKey<E>, name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("bpr20-{}-{}", name, std::process::id()));
+    pk.write_chunked(std::fs::File::create(&path).unwrap(), 16)
+        .unwrap();
+    path
+}
+
+/// Spawns a worker process serving the chunked proving key at `path` to the
+/// coordinator listening on `listener`, over BLS12-381 unless a `--curve` is
+/// passed in `args`.
+fn spawn_worker(args: &[&str], path: &Path, listener: &TcpListener) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_bpr20-worker"))
+        .args(args)
+        .arg(path)
+        .arg(listener.local_addr().unwrap().to_string())
+        .spawn()
+        .unwrap()
+}
+
+#[test]
+fn test_prove_with_worker_processes() {
+    const NUM_WORKERS: usize = 3;
+
+    let rng = &mut test_rng();
+    let setup_circuit = ProductCircuit::<Fr> {
+        a: None,
+        b: None,
+        num_inputs: 5,
+        num_witnesses: 50,
+    };
+    let params = generate_random_parameters::<Bls12_381, _, _>(setup_circuit, rng).unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+    let matrices = constraint_matrices(setup_circuit).unwrap();
+    let path = write_chunked(&params, "distributed-pk");
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut workers = (0..NUM_WORKERS)
+        .map(|_| spawn_worker(&[], &path, &listener))
+        .collect::<Vec<_>>();
+    let pk = ChunkedProvingKey::open(&path).unwrap();
+    let coordinator = Coordinator::accept(pk, &listener, NUM_WORKERS).unwrap();
+    assert_eq!(coordinator.num_workers(), NUM_WORKERS);
+
+    let ctx = ProverContext::new(&params, matrices.clone()).unwrap();
+    let distributed_ctx = ProverContext::new(&coordinator, matrices.clone()).unwrap();
+    // A memory budget makes the coordinator convert each range's scalars
+    // on its own.
+    let low_memory_ctx = ProverContext::new(&coordinator, matrices)
+        .unwrap()
+        .with_memory_budget(1);
+
+    for _ in 0..3 {
+        let a = Fr::rand(rng);
+        let b = Fr::rand(rng);
+        let circuit = ProductCircuit {
+            a: Some(a),
+            b: Some(b),
+            ..setup_circuit
+        };
+        let (r, s, zeta) = (Fr::rand(rng), Fr::rand(rng), Fr::rand(rng));
+
+        let proof = ctx.create_proof(circuit, r, s, zeta).unwrap();
+        let distributed_proof = distributed_ctx.create_proof(circuit, r, s, zeta).unwrap();
+        let low_memory_proof = low_memory_ctx.create_proof(circuit, r, s, zeta).unwrap();
+        assert!(proof == distributed_proof);
+        assert!(proof == low_memory_proof);
+        assert!(verify_proof(&pvk, &distributed_proof, &[a * b; 5]).unwrap());
+
+        // Without the `r * B` term, the B-query in `G1` is not needed.
+        let proof = ctx.create_proof(circuit, Fr::from(0u8), s, zeta).unwrap();
+        let distributed_proof = distributed_ctx
+            .create_proof(circuit, Fr::from(0u8), s, zeta)
+            .unwrap();
+        assert!(proof == distributed_proof);
+    }
+
+    // The workers exit once the coordinator closes their connections.
+    drop((distributed_ctx, low_memory_ctx));
+    drop(coordinator);
+    for worker in workers.iter_mut() {
+        assert!(worker.wait().unwrap().success());
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_reject_worker_with_other_proving_key() {
+    let rng = &mut test_rng();
+    let setup_circuit = ProductCircuit::<Fr> {
+        a: None,
+        b: None,
+        num_inputs: 1,
+        num_witnesses: 3,
+    };
+    let params = generate_random_parameters::<Bls12_381, _, _>(setup_circuit, rng).unwrap();
+    let other_params = generate_random_parameters::<Bls12_381, _, _>(setup_circuit, rng).unwrap();
+    let path = write_chunked(&params, "distributed-pk-rejected");
+    let other_path = write_chunked(&other_params, "distributed-other-pk");
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut worker = spawn_worker(&[], &other_path, &listener);
+    let pk = ChunkedProvingKey::<Bls12_381>::open(&path).unwrap();
+    assert!(Coordinator::accept(pk, &listener, 1).is_err());
+
+    worker.wait().unwrap();
+    std::fs::remove_file(&other_path).unwrap();
+
+    // A key with the same `delta_g1` and number of variables is rejected too.
+    let mut other_params = params.clone();
+    other_params.vk.alpha_g1 = params.a_query[1];
+    let other_path = write_chunked(&other_params, "distributed-other-alpha-pk");
+    let mut worker = spawn_worker(&[], &other_path, &listener);
+    let pk = ChunkedProvingKey::<Bls12_381>::open(&path).unwrap();
+    assert!(Coordinator::accept(pk, &listener, 1).is_err());
+
+    worker.wait().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&other_path).unwrap();
+}
+
+#[test]
+fn test_prove_with_worker_over_other_curve() {
+    let rng = &mut test_rng();
+    let setup_circuit = ProductCircuit::<<MNT4_298 as PairingEngine>::Fr> {
+        a: None,
+        b: None,
+        num_inputs: 2,
+        num_witnesses: 10,
+    };
+    let params = generate_random_parameters::<MNT4_298, _, _>(setup_circuit, rng).unwrap();
+    let matrices = constraint_matrices(setup_circuit).unwrap();
+    let path = write_chunked(&params, "distributed-mnt4-pk");
+
+    // A worker for another curve cannot open the key.
+    assert!(matches!(
+        ChunkedProvingKey::<Bls12_381>::open(&path),
+        Err(Error::Container(ContainerError::CurveMismatch { .. }))
+    ));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut worker = spawn_worker(&["--curve", "bls12-377"], &path, &listener);
+    assert!(!worker.wait().unwrap().success());
+    let mut worker = spawn_worker(&["--curve", "bn254"], &path, &listener);
+    assert_eq!(worker.wait().unwrap().code(), Some(2));
+
+    let mut worker = spawn_worker(&["--curve", "mnt4-298"], &path, &listener);
+    let pk = ChunkedProvingKey::open(&path).unwrap();
+    let coordinator = Coordinator::accept(pk, &listener, 1).unwrap();
+    let ctx = ProverContext::new(&params, matrices.clone()).unwrap();
+    let distributed_ctx = ProverContext::new(&coordinator, matrices).unwrap();
+    let a = UniformRand::rand(rng);
+    let b = UniformRand::rand(rng);
+    let circuit = ProductCircuit {
+        a: Some(a),
+        b: Some(b),
+        ..setup_circuit
+    };
+    let (r, s, zeta) = (
+        UniformRand::rand(rng),
+        UniformRand::rand(rng),
+        UniformRand::rand(rng),
+    );
+    let proof = distributed_ctx.create_proof(circuit, r, s, zeta).unwrap();
+    assert!(proof == ctx.create_proof(circuit, r, s, zeta).unwrap());
+    assert!(verify_proof(&prepare_verifying_key(&params.vk), &proof, &[a * b; 2]).unwrap());
+
+    drop(distributed_ctx);
+    drop(coordinator);
+    assert!(worker.wait().unwrap().success());
+    std::fs::remove_file(&path).unwrap();
+}
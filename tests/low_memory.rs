@@ -0,0 +1,242 @@
+#![warn(unused)]
+#![deny(
+    trivial_casts,
+    trivial_numeric_casts,
+    variant_size_differences,
+    stable_features,
+    non_shorthand_field_patterns,
+    renamed_and_removed_lints
+)]
+
+// Counting the bytes allocated by the prover requires a global allocator,
+// which is the only unsafe code in this test.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ark_bls12_381::{Bls12_381, Fr};
+use ark_ff::{Field, UniformRand};
+use ark_std::test_rng;
+
+use ark_relations::{
+    lc,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+use ark_bpr20::{
+    constraint_matrices, generate_random_parameters, prepare_verifying_key, verify_proof, Phase,
+    ProverContext, RunOptions,
+};
+
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+#[allow(unsafe_code)]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Returns the result of `f` and the peak number of bytes it allocated on
+/// top of what was allocated when it last called `reset_peak`, or when it
+/// started.
+fn peak_allocation<T>(f: impl FnOnce(&(dyn Fn() + Sync)) -> T) -> (T, usize) {
+    let base = AtomicUsize::new(ALLOCATED.load(Ordering::SeqCst));
+    PEAK.store(base.load(Ordering::SeqCst), Ordering::SeqCst);
+    let reset_peak = || {
+        let allocated = ALLOCATED.load(Ordering::SeqCst);
+        base.store(allocated, Ordering::SeqCst);
+        PEAK.store(allocated, Ordering::SeqCst);
+    };
+    let result = f(&reset_peak);
+    (
+        result,
+        PEAK.load(Ordering::SeqCst) - base.load(Ordering::SeqCst),
+    )
+}
+
+/// A circuit with `num_variables` witness variables and `num_constraints`
+/// copies of one constraint, so that either the MSM scalars or the QAP
+/// polynomials can dominate the prover's memory.
+#[derive(Clone, Copy)]
+struct TestCircuit<F: Field> {
+    a: Option<F>,
+    b: Option<F>,
+    num_variables: usize,
+    num_constraints: usize,
+}
+
+impl<F: Field> ConstraintSynthesizer<F> for TestCircuit<F> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        let a = cs.new_witness_variable(|| self.a.ok_or(SynthesisError::AssignmentMissing))?;
+        let b = cs.new_witness_variable(|| self.b.ok_or(SynthesisError::AssignmentMissing))?;
+        let c = cs.new_input_variable(|| {
+            let a = self.a.ok_or(SynthesisError::AssignmentMissing)?;
+            let b = self.b.ok_or(SynthesisError::AssignmentMissing)?;
+
+            Ok(a * b)
+        })?;
+
+        for _ in 0..(self.num_variables - 2) {
+            let _ = cs.new_witness_variable(|| self.a.ok_or(SynthesisError::AssignmentMissing))?;
+        }
+
+        for _ in 0..self.num_constraints {
+            cs.enforce_constraint(lc!() + a, lc!() + b, lc!() + c)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_low_memory_prover_peak_allocation() {
+    // A power of two, so that synthesis leaves no spare capacity behind.
+    const NUM_VARIABLES: usize = 1 << 16;
+    const NUM_CONSTRAINTS: usize = 1 << 12;
+    const MEMORY_BUDGET: usize = 1 << 18;
+
+    let rng = &mut test_rng();
+    let setup_circuit = TestCircuit::<Fr> {
+        a: None,
+        b: None,
+        num_variables: NUM_VARIABLES,
+        num_constraints: NUM_CONSTRAINTS,
+    };
+    let params = generate_random_parameters::<Bls12_381, _, _>(setup_circuit, rng).unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+    let matrices = constraint_matrices(setup_circuit).unwrap();
+
+    let ctx = ProverContext::new(&params, matrices.clone()).unwrap();
+    let low_memory_ctx = ProverContext::new(&params, matrices)
+        .unwrap()
+        .with_memory_budget(MEMORY_BUDGET);
+
+    let a = Fr::rand(rng);
+    let b = Fr::rand(rng);
+    let circuit = TestCircuit {
+        a: Some(a),
+        b: Some(b),
+        ..setup_circuit
+    };
+    let (r, s, zeta) = (Fr::rand(rng), Fr::rand(rng), Fr::rand(rng));
+
+    // Prove on a single thread, so that per-thread MSM buckets do not make
+    // the peak depend on the machine.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    let (proof, peak) =
+        pool.install(|| peak_allocation(|_| ctx.create_proof(circuit, r, s, zeta).unwrap()));
+    let (low_memory_proof, low_memory_peak) = pool
+        .install(|| peak_allocation(|_| low_memory_ctx.create_proof(circuit, r, s, zeta).unwrap()));
+
+    assert_eq!(proof, low_memory_proof);
+    assert!(verify_proof(&pvk, &low_memory_proof, &[a * b]).unwrap());
+
+    // The assignment and the coefficients of `h` are the floor; synthesis
+    // may transiently need half as much again.
+    let domain_size = (NUM_CONSTRAINTS + 2).next_power_of_two();
+    let floor = (NUM_VARIABLES + domain_size) * std::mem::size_of::<Fr>();
+    let bound = floor + floor / 2 + MEMORY_BUDGET;
+
+    assert!(
+        low_memory_peak <= bound,
+        "low-memory peak {} exceeds bound {}",
+        low_memory_peak,
+        bound
+    );
+    assert!(low_memory_peak < peak);
+}
+
+#[test]
+fn test_low_memory_witness_map_peak_allocation() {
+    // Many more constraints than variables, so that the QAP polynomials
+    // evaluated by the witness map dominate the prover's memory. Together
+    // with the two instance variables, the constraints fill the domain.
+    const NUM_VARIABLES: usize = 2;
+    const NUM_CONSTRAINTS: usize = (1 << 14) - 2;
+    const MEMORY_BUDGET: usize = 1 << 17;
+
+    let rng = &mut test_rng();
+    let setup_circuit = TestCircuit::<Fr> {
+        a: None,
+        b: None,
+        num_variables: NUM_VARIABLES,
+        num_constraints: NUM_CONSTRAINTS,
+    };
+    let params = generate_random_parameters::<Bls12_381, _, _>(setup_circuit, rng).unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+    let matrices = constraint_matrices(setup_circuit).unwrap();
+
+    let ctx = ProverContext::new(&params, matrices.clone()).unwrap();
+    let low_memory_ctx = ProverContext::new(&params, matrices)
+        .unwrap()
+        .with_memory_budget(MEMORY_BUDGET);
+
+    let a = Fr::rand(rng);
+    let b = Fr::rand(rng);
+    let circuit = TestCircuit {
+        a: Some(a),
+        b: Some(b),
+        ..setup_circuit
+    };
+    let (r, s, zeta) = (Fr::rand(rng), Fr::rand(rng), Fr::rand(rng));
+
+    // The peak is measured from the end of synthesis, which for this
+    // circuit needs more memory than the witness map.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    let prove = |ctx: &ProverContext<_>| {
+        pool.install(|| {
+            peak_allocation(|reset_peak| {
+                let progress = |phase, fraction| {
+                    if phase == Phase::Synthesis && fraction == 1.0 {
+                        reset_peak();
+                    }
+                };
+                let options = RunOptions::new().with_progress(&progress);
+                ctx.create_proof_with_options(circuit, r, s, zeta, options)
+                    .unwrap()
+            })
+        })
+    };
+    let (proof, peak) = prove(&ctx);
+    let (low_memory_proof, low_memory_peak) = prove(&low_memory_ctx);
+
+    assert_eq!(proof, low_memory_proof);
+    assert!(verify_proof(&pvk, &low_memory_proof, &[a * b]).unwrap());
+
+    // The coefficients of `h` are the floor, whereas the witness map without
+    // a budget holds the evaluations of two polynomials over the domain.
+    let domain_size = NUM_CONSTRAINTS + 2;
+    let domain_bytes = domain_size * std::mem::size_of::<Fr>();
+    let bound = domain_bytes + domain_bytes / 4 + MEMORY_BUDGET;
+
+    assert!(
+        low_memory_peak <= bound,
+        "low-memory peak {} exceeds bound {}",
+        low_memory_peak,
+        bound
+    );
+    assert!(peak > 2 * domain_bytes);
+    assert!(low_memory_peak < 2 * domain_bytes);
+}
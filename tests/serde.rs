@@ -0,0 +1,173 @@
+#![warn(unused)]
+#![deny(
+    trivial_casts,
+    trivial_numeric_casts,
+    variant_size_differences,
+    stable_features,
+    non_shorthand_field_patterns,
+    renamed_and_removed_lints
+)]
+
+use ark_ec::PairingEngine;
+use ark_ff::{Field, PrimeField, UniformRand, Zero};
+use ark_serialize::CanonicalSerialize;
+use ark_std::test_rng;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+
+use ark_relations::{
+    lc,
+    r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError},
+};
+
+use ark_bpr20::{
+    create_random_proof, generate_random_parameters, prepare_verifying_key, serde_public_inputs,
+    verify_proof, PreparedVerifyingKey, Proof, ProvingKey, SerializablePreparedG2, VerifyingKey,
+};
+
+struct MultiplyCircuit<F: Field> {
+    a: Option<F>,
+    b: Option<F>,
+}
+
+impl<F: Field> ConstraintSynthesizer<F> for MultiplyCircuit<F> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<F>) -> Result<(), SynthesisError> {
+        let a = cs.new_witness_variable(|| self.a.ok_or(SynthesisError::AssignmentMissing))?;
+        let b = cs.new_witness_variable(|| self.b.ok_or(SynthesisError::AssignmentMissing))?;
+        let c = cs.new_input_variable(|| {
+            let mut a = self.a.ok_or(SynthesisError::AssignmentMissing)?;
+            a *= &self.b.ok_or(SynthesisError::AssignmentMissing)?;
+            Ok(a)
+        })?;
+        cs.enforce_constraint(lc!() + a, lc!() + b, lc!() + c)?;
+        Ok(())
+    }
+}
+
+/// Public inputs, serialized as `#[serde(with = "serde_public_inputs")]`
+/// would serialize a field.
+#[derive(Debug, PartialEq)]
+struct Inputs<F: Field>(Vec<F>);
+
+impl<F: PrimeField> Serialize for Inputs<F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde_public_inputs::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de, F: PrimeField> Deserialize<'de> for Inputs<F> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        serde_public_inputs::deserialize(deserializer).map(Inputs)
+    }
+}
+
+/// Round-trips `value` through JSON, where it is a hex string, and CBOR,
+/// where it is a byte string.
+fn round_trip<T>(value: &T) -> (T, T)
+where
+    T: Serialize + DeserializeOwned,
+{
+    let json = serde_json::to_string(value).unwrap();
+    assert!(json.starts_with('"') && json.ends_with('"'));
+    assert!(json[1..json.len() - 1]
+        .bytes()
+        .all(|b| b.is_ascii_hexdigit()));
+
+    let mut cbor = Vec::new();
+    ciborium::ser::into_writer(value, &mut cbor).unwrap();
+    // The major type of a CBOR byte string is 2.
+    assert_eq!(cbor[0] >> 5, 2);
+
+    (
+        serde_json::from_str(&json).unwrap(),
+        ciborium::de::from_reader(&cbor[..]).unwrap(),
+    )
+}
+
+fn test_serde_round_trip<E>()
+where
+    E: PairingEngine,
+    E::G2Prepared: SerializablePreparedG2,
+{
+    let rng = &mut test_rng();
+    let params: ProvingKey<E> =
+        generate_random_parameters(MultiplyCircuit { a: None, b: None }, rng).unwrap();
+    let pvk = prepare_verifying_key(&params.vk);
+
+    let a = E::Fr::rand(rng);
+    let b = E::Fr::rand(rng);
+    let proof = create_random_proof(
+        MultiplyCircuit {
+            a: Some(a),
+            b: Some(b),
+        },
+        &params,
+        rng,
+    )
+    .unwrap();
+
+    let (from_json, from_cbor) = round_trip(&proof);
+    assert_eq!(from_json, proof);
+    assert_eq!(from_cbor, proof);
+    let (from_json, from_cbor) = round_trip(&params.vk);
+    assert_eq!(from_json, params.vk);
+    assert_eq!(from_cbor, params.vk);
+    let (from_json, from_cbor) = round_trip(&params);
+    assert_eq!(from_json, params);
+    assert_eq!(from_cbor, params);
+    let (from_json, from_cbor) = round_trip(&pvk);
+    assert_eq!(from_json.vk, pvk.vk);
+    assert_eq!(from_cbor.vk, pvk.vk);
+    assert!(verify_proof(&from_cbor, &proof, &[a * b]).unwrap());
+    let inputs = Inputs(vec![a * b, a, E::Fr::zero()]);
+    let (from_json, from_cbor) = round_trip(&inputs);
+    assert_eq!(from_json, inputs);
+    assert_eq!(from_cbor, inputs);
+    assert!(verify_proof(&pvk, &proof, &from_json.0[..1]).unwrap());
+
+    // Malformed encodings are rejected.
+    let json = serde_json::to_string(&proof).unwrap();
+    let hex = &json[1..json.len() - 1];
+    let truncated = format!("\"{}\"", &hex[..hex.len() - 2]);
+    assert!(serde_json::from_str::<Proof<E>>(&truncated).is_err());
+    let extended = format!("\"{}00\"", hex);
+    assert!(serde_json::from_str::<Proof<E>>(&extended).is_err());
+    let odd = format!("\"{}0\"", hex);
+    assert!(serde_json::from_str::<Proof<E>>(&odd).is_err());
+    let not_hex = format!("\"zz{}\"", &hex[2..]);
+    assert!(serde_json::from_str::<Proof<E>>(&not_hex).is_err());
+    // The encoding of `a` with both the infinity and the sign flags set.
+    let flags_end = 2 * E::G1Affine::zero().serialized_size();
+    let bad_flags = format!("\"{}ff{}\"", &hex[..flags_end - 2], &hex[flags_end..]);
+    assert!(serde_json::from_str::<Proof<E>>(&bad_flags).is_err());
+    assert!(serde_json::from_str::<VerifyingKey<E>>(&json).is_err());
+    assert!(serde_json::from_str::<PreparedVerifyingKey<E>>(&json).is_err());
+    // An input that is not in the field.
+    let json = serde_json::to_string(&Inputs(vec![E::Fr::zero()])).unwrap();
+    let element_len = 2 * E::Fr::zero().serialized_size();
+    let too_large = format!(
+        "{}{}\"",
+        &json[..json.len() - 1 - element_len],
+        "f".repeat(element_len)
+    );
+    assert!(serde_json::from_str::<Inputs<E::Fr>>(&too_large).is_err());
+}
+
+#[test]
+fn serde_round_trip_bls12_381() {
+    test_serde_round_trip::<ark_bls12_381::Bls12_381>();
+}
+
+#[test]
+fn serde_round_trip_cp6_782() {
+    test_serde_round_trip::<ark_cp6_782::CP6_782>();
+}
+
+#[test]
+fn serde_round_trip_mnt4_298() {
+    test_serde_round_trip::<ark_mnt4_298::MNT4_298>();
+}
+
+#[test]
+fn serde_round_trip_mnt6_298() {
+    test_serde_round_trip::<ark_mnt6_298::MNT6_298>();
+}